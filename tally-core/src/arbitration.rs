@@ -0,0 +1,519 @@
+//! The pure part of arbitration between the sources reporting a tally: what the device should
+//! show, given the latest report from each source.
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use tally_rpc::rpc::{Color, SourcePriorities, SourceTimeouts, TallyMapping, TallySource};
+
+use crate::{mapping::Matches, state::TallyState};
+
+const SOURCES: usize = TallySource::ALL.len();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// `None` withdraws the source's tally
+    Tally(Option<TallyState>),
+    /// `None` withdraws the source's override
+    ColorOverride(Option<Color>),
+    /// Some of the mapping's selectors, only from [`TallySource::Mapping`]
+    Matches(Matches),
+    /// The mapping's selectors in the mask, whose protocol lost its signal, are unknown again.
+    /// Only from [`TallySource::Mapping`]
+    Forget(u8),
+}
+
+/// Changes waiting to be applied. Only the latest of each kind from each source is kept, so a
+/// burst of reports can't push out the newest state.
+pub struct Pending {
+    tally: [Option<(Option<TallyState>, Instant)>; SOURCES],
+    color: [Option<(Option<Color>, Instant)>; SOURCES],
+    /// The merged matches, and the selectors forgotten since
+    matches: Option<(Matches, u8, Instant)>,
+}
+
+impl Default for Pending {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pending {
+    pub const fn new() -> Self {
+        Self {
+            tally: [None; SOURCES],
+            color: [None; SOURCES],
+            matches: None,
+        }
+    }
+
+    /// Hold a change from `source`, reported at `at`, replacing any earlier one of its kind.
+    pub fn push(&mut self, source: TallySource, change: Change, at: Instant) {
+        let i = source as usize;
+        match change {
+            Change::Tally(tally) => self.tally[i] = Some((tally, at)),
+            Change::ColorOverride(color) => self.color[i] = Some((color, at)),
+            Change::Matches(matches) => {
+                let (mut merged, forgotten) = self
+                    .matches
+                    .map_or_else(Default::default, |(m, f, _)| (m, f));
+                merged.merge(matches);
+                self.matches = Some((merged, forgotten & !matches.known, at));
+            }
+            Change::Forget(selectors) => {
+                let (mut merged, forgotten) = self
+                    .matches
+                    .map_or_else(Default::default, |(m, f, _)| (m, f));
+                merged.forget(selectors);
+                self.matches = Some((merged, forgotten | selectors, at));
+            }
+        }
+    }
+
+    /// Apply the held changes to `sources` in the order they were reported.
+    pub fn apply(self, sources: &mut Sources) {
+        let mut changes: Vec<_, { 2 * SOURCES }> = Vec::new();
+        for ((source, tally), color) in TallySource::ALL.iter().zip(self.tally).zip(self.color) {
+            if let Some((tally, at)) = tally {
+                let _ = changes.push((at, *source, Change::Tally(tally)));
+            }
+            if let Some((color, at)) = color {
+                let _ = changes.push((at, *source, Change::ColorOverride(color)));
+            }
+        }
+        changes.sort_unstable_by_key(|(at, _, _)| *at);
+        for (at, source, change) in changes {
+            sources.update(source, change, at);
+        }
+        // Forgetting first, as the matches already leave out anything forgotten before them
+        if let Some((matches, forgotten, at)) = self.matches {
+            if forgotten != 0 {
+                sources.update(TallySource::Mapping, Change::Forget(forgotten), at);
+            }
+            sources.update(TallySource::Mapping, Change::Matches(matches), at);
+        }
+    }
+}
+
+/// The latest report from each source, and when it came.
+pub struct Sources {
+    tally: [Option<(TallyState, Instant)>; SOURCES],
+    color: [Option<(Color, Instant)>; SOURCES],
+    mapping: Option<TallyMapping>,
+    matches: Matches,
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sources {
+    pub const fn new() -> Self {
+        Self {
+            tally: [None; SOURCES],
+            color: [None; SOURCES],
+            mapping: None,
+            matches: Matches { known: 0, on: 0 },
+        }
+    }
+
+    /// Follow a new mapping, forgetting the selectors reported for the old one.
+    pub fn set_mapping(&mut self, mapping: Option<TallyMapping>, now: Instant) {
+        if mapping != self.mapping {
+            self.mapping = mapping;
+            self.matches = Matches::default();
+            self.update(TallySource::Mapping, Change::Tally(None), now);
+        }
+    }
+
+    pub fn update(&mut self, source: TallySource, change: Change, now: Instant) {
+        let i = source as usize;
+        match change {
+            Change::Tally(tally) => self.tally[i] = tally.map(|t| (t, now)),
+            Change::ColorOverride(color) => self.color[i] = color.map(|c| (c, now)),
+            Change::Matches(matches) => {
+                self.matches.merge(matches);
+                self.update_mapping(now);
+            }
+            Change::Forget(selectors) => {
+                self.matches.forget(selectors);
+                self.update_mapping(now);
+            }
+        }
+    }
+
+    /// Work out the mapping's tally from its selectors. Once none of them are known it has lost
+    /// its signal, if it had one.
+    fn update_mapping(&mut self, now: Instant) {
+        let report = &mut self.tally[TallySource::Mapping as usize];
+        let tally = match self.mapping.as_ref().and_then(|m| self.matches.tally(m)) {
+            Some(tally) => Some(tally),
+            None => report.map(|_| TallyState::LostSignal),
+        };
+        *report = tally.map(|t| (t, now));
+    }
+
+    pub fn tally(&self, priorities: &SourcePriorities) -> Option<TallyState> {
+        self.winner(priorities).map(|(_, tally)| tally)
+    }
+
+    /// The source whose tally [`tally`](Self::tally) gives.
+    pub fn tally_source(&self, priorities: &SourcePriorities) -> Option<TallySource> {
+        self.winner(priorities).map(|(source, _)| source)
+    }
+
+    fn winner(&self, priorities: &SourcePriorities) -> Option<(TallySource, TallyState)> {
+        best(&self.tally, priorities, |t| *t != TallyState::LostSignal)
+    }
+
+    pub fn color_override(&self, priorities: &SourcePriorities) -> Option<Color> {
+        best(&self.color, priorities, |_| true).map(|(_, color)| color)
+    }
+
+    /// The next time a source will lose its signal, unless it reports before then.
+    pub fn next_deadline(&self, timeouts: &SourceTimeouts) -> Option<Instant> {
+        TallySource::ALL
+            .iter()
+            .zip(&self.tally)
+            .filter_map(|(source, report)| deadline(timeouts.get(*source), report))
+            .min()
+    }
+
+    /// Mark the sources that have gone quiet for too long as having lost their signal.
+    pub fn expire(&mut self, timeouts: &SourceTimeouts, now: Instant) {
+        for (source, report) in TallySource::ALL.iter().zip(&mut self.tally) {
+            match deadline(timeouts.get(*source), report) {
+                Some(deadline) if deadline <= now => {
+                    *report = Some((TallyState::LostSignal, deadline))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn lost_sources(&self) -> Vec<TallySource, SOURCES> {
+        TallySource::ALL
+            .iter()
+            .zip(&self.tally)
+            .filter(|(_, report)| matches!(report, Some((TallyState::LostSignal, _))))
+            .map(|(source, _)| *source)
+            .collect()
+    }
+}
+
+/// When a source with `report` will have gone quiet for too long, if it has a real tally and a
+/// timeout.
+fn deadline(timeout: u16, report: &Option<(TallyState, Instant)>) -> Option<Instant> {
+    match (timeout, report) {
+        (0, _) | (_, None | Some((TallyState::LostSignal, _))) => None,
+        (timeout, Some((_, at))) => Some(*at + Duration::from_secs(u64::from(timeout))),
+    }
+}
+
+/// The winning report and its source. Reports that aren't `real` only win if there's nothing
+/// else.
+fn best<T: Copy>(
+    reports: &[Option<(T, Instant)>; SOURCES],
+    priorities: &SourcePriorities,
+    real: impl Fn(&T) -> bool,
+) -> Option<(TallySource, T)> {
+    TallySource::ALL
+        .iter()
+        .zip(reports)
+        .filter_map(|(source, report)| {
+            report.map(|(value, at)| ((real(&value), priorities.get(*source), at), *source, value))
+        })
+        .max_by_key(|(rank, _, _)| *rank)
+        .map(|(_, source, value)| (source, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_secs(secs)
+    }
+
+    #[test]
+    fn test_pending() {
+        let priorities = SourcePriorities::default();
+        let mut pending = Pending::new();
+        // More reports than a queue would hold, of which only the last from each source counts
+        for i in 0..20 {
+            let tally = if i % 2 == 0 {
+                TallyState::Program
+            } else {
+                TallyState::Off
+            };
+            pending.push(TallySource::Obs, Change::Tally(Some(tally)), at(i));
+        }
+        pending.push(
+            TallySource::Atem,
+            Change::Tally(Some(TallyState::Preview)),
+            at(5),
+        );
+        pending.push(
+            TallySource::Mapping,
+            Change::Matches(Matches { known: 1, on: 1 }),
+            at(6),
+        );
+        pending.push(
+            TallySource::Mapping,
+            Change::Matches(Matches { known: 2, on: 0 }),
+            at(7),
+        );
+        pending.push(TallySource::Mapping, Change::Forget(0b101), at(8));
+        pending.push(
+            TallySource::Mapping,
+            Change::Matches(Matches { known: 4, on: 4 }),
+            at(9),
+        );
+        let mut sources = Sources::new();
+        sources.matches = Matches { known: 1, on: 1 };
+        pending.apply(&mut sources);
+        // Obs reported last
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Off));
+        assert_eq!(sources.matches, Matches { known: 6, on: 4 });
+        sources.update(TallySource::Obs, Change::Tally(None), at(30));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Preview));
+    }
+
+    #[test]
+    fn test_latest_wins() {
+        let priorities = SourcePriorities::default();
+        let mut sources = Sources::new();
+        assert_eq!(sources.tally(&priorities), None);
+        sources.update(
+            TallySource::Atem,
+            Change::Tally(Some(TallyState::Program)),
+            at(1),
+        );
+        sources.update(
+            TallySource::Tsl,
+            Change::Tally(Some(TallyState::Preview)),
+            at(2),
+        );
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Preview));
+        sources.update(TallySource::Tsl, Change::Tally(None), at(3));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+    }
+
+    #[test]
+    fn test_priority() {
+        let priorities = SourcePriorities {
+            gpi: 200,
+            ..SourcePriorities::default()
+        };
+        let mut sources = Sources::new();
+        sources.update(
+            TallySource::Gpi,
+            Change::Tally(Some(TallyState::Off)),
+            at(1),
+        );
+        sources.update(
+            TallySource::Atem,
+            Change::Tally(Some(TallyState::Program)),
+            at(2),
+        );
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Off));
+        assert_eq!(sources.tally_source(&priorities), Some(TallySource::Gpi));
+        sources.update(TallySource::Gpi, Change::Tally(None), at(3));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+        assert_eq!(sources.tally_source(&priorities), Some(TallySource::Atem));
+    }
+
+    #[test]
+    fn test_lost_signal() {
+        let priorities = SourcePriorities {
+            roland: 200,
+            ..SourcePriorities::default()
+        };
+        let mut sources = Sources::new();
+        sources.update(
+            TallySource::Roland,
+            Change::Tally(Some(TallyState::LostSignal)),
+            at(1),
+        );
+        assert_eq!(sources.tally(&priorities), Some(TallyState::LostSignal));
+        // Any real tally beats a lost signal, whatever the priority
+        sources.update(
+            TallySource::Vmix,
+            Change::Tally(Some(TallyState::Preview)),
+            at(2),
+        );
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Preview));
+        sources.update(
+            TallySource::Roland,
+            Change::Tally(Some(TallyState::Program)),
+            at(3),
+        );
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+    }
+
+    #[test]
+    fn test_color_override() {
+        let priorities = SourcePriorities {
+            api: 150,
+            ..SourcePriorities::default()
+        };
+        let (red, blue) = (Color::new(255, 0, 0), Color::new(0, 0, 255));
+        let mut sources = Sources::new();
+        sources.update(TallySource::Api, Change::ColorOverride(Some(red)), at(1));
+        sources.update(TallySource::Osc, Change::ColorOverride(Some(blue)), at(2));
+        sources.update(
+            TallySource::Osc,
+            Change::Tally(Some(TallyState::Program)),
+            at(3),
+        );
+        assert_eq!(sources.color_override(&priorities), Some(red));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+        sources.update(TallySource::Api, Change::ColorOverride(None), at(4));
+        assert_eq!(sources.color_override(&priorities), Some(blue));
+        // Withdrawing the override leaves the tally alone
+        sources.update(TallySource::Osc, Change::ColorOverride(None), at(5));
+        assert_eq!(sources.color_override(&priorities), None);
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+    }
+
+    #[test]
+    fn test_mapping() {
+        use tally_rpc::rpc::{Bus, Combine, MappedProtocol, Selector};
+
+        let priorities = SourcePriorities::default();
+        let selector = Selector {
+            protocol: MappedProtocol::Vmix,
+            input: 2,
+            bus: Bus::Program,
+        };
+        let mut mapping = TallyMapping {
+            combine: Combine::Or,
+            selectors: heapless::Vec::from_slice(&[selector]).unwrap(),
+        };
+        let mut sources = Sources::new();
+        sources.set_mapping(Some(mapping.clone()), at(1));
+        let matches = Matches { known: 1, on: 1 };
+        sources.update(TallySource::Mapping, Change::Matches(matches), at(2));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+        // Unchanged, so the matches still stand
+        sources.set_mapping(Some(mapping.clone()), at(3));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+        mapping.combine = Combine::And;
+        sources.set_mapping(Some(mapping), at(4));
+        assert_eq!(sources.tally(&priorities), None);
+    }
+
+    #[test]
+    fn test_mapping_keep_alive() {
+        use tally_rpc::rpc::{Bus, Combine, MappedProtocol, Selector};
+
+        let timeouts = SourceTimeouts {
+            mapping: 5,
+            ..SourceTimeouts::default()
+        };
+        let selector = Selector {
+            protocol: MappedProtocol::Atem,
+            input: 2,
+            bus: Bus::Program,
+        };
+        let mapping = TallyMapping {
+            combine: Combine::Or,
+            selectors: heapless::Vec::from_slice(&[selector]).unwrap(),
+        };
+        let mut sources = Sources::new();
+        sources.set_mapping(Some(mapping), at(1));
+        let matches = Matches { known: 1, on: 0 };
+        sources.update(TallySource::Mapping, Change::Matches(matches), at(2));
+        assert_eq!(sources.next_deadline(&timeouts), Some(at(7)));
+        // The same matches again still show the protocol is there
+        sources.update(TallySource::Mapping, Change::Matches(matches), at(6));
+        sources.expire(&timeouts, at(8));
+        assert!(sources.lost_sources().is_empty());
+        assert_eq!(sources.next_deadline(&timeouts), Some(at(11)));
+    }
+
+    #[test]
+    fn test_mapping_forget() {
+        use tally_rpc::rpc::{Bus, Combine, MappedProtocol, Selector};
+
+        let priorities = SourcePriorities::default();
+        let selector = |protocol, bus| Selector {
+            protocol,
+            input: 2,
+            bus,
+        };
+        let mapping = TallyMapping {
+            combine: Combine::Or,
+            selectors: heapless::Vec::from_slice(&[
+                selector(MappedProtocol::Vmix, Bus::Program),
+                selector(MappedProtocol::Atem, Bus::Preview),
+            ])
+            .unwrap(),
+        };
+        let mut sources = Sources::new();
+        sources.set_mapping(Some(mapping), at(1));
+        let matches = Matches {
+            known: 0b11,
+            on: 0b11,
+        };
+        sources.update(TallySource::Mapping, Change::Matches(matches), at(2));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::ProgramPreview));
+        // vMix went away, so its selector no longer matches
+        sources.update(TallySource::Mapping, Change::Forget(0b01), at(3));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Preview));
+        sources.update(TallySource::Mapping, Change::Forget(0b10), at(4));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::LostSignal));
+        assert_eq!(sources.lost_sources(), [TallySource::Mapping]);
+        let matches = Matches {
+            known: 0b01,
+            on: 0b01,
+        };
+        sources.update(TallySource::Mapping, Change::Matches(matches), at(5));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let priorities = SourcePriorities::default();
+        let timeouts = SourceTimeouts {
+            tsl: 5,
+            ..SourceTimeouts::default()
+        };
+        let mut sources = Sources::new();
+        sources.update(
+            TallySource::Tsl,
+            Change::Tally(Some(TallyState::Program)),
+            at(1),
+        );
+        sources.update(
+            TallySource::Atem,
+            Change::Tally(Some(TallyState::Preview)),
+            at(2),
+        );
+        assert_eq!(sources.next_deadline(&timeouts), Some(at(6)));
+        // Repeating the tally keeps it alive
+        sources.update(
+            TallySource::Tsl,
+            Change::Tally(Some(TallyState::Program)),
+            at(4),
+        );
+        sources.expire(&timeouts, at(8));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Program));
+        assert!(sources.lost_sources().is_empty());
+        sources.expire(&timeouts, at(9));
+        assert_eq!(sources.tally(&priorities), Some(TallyState::Preview));
+        assert_eq!(sources.lost_sources(), [TallySource::Tsl]);
+        assert_eq!(sources.next_deadline(&timeouts), None);
+        sources.update(
+            TallySource::Atem,
+            Change::Tally(Some(TallyState::LostSignal)),
+            at(10),
+        );
+        assert_eq!(sources.tally(&priorities), Some(TallyState::LostSignal));
+        assert_eq!(
+            sources.lost_sources(),
+            [TallySource::Tsl, TallySource::Atem]
+        );
+    }
+}
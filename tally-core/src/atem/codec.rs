@@ -0,0 +1,677 @@
+//! ATEM switcher control protocol packet codec.
+//!
+//! Every UDP packet starts with a 12 byte header: 5 bits of flags, an 11 bit length (including
+//! the header), the session ID, the ID of the packet being acknowledged, the ID to resend from,
+//! 2 unused bytes and finally this packet's own ID. The payload is a list of commands, each
+//! with a 4 character name.
+
+use crate::state::TallyState;
+
+pub const HEADER_LEN: usize = 12;
+const HELLO_LEN: usize = 20;
+const COMMAND_HEADER_LEN: usize = 8;
+
+/// Packet IDs are 15 bits.
+const PACKET_ID_MASK: u16 = 0x7fff;
+
+pub mod flags {
+    /// The sender wants this packet acknowledged
+    pub const ACK_REQUEST: u8 = 0x01;
+    /// Connection handshake
+    pub const HELLO: u8 = 0x02;
+    /// This packet is a retransmission
+    pub const RESEND: u8 = 0x04;
+    /// The sender wants packets resent, starting from `resend_from`
+    pub const RESEND_REQUEST: u8 = 0x08;
+    /// This packet acknowledges `ack_id`
+    pub const ACK: u8 = 0x10;
+}
+
+/// `RTMS` status flag: currently recording
+const RECORDING_STATUS_RECORDING: u16 = 0x0001;
+/// `StRS` status flag: currently streaming
+const STREAMING_STATUS_STREAMING: u16 = 0x0004;
+
+/// Hello reply payload: the switcher has accepted our connection.
+const HELLO_ACCEPTED: u8 = 0x02;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// Not enough bytes for the header, or fewer bytes than the header claims
+    TooShort,
+    /// A command claimed a length shorter than its own header
+    BadCommandLength,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Header {
+    pub flags: u8,
+    pub length: u16,
+    pub session_id: u16,
+    pub ack_id: u16,
+    pub resend_from: u16,
+    pub packet_id: u16,
+}
+
+impl Header {
+    pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        let buf: &[u8; HEADER_LEN] = buf
+            .get(..HEADER_LEN)
+            .ok_or(DecodeError::TooShort)?
+            .try_into()
+            .unwrap();
+        let be = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]);
+        Ok(Self {
+            flags: buf[0] >> 3,
+            length: be(0) & 0x07ff,
+            session_id: be(2),
+            ack_id: be(4),
+            resend_from: be(6),
+            packet_id: be(10),
+        })
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0; HEADER_LEN];
+        buf[0..2].copy_from_slice(&(u16::from(self.flags) << 11 | self.length).to_be_bytes());
+        buf[2..4].copy_from_slice(&self.session_id.to_be_bytes());
+        buf[4..6].copy_from_slice(&self.ack_id.to_be_bytes());
+        buf[6..8].copy_from_slice(&self.resend_from.to_be_bytes());
+        buf[10..12].copy_from_slice(&self.packet_id.to_be_bytes());
+        buf
+    }
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// A decoded packet: its header and command payload.
+pub struct Packet<'a> {
+    pub header: Header,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    pub fn decode(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        let header = Header::decode(buf)?;
+        let payload = buf
+            .get(HEADER_LEN..usize::from(header.length))
+            .ok_or(DecodeError::TooShort)?;
+        Ok(Self { header, payload })
+    }
+
+    pub fn commands(&self) -> Commands<'a> {
+        Commands { rest: self.payload }
+    }
+}
+
+/// Iterator over the commands in a packet payload.
+///
+/// Iteration stops after the first malformed command.
+pub struct Commands<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Commands<'a> {
+    type Item = Result<Command<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let &[len_hi, len_lo, _, _, a, b, c, d, ..] = self.rest else {
+            self.rest = &[];
+            return Some(Err(DecodeError::TooShort));
+        };
+        let len = usize::from(u16::from_be_bytes([len_hi, len_lo]));
+        if len < COMMAND_HEADER_LEN {
+            self.rest = &[];
+            return Some(Err(DecodeError::BadCommandLength));
+        }
+        let Some(data) = self.rest.get(COMMAND_HEADER_LEN..len) else {
+            self.rest = &[];
+            return Some(Err(DecodeError::TooShort));
+        };
+        self.rest = &self.rest[len..];
+        Some(Ok(Command::decode([a, b, c, d], data)))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Command<'a> {
+    /// `_ver`: protocol version
+    Version { major: u16, minor: u16 },
+    /// `InCm`: the initial state dump is complete
+    InitComplete,
+    /// `TlIn`: tally by input index
+    TallyByIndex(TallyByIndex<'a>),
+    /// `TlSr`: tally by source ID
+    TallyBySource(TallyBySource<'a>),
+    /// `TrPs`: transition position of a mix effect bus
+    TransitionPosition {
+        me: u8,
+        in_transition: bool,
+        frames_remaining: u8,
+        /// 0 to [`TRANSITION_POSITION_MAX`]
+        position: u16,
+    },
+    /// `RTMS`: recording status
+    RecordingStatus { recording: bool },
+    /// `StRS`: streaming status
+    StreamingStatus { streaming: bool },
+    /// Something we don't care about (yet)
+    Other([u8; 4], &'a [u8]),
+}
+
+impl<'a> Command<'a> {
+    fn decode(name: [u8; 4], data: &'a [u8]) -> Self {
+        match &name {
+            b"_ver" if data.len() >= 4 => Self::Version {
+                major: u16::from_be_bytes([data[0], data[1]]),
+                minor: u16::from_be_bytes([data[2], data[3]]),
+            },
+            b"InCm" => Self::InitComplete,
+            b"TlIn" => Self::TallyByIndex(TallyByIndex(data)),
+            b"TlSr" => Self::TallyBySource(TallyBySource(data)),
+            b"TrPs" if data.len() >= 6 => Self::TransitionPosition {
+                me: data[0],
+                in_transition: data[1] != 0,
+                frames_remaining: data[2],
+                position: u16::from_be_bytes([data[4], data[5]]),
+            },
+            b"RTMS" if data.len() >= 2 => Self::RecordingStatus {
+                recording: u16::from_be_bytes([data[0], data[1]]) & RECORDING_STATUS_RECORDING != 0,
+            },
+            b"StRS" if data.len() >= 2 => Self::StreamingStatus {
+                streaming: u16::from_be_bytes([data[0], data[1]]) & STREAMING_STATUS_STREAMING != 0,
+            },
+            _ => Self::Other(name, data),
+        }
+    }
+}
+
+/// Transition handle position when a transition is complete.
+pub const TRANSITION_POSITION_MAX: u16 = 10000;
+
+/// Works out how far a transition has taken an input towards program.
+///
+/// During a mix both inputs are reported as on program, so to know which way we're going we
+/// remember the input's tally from before the transition started.
+#[derive(Default)]
+pub struct TransitionTracker {
+    /// The input's tally, and the one it had before that
+    current: TallyState,
+    previous: TallyState,
+    /// The input's tally when the current transition started
+    before: Option<TallyState>,
+}
+
+impl TransitionTracker {
+    /// Record the input's latest tally.
+    pub fn tally(&mut self, tally: TallyState) {
+        if tally != self.current {
+            self.previous = self.current;
+            self.current = tally;
+        }
+    }
+
+    /// Record a transition position update, returning how far towards program the input is
+    /// (0 for preview, 255 for program), or `None` if it isn't involved in a transition.
+    pub fn position(&mut self, in_transition: bool, position: u16) -> Option<u8> {
+        if !in_transition {
+            self.before = None;
+            return None;
+        }
+        let before = *self.before.get_or_insert(match self.current {
+            // The tally for the start of the mix can come before the transition position, in
+            // which case it's already on program
+            TallyState::ProgramPreview => self.previous,
+            current => current,
+        });
+        let level = (u32::from(position.min(TRANSITION_POSITION_MAX)) * 255
+            / u32::from(TRANSITION_POSITION_MAX)) as u8;
+        match before {
+            // Coming on air
+            TallyState::Preview => Some(level),
+            // Going off air
+            TallyState::Program => Some(255 - level),
+            _ => None,
+        }
+    }
+}
+
+fn tally_flags(flags: u8) -> TallyState {
+    TallyState::new(flags & 0b01 != 0, flags & 0b10 != 0)
+}
+
+/// `TlIn` data: a count, then one flags byte per input.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TallyByIndex<'a>(&'a [u8]);
+
+impl TallyByIndex<'_> {
+    /// Tally for a (1-based) input number.
+    pub fn get(&self, input: u16) -> Option<TallyState> {
+        let count = u16::from_be_bytes([*self.0.first()?, *self.0.get(1)?]);
+        if input == 0 || input > count {
+            return None;
+        }
+        self.0.get(1 + usize::from(input)).copied().map(tally_flags)
+    }
+}
+
+/// `TlSr` data: a count, then a big-endian source ID and flags byte per source.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TallyBySource<'a>(&'a [u8]);
+
+impl TallyBySource<'_> {
+    pub fn get(&self, source: u16) -> Option<TallyState> {
+        let count = usize::from(u16::from_be_bytes([*self.0.first()?, *self.0.get(1)?]));
+        self.0
+            .get(2..)?
+            .chunks_exact(3)
+            .take(count)
+            .find(|e| u16::from_be_bytes([e[0], e[1]]) == source)
+            .map(|e| tally_flags(e[2]))
+    }
+}
+
+/// Is `id` after `last`, allowing for wraparound?
+fn is_newer(id: u16, last: u16) -> bool {
+    let diff = id.wrapping_sub(last) & PACKET_ID_MASK;
+    diff != 0 && diff < PACKET_ID_MASK / 2
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SessionState {
+    /// Hello sent, waiting for the switcher to reply
+    Connecting,
+    /// Receiving the initial state dump
+    Initialising,
+    Connected,
+}
+
+/// What to do with a packet received by a [`Session`].
+pub struct Received<'a> {
+    /// Send this back to the switcher
+    pub reply: Option<[u8; HEADER_LEN]>,
+    /// Commands to act on. `None` for packets we've already seen.
+    pub commands: Option<Commands<'a>>,
+}
+
+/// Client side of a single ATEM session.
+pub struct Session {
+    session_id: u16,
+    last_packet_id: Option<u16>,
+    pub state: SessionState,
+}
+
+impl Session {
+    /// Start a new session. `client_id` should differ between connections so the switcher can
+    /// tell them apart.
+    pub fn new(client_id: u16) -> Self {
+        Self {
+            session_id: client_id & PACKET_ID_MASK,
+            last_packet_id: None,
+            state: SessionState::Connecting,
+        }
+    }
+
+    pub fn session_id(&self) -> u16 {
+        self.session_id
+    }
+
+    pub fn hello(&self) -> [u8; HELLO_LEN] {
+        let mut buf = [0; HELLO_LEN];
+        buf[..HEADER_LEN].copy_from_slice(
+            &Header {
+                flags: flags::HELLO,
+                length: HELLO_LEN as u16,
+                session_id: self.session_id,
+                ..Default::default()
+            }
+            .encode(),
+        );
+        buf[HEADER_LEN] = 0x01;
+        buf
+    }
+
+    fn ack(&self, packet_id: u16) -> [u8; HEADER_LEN] {
+        Header {
+            flags: flags::ACK,
+            length: HEADER_LEN as u16,
+            session_id: self.session_id,
+            ack_id: packet_id,
+            ..Default::default()
+        }
+        .encode()
+    }
+
+    fn resend_request(&self, from: u16) -> [u8; HEADER_LEN] {
+        Header {
+            flags: flags::RESEND_REQUEST,
+            length: HEADER_LEN as u16,
+            session_id: self.session_id,
+            resend_from: from,
+            ..Default::default()
+        }
+        .encode()
+    }
+
+    pub fn receive<'a>(&mut self, buf: &'a [u8]) -> Result<Received<'a>, DecodeError> {
+        let packet = Packet::decode(buf)?;
+        let header = packet.header;
+        if header.has(flags::HELLO) {
+            if packet.payload.first() != Some(&HELLO_ACCEPTED) {
+                return Ok(Received {
+                    reply: None,
+                    commands: None,
+                });
+            }
+            self.state = SessionState::Initialising;
+            self.last_packet_id = None;
+            return Ok(Received {
+                reply: Some(self.ack(0)),
+                commands: None,
+            });
+        }
+        if self.state == SessionState::Connecting {
+            return Ok(Received {
+                reply: None,
+                commands: None,
+            });
+        }
+        // After the handshake the switcher picks the session ID
+        self.session_id = header.session_id;
+        if !header.has(flags::ACK_REQUEST) {
+            return Ok(Received {
+                reply: None,
+                commands: Some(packet.commands()),
+            });
+        }
+        let next = self
+            .last_packet_id
+            .map(|last| last.wrapping_add(1) & PACKET_ID_MASK);
+        match (self.last_packet_id, next) {
+            // Packets have to be processed in order, so ask for the ones we missed and leave
+            // this one unacked for the switcher to send again.
+            (_, Some(next)) if is_newer(header.packet_id, next) => Ok(Received {
+                reply: Some(self.resend_request(next)),
+                commands: None,
+            }),
+            // Always ack duplicates, as our previous ack may have been lost.
+            (Some(last), _) if !is_newer(header.packet_id, last) => Ok(Received {
+                reply: Some(self.ack(header.packet_id)),
+                commands: None,
+            }),
+            _ => {
+                self.last_packet_id = Some(header.packet_id);
+                Ok(Received {
+                    reply: Some(self.ack(header.packet_id)),
+                    commands: Some(packet.commands()),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Packets in the form an ATEM Mini sends them.
+    const HELLO_REPLY: [u8; 20] = [
+        0x10, 0x14, 0x53, 0xab, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+        0x03, 0x00, 0x00, 0x00, 0x00,
+    ];
+    const VERSION: [u8; 24] = [
+        0x08, 0x18, 0x80, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x0c, 0x00,
+        0x00, b'_', b'v', b'e', b'r', 0x00, 0x02, 0x00, 0x1e,
+    ];
+    const TALLY: [u8; 48] = [
+        0x08, 0x30, 0x80, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, // header
+        0x00, 0x10, 0x00, 0x00, b'T', b'l', b'I', b'n', 0x00, 0x04, 0x01, 0x02, 0x00, 0x03, 0x00,
+        0x00, // TlIn
+        0x00, 0x14, 0x00, 0x00, b'T', b'l', b'S', b'r', 0x00, 0x03, 0x00, 0x01, 0x01, 0x00, 0x02,
+        0x02, 0x03, 0xe8, 0x00, 0x00, // TlSr
+    ];
+    const INIT_COMPLETE: [u8; 24] = [
+        0x08, 0x18, 0x80, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x0c, 0x00,
+        0x00, b'I', b'n', b'C', b'm', 0x01, 0x00, 0x00, 0x00,
+    ];
+    const PING: [u8; 12] = [
+        0x08, 0x0c, 0x80, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+    ];
+
+    #[test]
+    fn test_header() {
+        let header = Header::decode(&VERSION).unwrap();
+        assert_eq!(
+            header,
+            Header {
+                flags: flags::ACK_REQUEST,
+                length: 24,
+                session_id: 0x800b,
+                ack_id: 0,
+                resend_from: 0,
+                packet_id: 1,
+            }
+        );
+        assert_eq!(header.encode(), VERSION[..12]);
+        assert_eq!(Header::decode(&VERSION[..11]), Err(DecodeError::TooShort));
+    }
+
+    #[test]
+    fn test_hello() {
+        let session = Session::new(0x53ab);
+        assert_eq!(
+            session.hello(),
+            [
+                0x10, 0x14, 0x53, 0xab, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        let packet = Packet::decode(&TALLY).unwrap();
+        let mut commands = packet.commands();
+        let Some(Ok(Command::TallyByIndex(by_index))) = commands.next() else {
+            panic!("expected TlIn");
+        };
+        assert_eq!(by_index.get(1), Some(TallyState::Program));
+        assert_eq!(by_index.get(2), Some(TallyState::Preview));
+        assert_eq!(by_index.get(3), Some(TallyState::Off));
+        assert_eq!(by_index.get(4), Some(TallyState::ProgramPreview));
+        assert_eq!(by_index.get(5), None);
+        assert_eq!(by_index.get(0), None);
+        let Some(Ok(Command::TallyBySource(by_source))) = commands.next() else {
+            panic!("expected TlSr");
+        };
+        assert_eq!(by_source.get(1), Some(TallyState::Program));
+        assert_eq!(by_source.get(2), Some(TallyState::Preview));
+        assert_eq!(by_source.get(1000), Some(TallyState::Off));
+        assert_eq!(by_source.get(3), None);
+        assert!(commands.next().is_none());
+
+        let packet = Packet::decode(&VERSION).unwrap();
+        assert_eq!(
+            packet.commands().next(),
+            Some(Ok(Command::Version {
+                major: 2,
+                minor: 30
+            }))
+        );
+    }
+
+    #[test]
+    fn test_bad_commands() {
+        let mut buf = VERSION;
+        // Command claims to be longer than the packet
+        buf[13] = 0x0d;
+        let packet = Packet::decode(&buf).unwrap();
+        let mut commands = packet.commands();
+        assert_eq!(commands.next(), Some(Err(DecodeError::TooShort)));
+        assert_eq!(commands.next(), None);
+
+        buf[13] = 0x04;
+        let packet = Packet::decode(&buf).unwrap();
+        assert_eq!(
+            packet.commands().next(),
+            Some(Err(DecodeError::BadCommandLength))
+        );
+
+        // Header claims to be longer than the packet
+        assert!(Packet::decode(&VERSION[..23]).is_err());
+    }
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::new(0x53ab);
+        assert_eq!(session.state, SessionState::Connecting);
+
+        // Nothing but a hello reply is meaningful until we're connected
+        let r = session.receive(&VERSION).unwrap();
+        assert!(r.reply.is_none() && r.commands.is_none());
+
+        let r = session.receive(&HELLO_REPLY).unwrap();
+        assert_eq!(
+            r.reply,
+            Some([0x80, 0x0c, 0x53, 0xab, 0, 0, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(session.state, SessionState::Initialising);
+
+        // Switcher assigns the session ID from here on
+        let r = session.receive(&VERSION).unwrap();
+        assert_eq!(
+            r.reply,
+            Some([0x80, 0x0c, 0x80, 0x0b, 0, 1, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(session.session_id(), 0x800b);
+        assert_eq!(r.commands.unwrap().count(), 1);
+
+        let r = session.receive(&TALLY).unwrap();
+        assert_eq!(r.commands.unwrap().count(), 2);
+
+        // A resent duplicate is acked, but not processed again
+        let mut resent = TALLY;
+        resent[0] |= flags::RESEND << 3;
+        let r = session.receive(&resent).unwrap();
+        assert_eq!(
+            r.reply,
+            Some([0x80, 0x0c, 0x80, 0x0b, 0, 2, 0, 0, 0, 0, 0, 0])
+        );
+        assert!(r.commands.is_none());
+
+        // Skipping a packet gets a request to resend from the missing one, and nothing else
+        let r = session.receive(&PING).unwrap();
+        assert_eq!(
+            r.reply,
+            Some([0x40, 0x0c, 0x80, 0x0b, 0, 0, 0, 3, 0, 0, 0, 0])
+        );
+        assert!(r.commands.is_none());
+
+        let r = session.receive(&INIT_COMPLETE).unwrap();
+        assert_eq!(r.commands.unwrap().next(), Some(Ok(Command::InitComplete)));
+
+        let r = session.receive(&PING).unwrap();
+        assert_eq!(
+            r.reply,
+            Some([0x80, 0x0c, 0x80, 0x0b, 0, 4, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(r.commands.unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_transition_position() {
+        let data = [0x00, 0x01, 0x0c, 0x00, 0x13, 0x88, 0x00, 0x00];
+        assert_eq!(
+            Command::decode(*b"TrPs", &data),
+            Command::TransitionPosition {
+                me: 0,
+                in_transition: true,
+                frames_remaining: 12,
+                position: 5000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_output_status() {
+        assert_eq!(
+            Command::decode(*b"RTMS", &[0x00, 0x01, 0x00, 0x00]),
+            Command::RecordingStatus { recording: true }
+        );
+        // Stopping
+        assert_eq!(
+            Command::decode(*b"RTMS", &[0x00, 0x80, 0x00, 0x00]),
+            Command::RecordingStatus { recording: false }
+        );
+        assert_eq!(
+            Command::decode(*b"StRS", &[0x00, 0x04, 0x00, 0x00]),
+            Command::StreamingStatus { streaming: true }
+        );
+        // Connecting
+        assert_eq!(
+            Command::decode(*b"StRS", &[0x00, 0x02, 0x00, 0x00]),
+            Command::StreamingStatus { streaming: false }
+        );
+    }
+
+    #[test]
+    fn test_transition_tracker() {
+        let mut incoming = TransitionTracker::default();
+        let mut outgoing = TransitionTracker::default();
+        let mut bystander = TransitionTracker::default();
+        incoming.tally(TallyState::Preview);
+        outgoing.tally(TallyState::Program);
+        bystander.tally(TallyState::Off);
+
+        assert_eq!(incoming.position(true, 0), Some(0));
+        assert_eq!(outgoing.position(true, 0), Some(255));
+        assert_eq!(bystander.position(true, 0), None);
+
+        // The switcher puts both inputs on program for the duration of the mix
+        incoming.tally(TallyState::ProgramPreview);
+        outgoing.tally(TallyState::ProgramPreview);
+        assert_eq!(incoming.position(true, 5000), Some(127));
+        assert_eq!(outgoing.position(true, 5000), Some(128));
+        assert_eq!(incoming.position(true, TRANSITION_POSITION_MAX), Some(255));
+        assert_eq!(outgoing.position(true, TRANSITION_POSITION_MAX), Some(0));
+
+        assert_eq!(incoming.position(false, 0), None);
+        assert_eq!(outgoing.position(false, 0), None);
+        incoming.tally(TallyState::Program);
+        outgoing.tally(TallyState::Preview);
+
+        // And back again
+        assert_eq!(incoming.position(true, 2500), Some(255 - 63));
+        assert_eq!(outgoing.position(true, 2500), Some(63));
+    }
+
+    #[test]
+    fn test_transition_tally_first() {
+        let mut incoming = TransitionTracker::default();
+        incoming.tally(TallyState::Preview);
+        // The mix's tally arrives before its first position
+        incoming.tally(TallyState::ProgramPreview);
+        assert_eq!(incoming.position(true, 0), Some(0));
+        assert_eq!(incoming.position(true, 5000), Some(127));
+        assert_eq!(incoming.position(false, 0), None);
+        incoming.tally(TallyState::Program);
+        assert_eq!(incoming.position(true, 2500), Some(255 - 63));
+    }
+
+    #[test]
+    fn test_packet_id_wrap() {
+        assert!(is_newer(1, 0));
+        assert!(is_newer(0, 0x7fff));
+        assert!(!is_newer(0x7fff, 0));
+        assert!(!is_newer(5, 5));
+        assert!(!is_newer(4, 5));
+    }
+}
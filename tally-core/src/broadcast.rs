@@ -0,0 +1,131 @@
+//! Following one camera through the native µTally tally broadcast, see
+//! [`tally_rpc::broadcast`].
+
+use embassy_time::{Duration, Instant};
+use tally_rpc::{
+    broadcast::{self, TallyBroadcast},
+    rpc::Color,
+};
+
+use crate::state::TallyState;
+
+/// After this long without a packet any sequence number is taken, in case the sender restarted.
+const SEQ_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What a broadcast means for this device.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Update {
+    pub tally: TallyState,
+    /// `Some` when the colour override needs to change
+    pub color: Option<Option<Color>>,
+}
+
+/// Follows one camera's state through a stream of broadcasts.
+pub struct Follower {
+    camera: u8,
+    /// Sequence number of the last broadcast taken, and when it arrived
+    last: Option<(u32, Instant)>,
+    /// Whether we've set a colour override, so it's only withdrawn when there is one
+    overriding: bool,
+}
+
+impl Follower {
+    pub fn new(camera: u8) -> Self {
+        Self {
+            camera,
+            last: None,
+            overriding: false,
+        }
+    }
+
+    /// Whether the last broadcast taken set a colour override.
+    pub fn overriding(&self) -> bool {
+        self.overriding
+    }
+
+    pub fn handle(&mut self, broadcast: &TallyBroadcast, now: Instant) -> Option<Update> {
+        if let Some((last, at)) = self.last
+            && !broadcast::is_newer(broadcast.seq, last)
+            && now - at < SEQ_TIMEOUT
+        {
+            return None;
+        }
+        self.last = Some((broadcast.seq, now));
+        let (program, preview) = broadcast.tally(self.camera)?;
+        let color = broadcast.color(self.camera);
+        let color = (color.is_some() || self.overriding).then_some(color);
+        self.overriding = matches!(color, Some(Some(_)));
+        Some(Update {
+            tally: TallyState::new(program, preview),
+            color,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::Vec;
+    use tally_rpc::broadcast::ColorOverride;
+
+    use super::*;
+
+    fn broadcast(seq: u32, program: u64, color: Option<Color>) -> TallyBroadcast {
+        let mut colors = Vec::new();
+        if let Some(color) = color {
+            colors.push(ColorOverride { camera: 2, color }).unwrap();
+        }
+        TallyBroadcast {
+            seq,
+            program,
+            preview: 0,
+            colors,
+            text: None,
+        }
+    }
+
+    #[test]
+    fn test_follower() {
+        let mut follower = Follower::new(2);
+        let at = Instant::from_secs(10);
+        assert_eq!(
+            follower.handle(&broadcast(5, 0b10, None), at),
+            Some(Update {
+                tally: TallyState::Program,
+                color: None,
+            })
+        );
+        // Out of order
+        assert_eq!(follower.handle(&broadcast(4, 0, None), at), None);
+        let blue = Color::new(0, 0, 255);
+        assert_eq!(
+            follower.handle(&broadcast(6, 0, Some(blue)), at),
+            Some(Update {
+                tally: TallyState::Off,
+                color: Some(Some(blue)),
+            })
+        );
+        assert_eq!(
+            follower.handle(&broadcast(7, 0, None), at),
+            Some(Update {
+                tally: TallyState::Off,
+                color: Some(None),
+            })
+        );
+        assert_eq!(
+            follower.handle(&broadcast(8, 0, None), at),
+            Some(Update {
+                tally: TallyState::Off,
+                color: None,
+            })
+        );
+        // The sender restarted
+        assert_eq!(follower.handle(&broadcast(0, 0b10, None), at), None);
+        assert_eq!(
+            follower.handle(&broadcast(0, 0b10, None), at + SEQ_TIMEOUT),
+            Some(Update {
+                tally: TallyState::Program,
+                color: None,
+            })
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! E1.31 (sACN) and Art-Net DMX packets, and mapping their data onto pixels.
+
+use embassy_time::{Duration, Instant};
+use tally_rpc::rpc::{Color, DmxMode};
+
+pub const SACN_PORT: u16 = 5568;
+pub const ARTNET_PORT: u16 = 6454;
+
+/// Longest packets we care about: full universes of 512 slots.
+pub const SACN_MAX_LEN: usize = 126 + 512;
+pub const ARTNET_MAX_LEN: usize = 18 + 512;
+
+/// E1.31 says a source that's been quiet this long has gone away.
+const SOURCE_TIMEOUT: Duration = Duration::from_millis(2500);
+
+const ACN_PACKET_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const OPTION_PREVIEW: u8 = 0x80;
+const OPTION_TERMINATED: u8 = 0x40;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_OP_DMX: u16 = 0x5000;
+
+/// The DMX null start code, i.e. plain levels.
+const START_CODE_DMX: u8 = 0x00;
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    TooShort,
+    BadHeader,
+    /// A valid packet, but not one carrying DMX levels
+    NotDmx,
+}
+
+/// The multicast group sACN sends `universe` to.
+pub fn sacn_multicast_group(universe: u16) -> [u8; 4] {
+    let [hi, lo] = universe.to_be_bytes();
+    [239, 255, hi, lo]
+}
+
+fn u16_at(buf: &[u8], i: usize) -> u16 {
+    u16::from_be_bytes([buf[i], buf[i + 1]])
+}
+
+fn u32_at(buf: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes(buf[i..i + 4].try_into().unwrap())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SacnPacket<'a> {
+    /// Identifies the sender
+    pub cid: [u8; 16],
+    pub priority: u8,
+    /// Not meant for live output
+    pub preview: bool,
+    /// The source is going away
+    pub terminated: bool,
+    pub universe: u16,
+    /// Levels, starting at DMX address 1
+    pub data: &'a [u8],
+}
+
+impl<'a> SacnPacket<'a> {
+    pub fn decode(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        if buf.len() < 126 {
+            return Err(DecodeError::TooShort);
+        }
+        if u16_at(buf, 0) != 0x0010 || &buf[4..16] != ACN_PACKET_ID {
+            return Err(DecodeError::BadHeader);
+        }
+        if u32_at(buf, 18) != VECTOR_ROOT_E131_DATA
+            || u32_at(buf, 40) != VECTOR_E131_DATA_PACKET
+            || buf[117] != VECTOR_DMP_SET_PROPERTY
+        {
+            // Sync and discovery packets
+            return Err(DecodeError::NotDmx);
+        }
+        if buf[125] != START_CODE_DMX {
+            return Err(DecodeError::NotDmx);
+        }
+        // The count includes the start code
+        let slots = usize::from(u16_at(buf, 123)).saturating_sub(1);
+        let data = buf.get(126..126 + slots).ok_or(DecodeError::TooShort)?;
+        Ok(Self {
+            cid: buf[22..38].try_into().unwrap(),
+            priority: buf[108],
+            preview: buf[112] & OPTION_PREVIEW != 0,
+            terminated: buf[112] & OPTION_TERMINATED != 0,
+            universe: u16_at(buf, 113),
+            data,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ArtDmx<'a> {
+    /// Net, Sub-Net and Universe combined
+    pub port_address: u16,
+    /// Levels, starting at DMX address 1
+    pub data: &'a [u8],
+}
+
+impl<'a> ArtDmx<'a> {
+    pub fn decode(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        if buf.len() < 10 {
+            return Err(DecodeError::TooShort);
+        }
+        if &buf[..8] != ARTNET_ID {
+            return Err(DecodeError::BadHeader);
+        }
+        // Opcodes are little endian, unlike everything else
+        if u16::from_le_bytes([buf[8], buf[9]]) != ARTNET_OP_DMX {
+            return Err(DecodeError::NotDmx);
+        }
+        if buf.len() < 18 {
+            return Err(DecodeError::TooShort);
+        }
+        let len = usize::from(u16_at(buf, 16));
+        Ok(Self {
+            port_address: u16::from_le_bytes([buf[14], buf[15]]) & 0x7fff,
+            data: buf.get(18..18 + len).ok_or(DecodeError::TooShort)?,
+        })
+    }
+}
+
+/// Map DMX levels onto `N` pixels, starting at (1-based) `start_address`. Channels the data
+/// doesn't reach are off.
+pub fn map_pixels<const N: usize>(data: &[u8], start_address: u16, mode: DmxMode) -> [Color; N] {
+    let level = |channel: usize| {
+        usize::from(start_address.max(1) - 1)
+            .checked_add(channel)
+            .and_then(|i| data.get(i))
+            .copied()
+            .unwrap_or(0)
+    };
+    let color = |first: usize| Color::new(level(first), level(first + 1), level(first + 2));
+    match mode {
+        DmxMode::PerPixel => core::array::from_fn(|i| color(i * 3)),
+        DmxMode::WholeDevice => [color(0); N],
+    }
+}
+
+struct Active<S> {
+    source: S,
+    priority: u8,
+    last_seen: Instant,
+}
+
+/// Picks which source's data to show. The highest priority source wins, and ties go to
+/// whoever was there first. There's no merging of sources.
+pub struct Arbiter<S> {
+    active: Option<Active<S>>,
+}
+
+impl<S> Default for Arbiter<S> {
+    fn default() -> Self {
+        Self { active: None }
+    }
+}
+
+impl<S: PartialEq> Arbiter<S> {
+    /// Whether data just received from `source` should be shown.
+    pub fn accept(&mut self, source: S, priority: u8, now: Instant) -> bool {
+        if let Some(active) = &mut self.active {
+            if active.source == source {
+                active.priority = priority;
+                active.last_seen = now;
+                return true;
+            }
+            let timed_out = now.saturating_duration_since(active.last_seen) >= SOURCE_TIMEOUT;
+            if priority <= active.priority && !timed_out {
+                return false;
+            }
+        }
+        self.active = Some(Active {
+            source,
+            priority,
+            last_seen: now,
+        });
+        true
+    }
+
+    /// Forget `source`, which has said it's going away.
+    pub fn terminate(&mut self, source: S) {
+        if self.active.as_ref().is_some_and(|a| a.source == source) {
+            self.active = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sacn_packet(
+        universe: u16,
+        priority: u8,
+        options: u8,
+        data: &[u8],
+    ) -> heapless::Vec<u8, 638> {
+        let mut p = heapless::Vec::new();
+        p.extend_from_slice(&[0x00, 0x10, 0x00, 0x00]).unwrap();
+        p.extend_from_slice(ACN_PACKET_ID).unwrap();
+        let root_len = 0x7000 | (110 + data.len() as u16);
+        p.extend_from_slice(&root_len.to_be_bytes()).unwrap();
+        p.extend_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes())
+            .unwrap();
+        p.extend_from_slice(&[0xc1; 16]).unwrap();
+        let framing_len = 0x7000 | (88 + data.len() as u16);
+        p.extend_from_slice(&framing_len.to_be_bytes()).unwrap();
+        p.extend_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes())
+            .unwrap();
+        p.extend_from_slice(&[b'x'; 64]).unwrap();
+        p.push(priority).unwrap();
+        p.extend_from_slice(&[0, 0, 7, options]).unwrap();
+        p.extend_from_slice(&universe.to_be_bytes()).unwrap();
+        let dmp_len = 0x7000 | (11 + data.len() as u16);
+        p.extend_from_slice(&dmp_len.to_be_bytes()).unwrap();
+        p.extend_from_slice(&[0x02, 0xa1, 0x00, 0x00, 0x00, 0x01])
+            .unwrap();
+        p.extend_from_slice(&(1 + data.len() as u16).to_be_bytes())
+            .unwrap();
+        p.push(START_CODE_DMX).unwrap();
+        p.extend_from_slice(data).unwrap();
+        p
+    }
+
+    #[test]
+    fn test_sacn() {
+        let packet = sacn_packet(1, 150, OPTION_TERMINATED, &[1, 2, 3]);
+        assert_eq!(
+            SacnPacket::decode(&packet),
+            Ok(SacnPacket {
+                cid: [0xc1; 16],
+                priority: 150,
+                preview: false,
+                terminated: true,
+                universe: 1,
+                data: &[1, 2, 3],
+            })
+        );
+        assert_eq!(
+            SacnPacket::decode(&packet[..125]),
+            Err(DecodeError::TooShort)
+        );
+        assert_eq!(
+            SacnPacket::decode(&packet[..packet.len() - 1]),
+            Err(DecodeError::TooShort)
+        );
+        let mut sync = packet.clone();
+        sync[43] = 0x01;
+        assert_eq!(SacnPacket::decode(&sync), Err(DecodeError::NotDmx));
+        assert_eq!(sacn_multicast_group(0x1234), [239, 255, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_artnet() {
+        let mut packet = heapless::Vec::<u8, 32>::new();
+        packet.extend_from_slice(ARTNET_ID).unwrap();
+        packet
+            .extend_from_slice(&[0x00, 0x50, 0, 14, 1, 0, 0x21, 0x03, 0x00, 0x04, 1, 2, 3, 4])
+            .unwrap();
+        assert_eq!(
+            ArtDmx::decode(&packet),
+            Ok(ArtDmx {
+                port_address: 0x0321,
+                data: &[1, 2, 3, 4]
+            })
+        );
+        // ArtPoll
+        assert_eq!(
+            ArtDmx::decode(b"Art-Net\0\x00\x20\x00\x0e\x00\x00"),
+            Err(DecodeError::NotDmx)
+        );
+    }
+
+    #[test]
+    fn test_map_pixels() {
+        let data = [0, 10, 20, 30, 40, 50, 60];
+        assert_eq!(
+            map_pixels::<3>(&data, 2, DmxMode::PerPixel),
+            [
+                Color::new(10, 20, 30),
+                Color::new(40, 50, 60),
+                Color::new(0, 0, 0)
+            ]
+        );
+        assert_eq!(
+            map_pixels::<2>(&data, 5, DmxMode::WholeDevice),
+            [Color::new(40, 50, 60); 2]
+        );
+        assert_eq!(
+            map_pixels::<1>(&data, 512, DmxMode::PerPixel),
+            [Color::new(0, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_arbiter() {
+        let t = |ms| Instant::from_millis(ms);
+        let mut arbiter = Arbiter::default();
+        assert!(arbiter.accept('a', 100, t(0)));
+        // Same priority, someone else
+        assert!(!arbiter.accept('b', 100, t(100)));
+        // Higher priority takes over
+        assert!(arbiter.accept('c', 150, t(200)));
+        assert!(!arbiter.accept('a', 100, t(300)));
+        // Until it goes quiet
+        assert!(arbiter.accept('a', 100, t(2700)));
+        arbiter.terminate('a');
+        assert!(arbiter.accept('b', 50, t(2800)));
+    }
+}
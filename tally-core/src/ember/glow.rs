@@ -0,0 +1,496 @@
+//! Just enough of Glow, the BER-encoded Ember+ data model, to follow one parameter.
+//!
+//! Providers may use definite or indefinite lengths, and may send a parameter either by its
+//! full path or nested in the nodes above it, so both have to be handled.
+
+use heapless::Vec;
+
+/// Deepest tree path we handle.
+pub const MAX_PATH: usize = 12;
+/// Deepest BER nesting we follow, well beyond the path depth as each element is several
+/// levels of TLVs.
+const MAX_DEPTH: usize = 64;
+
+mod tag {
+    pub const BOOLEAN: u8 = 0x01;
+    pub const INTEGER: u8 = 0x02;
+    pub const RELATIVE_OID: u8 = 0x0d;
+    pub const SET: u8 = 0x31;
+
+    pub const ROOT: u8 = 0x60;
+    pub const PARAMETER: u8 = 0x61;
+    pub const COMMAND: u8 = 0x62;
+    pub const NODE: u8 = 0x63;
+    pub const ELEMENT_COLLECTION: u8 = 0x64;
+    pub const QUALIFIED_PARAMETER: u8 = 0x69;
+    pub const QUALIFIED_NODE: u8 = 0x6a;
+    pub const ROOT_ELEMENT_COLLECTION: u8 = 0x6b;
+
+    /// Context-specific, constructed tag `[n]`
+    pub const fn context(n: u8) -> u8 {
+        0xa0 | n
+    }
+}
+
+pub mod command {
+    pub const SUBSCRIBE: u32 = 30;
+    pub const GET_DIRECTORY: u32 = 32;
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    Truncated,
+    /// Multi-byte tags, which Glow doesn't use
+    BadTag,
+    BadLength,
+    TooDeep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Value {
+    Boolean(bool),
+    Integer(i64),
+    /// Anything else, e.g. a string or real
+    Other,
+}
+
+/// One BER tag-length-value.
+#[derive(Debug, Clone, Copy)]
+struct Tlv<'a> {
+    tag: u8,
+    value: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    /// Read the TLV at the start of `buf`, returning it and the number of bytes it took.
+    fn read(buf: &'a [u8], depth: usize) -> Result<(Self, usize), DecodeError> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::TooDeep);
+        }
+        let [tag, len, rest @ ..] = buf else {
+            return Err(DecodeError::Truncated);
+        };
+        if tag & 0x1f == 0x1f {
+            return Err(DecodeError::BadTag);
+        }
+        match *len {
+            // Indefinite length, which runs until an end-of-contents at this level
+            0x80 => {
+                if tag & 0x20 == 0 {
+                    return Err(DecodeError::BadLength);
+                }
+                let mut pos = 0;
+                loop {
+                    if rest[pos..].starts_with(&[0, 0]) {
+                        let tlv = Self {
+                            tag: *tag,
+                            value: &rest[..pos],
+                        };
+                        return Ok((tlv, 2 + pos + 2));
+                    }
+                    let (_, used) = Self::read(&rest[pos..], depth + 1)?;
+                    pos += used;
+                }
+            }
+            len if len & 0x80 != 0 => {
+                let count = usize::from(len & 0x7f);
+                if count > 4 {
+                    return Err(DecodeError::BadLength);
+                }
+                let bytes = rest.get(..count).ok_or(DecodeError::Truncated)?;
+                let len = bytes
+                    .iter()
+                    .fold(0usize, |len, b| (len << 8) | usize::from(*b));
+                let end = count.checked_add(len).ok_or(DecodeError::BadLength)?;
+                let value = rest.get(count..end).ok_or(DecodeError::Truncated)?;
+                Ok((Self { tag: *tag, value }, 2 + end))
+            }
+            len => {
+                let len = usize::from(len);
+                let value = rest.get(..len).ok_or(DecodeError::Truncated)?;
+                Ok((Self { tag: *tag, value }, 2 + len))
+            }
+        }
+    }
+
+    /// The TLVs inside this one, which must be constructed.
+    fn children(&self, depth: usize) -> Children<'a> {
+        Children {
+            rest: self.value,
+            depth: depth + 1,
+        }
+    }
+
+    /// The single TLV inside an explicit tag.
+    fn inner(&self, depth: usize) -> Result<Self, DecodeError> {
+        Tlv::read(self.value, depth + 1).map(|(tlv, _)| tlv)
+    }
+}
+
+struct Children<'a> {
+    rest: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Result<Tlv<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        match Tlv::read(self.rest, self.depth) {
+            Ok((tlv, used)) => {
+                self.rest = &self.rest[used..];
+                Some(Ok(tlv))
+            }
+            Err(e) => {
+                self.rest = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+fn integer(bytes: &[u8]) -> Result<i64, DecodeError> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return Err(DecodeError::BadLength);
+    }
+    // Sign extend from the first byte
+    let init = if bytes[0] & 0x80 != 0 { -1 } else { 0 };
+    Ok(bytes
+        .iter()
+        .fold(init, |n: i64, b| (n << 8) | i64::from(*b)))
+}
+
+fn relative_oid(bytes: &[u8]) -> Result<Vec<u32, MAX_PATH>, DecodeError> {
+    let mut path = Vec::new();
+    let mut n = 0u32;
+    for b in bytes {
+        n = n.checked_mul(128).ok_or(DecodeError::BadLength)? | u32::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            path.push(n).map_err(|_| DecodeError::TooDeep)?;
+            n = 0;
+        }
+    }
+    Ok(path)
+}
+
+/// Find the value of the parameter at `target` in a Glow message, if it has one.
+pub fn find_value(message: &[u8], target: &[u32]) -> Result<Option<Value>, DecodeError> {
+    let (root, _) = Tlv::read(message, 0)?;
+    if root.tag != tag::ROOT {
+        return Ok(None);
+    }
+    let mut finder = Finder {
+        target,
+        path: Vec::new(),
+        value: None,
+    };
+    for child in root.children(0) {
+        let child = child?;
+        // Streams and invocation results can also be sent at the root
+        if child.tag == tag::ROOT_ELEMENT_COLLECTION {
+            finder.collection(&child, 1)?;
+        }
+    }
+    Ok(finder.value)
+}
+
+struct Finder<'t> {
+    target: &'t [u32],
+    path: Vec<u32, MAX_PATH>,
+    value: Option<Value>,
+}
+
+impl Finder<'_> {
+    /// A sequence of `[0]` tagged elements.
+    fn collection(&mut self, collection: &Tlv, depth: usize) -> Result<(), DecodeError> {
+        for item in collection.children(depth) {
+            let item = item?;
+            if item.tag == tag::context(0) {
+                self.element(&item.inner(depth + 1)?, depth + 2)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn element(&mut self, element: &Tlv, depth: usize) -> Result<(), DecodeError> {
+        let qualified = match element.tag {
+            tag::PARAMETER | tag::NODE => false,
+            tag::QUALIFIED_PARAMETER | tag::QUALIFIED_NODE => true,
+            // Commands, matrices, functions and templates
+            _ => return Ok(()),
+        };
+        let parameter = matches!(element.tag, tag::PARAMETER | tag::QUALIFIED_PARAMETER);
+        let parent = self.path.clone();
+        let result = self.element_fields(element, qualified, parameter, depth);
+        self.path = parent;
+        result
+    }
+
+    fn element_fields(
+        &mut self,
+        element: &Tlv,
+        qualified: bool,
+        parameter: bool,
+        depth: usize,
+    ) -> Result<(), DecodeError> {
+        for field in element.children(depth) {
+            let field = field?;
+            match field.tag {
+                // The number within the parent, or the whole path
+                0xa0 => {
+                    let inner = field.inner(depth + 1)?;
+                    match (qualified, inner.tag) {
+                        (false, tag::INTEGER) => {
+                            let number = u32::try_from(integer(inner.value)?)
+                                .map_err(|_| DecodeError::BadLength)?;
+                            self.path.push(number).map_err(|_| DecodeError::TooDeep)?;
+                        }
+                        (true, tag::RELATIVE_OID) => self.path = relative_oid(inner.value)?,
+                        _ => return Err(DecodeError::BadTag),
+                    }
+                }
+                // Contents
+                0xa1 if parameter && self.path == self.target => {
+                    let contents = field.inner(depth + 1)?;
+                    if contents.tag == tag::SET {
+                        self.contents(&contents, depth + 2)?;
+                    }
+                }
+                // Children
+                0xa2 => {
+                    let children = field.inner(depth + 1)?;
+                    if children.tag == tag::ELEMENT_COLLECTION {
+                        self.collection(&children, depth + 2)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn contents(&mut self, contents: &Tlv, depth: usize) -> Result<(), DecodeError> {
+        for field in contents.children(depth) {
+            let field = field?;
+            if field.tag == tag::context(2) {
+                let value = field.inner(depth + 1)?;
+                self.value = Some(match value.tag {
+                    tag::BOOLEAN => Value::Boolean(value.value.iter().any(|b| *b != 0)),
+                    tag::INTEGER => Value::Integer(integer(value.value)?),
+                    _ => Value::Other,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Append a TLV with a definite length.
+fn write_tlv<const N: usize>(out: &mut Vec<u8, N>, tag: u8, value: &[u8]) -> Result<(), ()> {
+    out.push(tag).map_err(drop)?;
+    match u8::try_from(value.len()) {
+        Ok(len) if len < 0x80 => out.push(len).map_err(drop)?,
+        Ok(len) => out.extend_from_slice(&[0x81, len])?,
+        Err(_) => {
+            let len = u16::try_from(value.len()).map_err(drop)?;
+            out.push(0x82).map_err(drop)?;
+            out.extend_from_slice(&len.to_be_bytes())?;
+        }
+    }
+    out.extend_from_slice(value)
+}
+
+/// Scratch space for building up a command, big enough for the longest path.
+type Buf = Vec<u8, { MAX_PATH * 5 + 64 }>;
+
+/// Ask the provider to run `command` on the element at `path`, a node if `node` is set and a
+/// parameter otherwise. An empty path means the root.
+// Like heapless, the only way this can fail is running out of room
+#[allow(clippy::result_unit_err)]
+pub fn request<const N: usize>(
+    out: &mut Vec<u8, N>,
+    path: &[u32],
+    node: bool,
+    command: u32,
+) -> Result<(), ()> {
+    let mut oid = Buf::new();
+    for n in path {
+        // Base 128, most significant group first, with the top bit set on all but the last
+        let groups = (32 - n.leading_zeros()).div_ceil(7).max(1);
+        for i in (0..groups).rev() {
+            let more = if i > 0 { 0x80 } else { 0 };
+            oid.push(((n >> (7 * i)) & 0x7f) as u8 | more)
+                .map_err(drop)?;
+        }
+    }
+    // The shortest encoding, keeping a zero byte if the top bit would otherwise be set
+    let bytes = command.to_be_bytes();
+    let skip = bytes
+        .windows(2)
+        .take_while(|w| w[0] == 0 && w[1] & 0x80 == 0)
+        .count();
+
+    // Built inside out, as each length depends on what's inside
+    let (mut a, mut b) = (Buf::new(), Buf::new());
+    write_tlv(&mut a, tag::INTEGER, &bytes[skip..])?;
+    write_tlv(&mut b, tag::context(0), &a)?;
+    let mut element = Buf::new();
+    write_tlv(&mut element, tag::COMMAND, &b)?;
+    b.clear();
+    write_tlv(&mut b, tag::context(0), &element)?;
+    a.clear();
+    write_tlv(&mut a, tag::ELEMENT_COLLECTION, &b)?;
+    let mut children = Buf::new();
+    write_tlv(&mut children, tag::context(2), &a)?;
+
+    b.clear();
+    if path.is_empty() {
+        // Commands on the root are sent bare
+        write_tlv(&mut b, tag::context(0), &element)?;
+    } else {
+        a.clear();
+        write_tlv(&mut a, tag::RELATIVE_OID, &oid)?;
+        let mut fields = Buf::new();
+        write_tlv(&mut fields, tag::context(0), &a)?;
+        fields.extend_from_slice(&children)?;
+        let element_tag = if node {
+            tag::QUALIFIED_NODE
+        } else {
+            tag::QUALIFIED_PARAMETER
+        };
+        element.clear();
+        write_tlv(&mut element, element_tag, &fields)?;
+        write_tlv(&mut b, tag::context(0), &element)?;
+    }
+    a.clear();
+    write_tlv(&mut a, tag::ROOT_ELEMENT_COLLECTION, &b)?;
+    write_tlv(out, tag::ROOT, &a)
+}
+
+/// Parse a dotted numeric path like `1.2.3`.
+pub fn parse_path(path: &str) -> Option<Vec<u32, MAX_PATH>> {
+    path.split('.').map(|n| n.trim().parse().ok()).try_fold(
+        Vec::new(),
+        |mut path: Vec<u32, MAX_PATH>, n| {
+            path.push(n?).ok()?;
+            Some(path)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A parameter update as a Lawo-style provider sends it: indefinite lengths throughout, and
+    /// the parameter nested under a qualified node.
+    const NESTED_UPDATE: &[u8] = &[
+        0x60, 0x80, // Root
+        0x6b, 0x80, // RootElementCollection
+        0xa0, 0x80, // [0]
+        0x6a, 0x80, // QualifiedNode
+        0xa0, 0x04, 0x0d, 0x02, 0x01, 0x02, // path 1.2
+        0xa2, 0x80, // children
+        0x64, 0x80, // ElementCollection
+        0xa0, 0x80, // [0]
+        0x61, 0x80, // Parameter
+        0xa0, 0x03, 0x02, 0x01, 0x03, // number 3
+        0xa1, 0x80, // contents
+        0x31, 0x80, // SET
+        0xa0, 0x07, 0x0c, 0x05, b't', b'a', b'l', b'l', b'y', // identifier
+        0xa2, 0x03, 0x01, 0x01, 0xff, // value true
+        0x00, 0x00, 0x00, 0x00, // SET, contents
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Parameter, [0], ElementCollection
+        0x00, 0x00, 0x00, 0x00, // children, QualifiedNode
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // [0], RootElementCollection, Root
+    ];
+
+    /// A qualified parameter with definite lengths and an integer value, alongside a node
+    /// without contents.
+    const QUALIFIED_UPDATE: &[u8] = &[
+        0x60, 0x26, // Root
+        0x6b, 0x24, // RootElementCollection
+        0xa0, 0x0b, // [0]
+        0x63, 0x09, // Node
+        0xa0, 0x03, 0x02, 0x01, 0x01, // number 1
+        0xa1, 0x02, 0x31, 0x00, // empty contents
+        0xa0, 0x15, // [0]
+        0x69, 0x13, // QualifiedParameter
+        0xa0, 0x06, 0x0d, 0x04, 0x01, 0x02, 0x81, 0x00, // path 1.2.128
+        0xa1, 0x09, // contents
+        0x31, 0x07, // SET
+        0xa2, 0x05, 0x02, 0x03, 0x00, 0x80, 0x02, // value 32770
+    ];
+
+    #[test]
+    fn test_nested() {
+        assert_eq!(
+            find_value(NESTED_UPDATE, &[1, 2, 3]),
+            Ok(Some(Value::Boolean(true)))
+        );
+        assert_eq!(find_value(NESTED_UPDATE, &[1, 2]), Ok(None));
+        assert_eq!(find_value(NESTED_UPDATE, &[1, 2, 4]), Ok(None));
+    }
+
+    #[test]
+    fn test_qualified() {
+        assert_eq!(
+            find_value(QUALIFIED_UPDATE, &[1, 2, 128]),
+            Ok(Some(Value::Integer(32770)))
+        );
+        assert_eq!(find_value(QUALIFIED_UPDATE, &[1]), Ok(None));
+    }
+
+    #[test]
+    fn test_truncated() {
+        for len in 0..NESTED_UPDATE.len() {
+            assert!(find_value(&NESTED_UPDATE[..len], &[1, 2, 3]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_integer() {
+        assert_eq!(integer(&[0xff]), Ok(-1));
+        assert_eq!(integer(&[0x00, 0xff]), Ok(255));
+        assert_eq!(integer(&[0xff, 0x7f]), Ok(-129));
+        assert!(integer(&[]).is_err());
+    }
+
+    #[test]
+    fn test_command() {
+        let mut out = Vec::<u8, 128>::new();
+        request(&mut out, &[1, 200], true, command::GET_DIRECTORY).unwrap();
+        assert_eq!(
+            out,
+            [
+                0x60, 0x1a, 0x6b, 0x18, 0xa0, 0x16, 0x6a, 0x14, 0xa0, 0x05, 0x0d, 0x03, 0x01, 0x81,
+                0x48, 0xa2, 0x0b, 0x64, 0x09, 0xa0, 0x07, 0x62, 0x05, 0xa0, 0x03, 0x02, 0x01, 0x20,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_root_command() {
+        let mut out = Vec::<u8, 128>::new();
+        request(&mut out, &[], true, command::GET_DIRECTORY).unwrap();
+        assert_eq!(
+            out,
+            [
+                0x60, 0x0b, 0x6b, 0x09, 0xa0, 0x07, 0x62, 0x05, 0xa0, 0x03, 0x02, 0x01, 0x20
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("1.2.3").unwrap(), [1, 2, 3]);
+        assert_eq!(parse_path("7").unwrap(), [7]);
+        assert_eq!(parse_path("1..2"), None);
+        assert_eq!(parse_path(""), None);
+    }
+}
@@ -0,0 +1,380 @@
+//! S101 framing, which carries Ember+ over TCP.
+//!
+//! Each frame is `BOF`, escaped contents and CRC, then `EOF`. Ember+ messages can be split
+//! over several frames, which [`Assembler`] puts back together.
+
+use heapless::Vec;
+
+const BOF: u8 = 0xfe;
+const EOF: u8 = 0xff;
+/// Escapes the byte after it, which is XORed with [`XOR`]
+const CE: u8 = 0xfd;
+const XOR: u8 = 0x20;
+/// Bytes from here up have to be escaped
+const INVALID: u8 = 0xf8;
+
+const SLOT: u8 = 0x00;
+const MESSAGE_EMBER: u8 = 0x0e;
+const VERSION: u8 = 0x01;
+/// Glow, the Ember+ data type
+const DTD_GLOW: u8 = 0x01;
+/// Glow DTD version 2.31, as (minor, major)
+const GLOW_VERSION: [u8; 2] = [0x1f, 0x02];
+
+mod command {
+    pub const EMBER: u8 = 0x00;
+    pub const KEEPALIVE_REQUEST: u8 = 0x01;
+    pub const KEEPALIVE_RESPONSE: u8 = 0x02;
+}
+
+pub mod flags {
+    pub const FIRST: u8 = 0x80;
+    pub const LAST: u8 = 0x40;
+    pub const EMPTY: u8 = 0x20;
+}
+
+fn crc_update(crc: u16, byte: u8) -> u16 {
+    (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+        if crc & 1 != 0 {
+            (crc >> 1) ^ 0x8408
+        } else {
+            crc >> 1
+        }
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameError {
+    /// The frame or message didn't fit our buffer
+    TooLong,
+    BadCrc,
+    TooShort,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame<'a> {
+    KeepaliveRequest,
+    KeepaliveResponse,
+    Ember { flags: u8, payload: &'a [u8] },
+    Other,
+}
+
+impl<'a> Frame<'a> {
+    /// Parse the unescaped contents of a frame, without its CRC.
+    fn parse(contents: &'a [u8]) -> Result<Self, FrameError> {
+        let [_slot, message, command, _version, rest @ ..] = contents else {
+            return Err(FrameError::TooShort);
+        };
+        if *message != MESSAGE_EMBER {
+            return Ok(Self::Other);
+        }
+        match *command {
+            command::KEEPALIVE_REQUEST => Ok(Self::KeepaliveRequest),
+            command::KEEPALIVE_RESPONSE => Ok(Self::KeepaliveResponse),
+            command::EMBER => {
+                let [flags, _dtd, app_len, rest @ ..] = rest else {
+                    return Err(FrameError::TooShort);
+                };
+                let payload = rest
+                    .get(usize::from(*app_len)..)
+                    .ok_or(FrameError::TooShort)?;
+                Ok(Self::Ember {
+                    flags: *flags,
+                    payload,
+                })
+            }
+            _ => Ok(Self::Other),
+        }
+    }
+}
+
+/// Splits a byte stream into frames.
+pub struct Decoder<const N: usize> {
+    buf: Vec<u8, N>,
+    in_frame: bool,
+    escape: bool,
+    overflow: bool,
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Decoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            in_frame: false,
+            escape: false,
+            overflow: false,
+        }
+    }
+
+    /// Feed the next byte, returning a frame if one is now complete.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Frame<'_>, FrameError>> {
+        match byte {
+            BOF => {
+                self.buf.clear();
+                self.in_frame = true;
+                self.escape = false;
+                self.overflow = false;
+            }
+            EOF if self.in_frame => {
+                self.in_frame = false;
+                if self.overflow {
+                    return Some(Err(FrameError::TooLong));
+                }
+                if self.buf.len() < 2 {
+                    return Some(Err(FrameError::TooShort));
+                }
+                // Running the CRC over the contents and the CRC itself leaves this residue
+                if self.buf.iter().fold(0xffff, |crc, b| crc_update(crc, *b)) != 0xf0b8 {
+                    return Some(Err(FrameError::BadCrc));
+                }
+                let contents = &self.buf[..self.buf.len() - 2];
+                return Some(Frame::parse(contents));
+            }
+            _ if !self.in_frame => {}
+            CE => self.escape = true,
+            _ => {
+                let byte = if self.escape { byte ^ XOR } else { byte };
+                self.escape = false;
+                if self.buf.push(byte).is_err() {
+                    self.overflow = true;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Puts Ember+ messages split over several frames back together.
+pub struct Assembler<const N: usize> {
+    buf: Vec<u8, N>,
+    /// Dropping the rest of a message that doesn't fit
+    overflow: bool,
+}
+
+impl<const N: usize> Default for Assembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Assembler<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// Add a frame's payload, returning the whole message once it's complete, or an error if
+    /// it didn't fit.
+    pub fn push(&mut self, frame_flags: u8, payload: &[u8]) -> Option<Result<&[u8], FrameError>> {
+        if frame_flags & flags::EMPTY != 0 {
+            return None;
+        }
+        if frame_flags & flags::FIRST != 0 {
+            self.buf.clear();
+            self.overflow = false;
+        }
+        if self.buf.extend_from_slice(payload).is_err() {
+            self.overflow = true;
+        }
+        if frame_flags & flags::LAST == 0 {
+            return None;
+        }
+        if core::mem::take(&mut self.overflow) {
+            Some(Err(FrameError::TooLong))
+        } else {
+            Some(Ok(&self.buf))
+        }
+    }
+}
+
+/// Write a frame with `contents` into `out`, returning its length, or `None` if it doesn't
+/// fit.
+fn encode(out: &mut [u8], contents: &[&[u8]]) -> Option<usize> {
+    let mut len = 0;
+    let mut put = |b: u8| {
+        *out.get_mut(len)? = b;
+        len += 1;
+        Some(())
+    };
+    put(BOF)?;
+    let mut crc = 0xffff;
+    for b in contents.iter().flat_map(|c| c.iter()).copied() {
+        crc = crc_update(crc, b);
+        escaped(&mut put, b)?;
+    }
+    for b in (!crc).to_le_bytes() {
+        escaped(&mut put, b)?;
+    }
+    put(EOF)?;
+    Some(len)
+}
+
+fn escaped(put: &mut impl FnMut(u8) -> Option<()>, b: u8) -> Option<()> {
+    if b >= INVALID {
+        put(CE)?;
+        put(b ^ XOR)
+    } else {
+        put(b)
+    }
+}
+
+/// Frame a whole Glow message as one packet.
+pub fn ember(out: &mut [u8], payload: &[u8]) -> Option<usize> {
+    let header = [
+        SLOT,
+        MESSAGE_EMBER,
+        command::EMBER,
+        VERSION,
+        flags::FIRST | flags::LAST,
+        DTD_GLOW,
+        GLOW_VERSION.len() as u8,
+        GLOW_VERSION[0],
+        GLOW_VERSION[1],
+    ];
+    encode(out, &[&header, payload])
+}
+
+pub fn keepalive_response(out: &mut [u8]) -> Option<usize> {
+    encode(
+        out,
+        &[&[SLOT, MESSAGE_EMBER, command::KEEPALIVE_RESPONSE, VERSION]],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CRC-CCITT as S101 uses it: reflected, starting from all ones and inverted at the end.
+    fn crc(data: &[u8]) -> u16 {
+        !data.iter().fold(0xffff, |crc, b| crc_update(crc, *b))
+    }
+
+    #[test]
+    fn test_crc() {
+        // The CRC-16/X-25 check value
+        assert_eq!(crc(b"123456789"), 0x906e);
+    }
+
+    #[test]
+    fn test_keepalive() {
+        let mut out = [0; 16];
+        let len = keepalive_response(&mut out).unwrap();
+        let mut decoder = Decoder::<16>::new();
+        let frames: Vec<_, 2> = out[..len]
+            .iter()
+            .filter_map(|b| {
+                decoder
+                    .push(*b)
+                    .map(|f| f.map(|f| f == Frame::KeepaliveResponse))
+            })
+            .collect();
+        assert_eq!(frames, [Ok(true)]);
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut out = [0; 32];
+        let len = ember(&mut out, &[0xfe, 0x01, 0xff]).unwrap();
+        assert!(!out[1..len - 1].iter().any(|b| *b == BOF || *b == EOF));
+        let mut decoder = Decoder::<32>::new();
+        let mut found = false;
+        for b in &out[..len] {
+            if let Some(frame) = decoder.push(*b) {
+                assert_eq!(
+                    frame,
+                    Ok(Frame::Ember {
+                        flags: flags::FIRST | flags::LAST,
+                        payload: &[0xfe, 0x01, 0xff]
+                    })
+                );
+                found = true;
+            }
+        }
+        assert!(found);
+    }
+
+    #[test]
+    fn test_bad_frames() {
+        let mut decoder = Decoder::<8>::new();
+        // Keepalive request with a broken CRC
+        let mut result = None;
+        for b in [BOF, 0x00, 0x0e, 0x01, 0x01, 0x12, 0x34, EOF] {
+            if let Some(Err(e)) = decoder.push(b) {
+                result = Some(e);
+            }
+        }
+        assert_eq!(result, Some(FrameError::BadCrc));
+        let mut result = None;
+        for b in [BOF, 1, 2, 3, 4, 5, 6, 7, 8, 9, EOF] {
+            if let Some(Err(e)) = decoder.push(b) {
+                result = Some(e);
+            }
+        }
+        assert_eq!(result, Some(FrameError::TooLong));
+    }
+
+    #[test]
+    fn test_assembler() {
+        let mut assembler = Assembler::<8>::new();
+        assert_eq!(assembler.push(flags::FIRST, &[1, 2]), None);
+        assert_eq!(assembler.push(0, &[3]), None);
+        assert_eq!(
+            assembler.push(flags::LAST, &[4]),
+            Some(Ok(&[1, 2, 3, 4][..]))
+        );
+        assert_eq!(assembler.push(flags::EMPTY, &[]), None);
+        assert_eq!(assembler.push(flags::FIRST, &[0; 6]), None);
+        assert_eq!(
+            assembler.push(flags::LAST, &[0; 6]),
+            Some(Err(FrameError::TooLong))
+        );
+        assert_eq!(
+            assembler.push(flags::FIRST | flags::LAST, &[5]),
+            Some(Ok(&[5][..]))
+        );
+    }
+
+    /// A keepalive request, then a boolean parameter update split over two packets, as a
+    /// provider sends them.
+    const CAPTURE: &[u8] = &[
+        0xfe, 0x00, 0x0e, 0x01, 0x01, 0x94, 0xe4, 0xff, 0xfe, 0x00, 0x0e, 0x00, 0x01, 0x80, 0x01,
+        0x02, 0x1f, 0x02, 0x60, 0x80, 0x6b, 0x80, 0xa0, 0x80, 0x6a, 0x80, 0xa0, 0x04, 0x0d, 0x02,
+        0x01, 0x02, 0xa2, 0x80, 0x64, 0x80, 0xa0, 0x80, 0xd4, 0xde, 0xff, 0xfe, 0x00, 0x0e, 0x00,
+        0x01, 0x40, 0x01, 0x02, 0x1f, 0x02, 0x61, 0x80, 0xa0, 0x03, 0x02, 0x01, 0x03, 0xa1, 0x80,
+        0x31, 0x80, 0xa0, 0x07, 0x0c, 0x05, 0x74, 0x61, 0x6c, 0x6c, 0x79, 0xa2, 0x03, 0x01, 0x01,
+        0xfd, 0xdf, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfd, 0xda, 0x58, 0xff,
+    ];
+
+    #[test]
+    fn test_capture() {
+        let mut decoder = Decoder::<64>::new();
+        let mut assembler = Assembler::<128>::new();
+        let mut keepalives = 0;
+        let mut values = Vec::<_, 2>::new();
+        for b in CAPTURE {
+            match decoder.push(*b) {
+                Some(Ok(Frame::KeepaliveRequest)) => keepalives += 1,
+                Some(Ok(Frame::Ember { flags, payload })) => {
+                    if let Some(Ok(message)) = assembler.push(flags, payload) {
+                        let value = crate::ember::glow::find_value(message, &[1, 2, 3]);
+                        values.push(value).unwrap();
+                    }
+                }
+                frame => assert!(frame.is_none()),
+            }
+        }
+        assert_eq!(keepalives, 1);
+        assert_eq!(values, [Ok(Some(crate::ember::glow::Value::Boolean(true)))]);
+    }
+}
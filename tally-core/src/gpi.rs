@@ -0,0 +1,243 @@
+//! Debouncing GPI (contact closure) inputs and working out what they add up to.
+
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::{delay::DelayNs, digital::Wait};
+use tally_rpc::rpc::{GpiConfig, GpiFunction};
+
+use crate::state::TallyState;
+
+/// One debounced input.
+pub struct Gpi<P> {
+    pin: P,
+    config: GpiConfig,
+    active: bool,
+}
+
+impl<P: InputPin + Wait> Gpi<P> {
+    pub fn new(mut pin: P, config: GpiConfig) -> Result<Self, P::Error> {
+        let active = pin.is_high()? != config.active_low;
+        Ok(Self {
+            pin,
+            config,
+            active,
+        })
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Wait for the input to change, returning whether it's now active. A new level only counts
+    /// if it's still there after the debounce time.
+    pub async fn changed(&mut self, delay: &mut impl DelayNs) -> Result<bool, P::Error> {
+        // The pin level we're waiting for
+        let high = self.active == self.config.active_low;
+        loop {
+            if high {
+                self.pin.wait_for_high().await?;
+            } else {
+                self.pin.wait_for_low().await?;
+            }
+            delay.delay_ms(u32::from(self.config.debounce_ms)).await;
+            if self.pin.is_high()? == high {
+                self.active = !self.active;
+                return Ok(self.active);
+            }
+        }
+    }
+}
+
+/// What a set of inputs add up to. Any active input with a function turns it on.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Levels {
+    /// `None` if no input is a program or preview input, so the tally is left alone
+    pub tally: Option<TallyState>,
+    pub talkback: bool,
+}
+
+impl Levels {
+    pub fn new(inputs: impl IntoIterator<Item = (GpiFunction, bool)>) -> Self {
+        let (mut program, mut preview) = (None, None);
+        let mut talkback = false;
+        for (function, active) in inputs {
+            match function {
+                GpiFunction::Program => program = Some(program.unwrap_or(false) | active),
+                GpiFunction::Preview => preview = Some(preview.unwrap_or(false) | active),
+                GpiFunction::Talkback => talkback |= active,
+            }
+        }
+        Self {
+            tally: (program.is_some() || preview.is_some())
+                .then(|| TallyState::new(program.unwrap_or(false), preview.unwrap_or(false))),
+            talkback,
+        }
+    }
+}
+
+pub fn levels<P>(gpis: &[Option<Gpi<P>>]) -> Levels {
+    Levels::new(
+        gpis.iter()
+            .flatten()
+            .map(|gpi| (gpi.config.function, gpi.active)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{cell::Cell, convert::Infallible};
+
+    use embassy_futures::block_on;
+    use embedded_hal::digital::ErrorType;
+
+    use super::*;
+
+    /// A pin following a script of `(time, level)` changes, on a clock the delay moves forward.
+    struct MockPin<'a> {
+        clock: &'a Cell<u32>,
+        script: &'a [(u32, bool)],
+    }
+
+    impl MockPin<'_> {
+        fn level(&self) -> bool {
+            self.script
+                .iter()
+                .rev()
+                .find(|(at, _)| *at <= self.clock.get())
+                .is_some_and(|(_, level)| *level)
+        }
+
+        fn wait_for(&mut self, level: bool) {
+            if self.level() != level {
+                let (at, _) = self
+                    .script
+                    .iter()
+                    .find(|(at, l)| *at > self.clock.get() && *l == level)
+                    .expect("waited forever");
+                self.clock.set(*at);
+            }
+        }
+    }
+
+    impl ErrorType for MockPin<'_> {
+        type Error = Infallible;
+    }
+
+    impl InputPin for MockPin<'_> {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.level())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.level())
+        }
+    }
+
+    impl Wait for MockPin<'_> {
+        async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+            self.wait_for(true);
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+            self.wait_for(false);
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+            self.wait_for(false);
+            self.wait_for(true);
+            Ok(())
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+            self.wait_for(true);
+            self.wait_for(false);
+            Ok(())
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+            self.wait_for(!self.level());
+            Ok(())
+        }
+    }
+
+    struct MockDelay<'a>(&'a Cell<u32>);
+
+    impl DelayNs for MockDelay<'_> {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0.set(self.0.get() + ns / 1_000_000);
+        }
+
+        async fn delay_ms(&mut self, ms: u32) {
+            self.0.set(self.0.get() + ms);
+        }
+    }
+
+    fn config(active_low: bool) -> GpiConfig {
+        GpiConfig {
+            active_low,
+            debounce_ms: 20,
+            function: GpiFunction::Program,
+        }
+    }
+
+    #[test]
+    fn test_debounce() {
+        let clock = Cell::new(0);
+        // Bounces on closing, then a glitch shorter than the debounce time, then opens
+        let script = [
+            (0, false),
+            (10, true),
+            (12, false),
+            (14, true),
+            (100, false),
+            (105, true),
+            (200, false),
+        ];
+        let pin = MockPin {
+            clock: &clock,
+            script: &script,
+        };
+        let mut gpi = Gpi::new(pin, config(false)).unwrap();
+        assert!(!gpi.active());
+        let mut delay = MockDelay(&clock);
+        assert_eq!(block_on(gpi.changed(&mut delay)), Ok(true));
+        assert_eq!(clock.get(), 30);
+        assert_eq!(block_on(gpi.changed(&mut delay)), Ok(false));
+        assert_eq!(clock.get(), 220);
+    }
+
+    #[test]
+    fn test_active_low() {
+        let clock = Cell::new(0);
+        let script = [(0, true), (50, false)];
+        let pin = MockPin {
+            clock: &clock,
+            script: &script,
+        };
+        let mut gpi = Gpi::new(pin, config(true)).unwrap();
+        assert!(!gpi.active());
+        assert_eq!(block_on(gpi.changed(&mut MockDelay(&clock))), Ok(true));
+        assert_eq!(clock.get(), 70);
+    }
+
+    #[test]
+    fn test_levels() {
+        use GpiFunction::*;
+        assert_eq!(
+            Levels::new([(Program, false), (Preview, true)]),
+            Levels {
+                tally: Some(TallyState::Preview),
+                talkback: false,
+            }
+        );
+        assert_eq!(
+            Levels::new([(Program, true), (Program, false), (Talkback, true)]),
+            Levels {
+                tally: Some(TallyState::Program),
+                talkback: true,
+            }
+        );
+        assert_eq!(Levels::new([(Talkback, false)]), Levels::default());
+    }
+}
@@ -0,0 +1,184 @@
+//! Just enough HTTP/1.1 to serve a JSON API and a static page: one request per connection,
+//! bodies sized by `Content-Length`.
+
+use core::fmt::{self, Write};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Method {
+    Get,
+    Put,
+    Post,
+    Other,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    /// The request line or a header is malformed
+    BadRequest,
+    /// Headers and body don't fit our buffer
+    TooLarge,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request<'a> {
+    pub method: Method,
+    /// Path, without any query string
+    pub path: &'a str,
+    /// Length of the request line and headers, including the blank line
+    pub header_len: usize,
+    pub content_length: usize,
+}
+
+impl<'a> Request<'a> {
+    /// Parse the request at the start of `buf`, which can hold at most `capacity` bytes.
+    /// Returns `None` until all of the headers have arrived.
+    pub fn parse(buf: &'a [u8], capacity: usize) -> Result<Option<Self>, ParseError> {
+        let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return if buf.len() >= capacity {
+                Err(ParseError::TooLarge)
+            } else {
+                Ok(None)
+            };
+        };
+        let head = core::str::from_utf8(&buf[..end]).map_err(|_| ParseError::BadRequest)?;
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap_or_default().split(' ');
+        let method = match request_line.next() {
+            Some("GET") => Method::Get,
+            Some("PUT") => Method::Put,
+            Some("POST") => Method::Post,
+            Some(_) => Method::Other,
+            None => return Err(ParseError::BadRequest),
+        };
+        let target = request_line.next().ok_or(ParseError::BadRequest)?;
+        let path = target.split_once('?').map_or(target, |(path, _)| path);
+        if !request_line
+            .next()
+            .is_some_and(|v| v.starts_with("HTTP/1."))
+        {
+            return Err(ParseError::BadRequest);
+        }
+        let mut content_length = 0;
+        for line in lines {
+            let (name, value) = line.split_once(':').ok_or(ParseError::BadRequest)?;
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| ParseError::BadRequest)?;
+            }
+        }
+        let header_len = end + 4;
+        if header_len.saturating_add(content_length) > capacity {
+            return Err(ParseError::TooLarge);
+        }
+        Ok(Some(Self {
+            method,
+            path,
+            header_len,
+            content_length,
+        }))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Status {
+    Ok,
+    NoContent,
+    BadRequest,
+    NotFound,
+    MethodNotAllowed,
+    PayloadTooLarge,
+    InternalServerError,
+}
+
+impl Status {
+    fn line(self) -> &'static str {
+        match self {
+            Self::Ok => "200 OK",
+            Self::NoContent => "204 No Content",
+            Self::BadRequest => "400 Bad Request",
+            Self::NotFound => "404 Not Found",
+            Self::MethodNotAllowed => "405 Method Not Allowed",
+            Self::PayloadTooLarge => "413 Payload Too Large",
+            Self::InternalServerError => "500 Internal Server Error",
+        }
+    }
+}
+
+/// Write the status line and headers of a response, after which the connection is closed.
+pub fn write_head(
+    out: &mut impl Write,
+    status: Status,
+    content_type: &str,
+    content_encoding: Option<&str>,
+    content_length: usize,
+) -> fmt::Result {
+    write!(
+        out,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\n",
+        status.line(),
+        content_type
+    )?;
+    if let Some(encoding) = content_encoding {
+        write!(out, "Content-Encoding: {}\r\n", encoding)?;
+    }
+    write!(
+        out,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        content_length
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::String;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let request =
+            b"PUT /api/config/tsl?x=1 HTTP/1.1\r\nHost: utally\r\ncontent-length: 2\r\n\r\n{}";
+        assert_eq!(Request::parse(&request[..20], 256), Ok(None));
+        assert_eq!(
+            Request::parse(request, 256),
+            Ok(Some(Request {
+                method: Method::Put,
+                path: "/api/config/tsl",
+                header_len: request.len() - 2,
+                content_length: 2,
+            }))
+        );
+        assert_eq!(Request::parse(request, 64), Err(ParseError::TooLarge));
+        assert_eq!(
+            Request::parse(&request[..20], 20),
+            Err(ParseError::TooLarge)
+        );
+        assert_eq!(
+            Request::parse(b"GET /\r\n\r\n", 256),
+            Err(ParseError::BadRequest)
+        );
+        assert_eq!(
+            Request::parse(b"GET / HTTP/1.1\r\nContent-Length: lots\r\n\r\n", 256),
+            Err(ParseError::BadRequest)
+        );
+    }
+
+    #[test]
+    fn test_write_head() {
+        let mut out = String::<128>::new();
+        write_head(&mut out, Status::NotFound, "application/json", None, 0).unwrap();
+        assert_eq!(
+            out,
+            "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\nContent-Length: 0\r\n\
+             Connection: close\r\n\r\n"
+        );
+        out.clear();
+        write_head(&mut out, Status::Ok, "text/html", Some("gzip"), 12).unwrap();
+        assert_eq!(
+            out,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Encoding: gzip\r\n\
+             Content-Length: 12\r\nConnection: close\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! The hardware-independent part of the firmware: protocol codecs and tally logic, kept apart
+//! so it builds and tests on the host with `cargo test`.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod arbitration;
+pub mod broadcast;
+pub mod gpi;
+pub mod mapping;
+pub mod mqtt;
+pub mod roland;
+pub mod state;
+pub mod tally;
+pub mod vmix;
+pub mod websocket;
+
+pub mod atem {
+    pub mod codec;
+}
+
+pub mod dmx {
+    pub mod codec;
+}
+
+pub mod ember {
+    pub mod glow;
+    pub mod s101;
+}
+
+pub mod http {
+    pub mod request;
+}
+
+pub mod obs {
+    pub mod protocol;
+}
+
+pub mod osc {
+    pub mod codec;
+}
+
+pub mod tally_arbiter {
+    pub mod protocol;
+}
+
+pub mod tricaster {
+    pub mod xml;
+}
@@ -0,0 +1,213 @@
+//! Mapping rules between the inputs protocols report and this device's tally, see
+//! [`TallyMapping`].
+//!
+//! Protocols the mapping mentions look up their selectors through [`Inputs`], and arbitration
+//! keeps the [`Matches`] for all of them, working out the tally under the mapping's source.
+
+use heapless::Vec;
+use tally_rpc::rpc::{Bus, Combine, MAX_SELECTORS, MappedProtocol, TallyMapping};
+
+use crate::state::TallyState;
+
+/// Which selectors have reported, and which of those match. Bit `n` is selector `n`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Matches {
+    pub known: u8,
+    pub on: u8,
+}
+
+impl Matches {
+    /// Take the selectors `other` knows about from it.
+    pub fn merge(&mut self, other: Matches) {
+        self.known |= other.known;
+        self.on = (self.on & !other.known) | (other.on & other.known);
+    }
+
+    /// Forget the selectors in `mask`, which haven't reported any more.
+    pub fn forget(&mut self, mask: u8) {
+        self.known &= !mask;
+        self.on &= !mask;
+    }
+
+    /// The tally `mapping` gives, or `None` until any of its selectors have reported. Selectors
+    /// that haven't reported don't match.
+    pub fn tally(&self, mapping: &TallyMapping) -> Option<TallyState> {
+        if self.known == 0 {
+            return None;
+        }
+        let on_bus = |bus| {
+            let (count, on) = mapping
+                .selectors
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.bus == bus)
+                .fold((0, 0), |(count, on), (i, _)| {
+                    (count + 1, on + usize::from(self.on & (1 << i) != 0))
+                });
+            match mapping.combine {
+                Combine::Or => on > 0,
+                Combine::And => count > 0 && on == count,
+            }
+        };
+        Some(TallyState::new(on_bus(Bus::Program), on_bus(Bus::Preview)))
+    }
+}
+
+/// One protocol's selectors in the mapping.
+pub struct Inputs {
+    /// Index in the mapping, input and bus of each selector
+    selectors: Vec<(u8, u16, Bus), MAX_SELECTORS>,
+    matches: Matches,
+}
+
+impl Inputs {
+    /// `None` when the mapping doesn't mention `protocol`, which then follows its own input.
+    pub fn new(mapping: Option<&TallyMapping>, protocol: MappedProtocol) -> Option<Self> {
+        let selectors: Vec<_, _> = mapping?
+            .selectors
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.protocol == protocol)
+            .map(|(i, s)| (i as u8, s.input, s.bus))
+            .collect();
+        (!selectors.is_empty()).then_some(Self {
+            selectors,
+            matches: Matches::default(),
+        })
+    }
+
+    /// Look up the selectors' inputs with `tally`, which gives `None` for inputs it doesn't
+    /// know about, returning the matches so far. They're worth reporting even when nothing
+    /// changed, as that shows the protocol is still there.
+    pub fn update(&mut self, tally: impl Fn(u16) -> Option<TallyState>) -> Matches {
+        let mut update = Matches::default();
+        for &(i, input, bus) in &self.selectors {
+            let Some(state) = tally(input) else {
+                continue;
+            };
+            let on = match bus {
+                Bus::Program => matches!(state, TallyState::Program | TallyState::ProgramPreview),
+                Bus::Preview => matches!(state, TallyState::Preview | TallyState::ProgramPreview),
+            };
+            update.known |= 1 << i;
+            update.on |= u8::from(on) << i;
+        }
+        self.matches.merge(update);
+        self.matches
+    }
+
+    /// The input of the first selector on `bus`.
+    pub fn first_input(&self, bus: Bus) -> Option<u16> {
+        self.selectors
+            .iter()
+            .find(|(_, _, b)| *b == bus)
+            .map(|(_, input, _)| *input)
+    }
+
+    /// Forget the selectors' inputs, e.g. when the protocol loses its signal, returning the mask
+    /// of the selectors.
+    pub fn forget(&mut self) -> u8 {
+        self.matches = Matches::default();
+        self.selectors
+            .iter()
+            .fold(0, |mask, (i, _, _)| mask | 1 << i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tally_rpc::rpc::Selector;
+
+    use super::*;
+
+    fn mapping(combine: Combine) -> TallyMapping {
+        let selector = |protocol, input, bus| Selector {
+            protocol,
+            input,
+            bus,
+        };
+        TallyMapping {
+            combine,
+            selectors: Vec::from_slice(&[
+                selector(MappedProtocol::Atem, 3, Bus::Program),
+                selector(MappedProtocol::Atem, 6000, Bus::Program),
+                selector(MappedProtocol::Tsl, 3, Bus::Preview),
+            ])
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_inputs() {
+        let mapping = mapping(Combine::Or);
+        assert!(Inputs::new(Some(&mapping), MappedProtocol::Vmix).is_none());
+        assert!(Inputs::new(None, MappedProtocol::Atem).is_none());
+        let mut inputs = Inputs::new(Some(&mapping), MappedProtocol::Atem).unwrap();
+        assert_eq!(inputs.first_input(Bus::Program), Some(3));
+        assert_eq!(inputs.first_input(Bus::Preview), None);
+        let tally = |input| match input {
+            3 => Some(TallyState::ProgramPreview),
+            4 => Some(TallyState::Program),
+            _ => None,
+        };
+        let matches = Matches {
+            known: 0b01,
+            on: 0b01,
+        };
+        assert_eq!(inputs.update(tally), matches);
+        // Nothing changed
+        assert_eq!(inputs.update(tally), matches);
+        let tally = |input| (input == 6000).then_some(TallyState::Off);
+        assert_eq!(
+            inputs.update(tally),
+            Matches {
+                known: 0b11,
+                on: 0b01
+            }
+        );
+        assert_eq!(inputs.forget(), 0b11);
+        // Only what's reported since
+        assert_eq!(inputs.update(tally), Matches { known: 0b10, on: 0 });
+    }
+
+    #[test]
+    fn test_or() {
+        let mapping = mapping(Combine::Or);
+        let mut matches = Matches::default();
+        assert_eq!(matches.tally(&mapping), None);
+        matches.merge(Matches {
+            known: 0b11,
+            on: 0b10,
+        });
+        assert_eq!(matches.tally(&mapping), Some(TallyState::Program));
+        matches.merge(Matches {
+            known: 0b100,
+            on: 0b100,
+        });
+        assert_eq!(matches.tally(&mapping), Some(TallyState::ProgramPreview));
+        matches.merge(Matches { known: 0b11, on: 0 });
+        assert_eq!(matches.tally(&mapping), Some(TallyState::Preview));
+    }
+
+    #[test]
+    fn test_and() {
+        let mapping = mapping(Combine::And);
+        let mut matches = Matches::default();
+        matches.merge(Matches {
+            known: 0b01,
+            on: 0b01,
+        });
+        // Selectors that haven't reported don't match
+        assert_eq!(matches.tally(&mapping), Some(TallyState::Off));
+        matches.merge(Matches {
+            known: 0b10,
+            on: 0b10,
+        });
+        assert_eq!(matches.tally(&mapping), Some(TallyState::Program));
+        matches.merge(Matches {
+            known: 0b100,
+            on: 0b100,
+        });
+        assert_eq!(matches.tally(&mapping), Some(TallyState::ProgramPreview));
+    }
+}
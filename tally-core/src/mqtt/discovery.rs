@@ -0,0 +1,193 @@
+//! Home Assistant MQTT discovery, so devices show up there without any YAML.
+//!
+//! See <https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery>. Topics are written
+//! into the JSON unescaped, so prefixes mustn't contain `"` or `\`.
+
+use core::fmt::{self, Write};
+
+use serde::Deserialize;
+use tally_rpc::rpc::Color;
+
+const DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// Identifies this device to Home Assistant.
+pub struct Device<'a> {
+    /// Unique to this device, used to build entity IDs
+    pub id: &'a str,
+    pub name: &'a str,
+    /// This device's MQTT topic prefix
+    pub prefix: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Entity {
+    /// Colour and brightness of the LEDs
+    Light,
+    /// The tally state being shown
+    Tally,
+    /// The button on GPIO0
+    Button,
+}
+
+impl Entity {
+    pub const ALL: [Self; 3] = [Self::Light, Self::Tally, Self::Button];
+
+    fn component(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Tally => "sensor",
+            Self::Button => "binary_sensor",
+        }
+    }
+
+    /// Also the suffix of the entity's state topic.
+    fn object_id(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Tally => "tally",
+            Self::Button => "button",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Light => "Light",
+            Self::Tally => "Tally",
+            Self::Button => "Button",
+        }
+    }
+
+    /// Write the topic the entity's config is published on.
+    pub fn config_topic(self, out: &mut impl Write, device: &Device) -> fmt::Result {
+        write!(
+            out,
+            "{}/{}/{}/{}/config",
+            DISCOVERY_PREFIX,
+            self.component(),
+            device.id,
+            self.object_id()
+        )
+    }
+
+    /// Write the entity's config.
+    pub fn config(self, out: &mut impl Write, device: &Device) -> fmt::Result {
+        let prefix = device.prefix;
+        write!(
+            out,
+            r#"{{"name":"{}","unique_id":"{}_{}","state_topic":"{}/{}","availability_topic":"{}/status","#,
+            self.name(),
+            device.id,
+            self.object_id(),
+            prefix,
+            self.object_id(),
+            prefix
+        )?;
+        match self {
+            Self::Light => write!(
+                out,
+                r#""schema":"json","command_topic":"{}/light/set","brightness":true,"supported_color_modes":["rgb"],"#,
+                prefix
+            )?,
+            Self::Tally => out.write_str(
+                r#""device_class":"enum","options":["off","preview","program","program_preview","lost_signal"],"#,
+            )?,
+            Self::Button => {}
+        }
+        write!(
+            out,
+            r#""device":{{"identifiers":["{}"],"name":"{}","manufacturer":"wlcx industries","model":"µTally","sw_version":"{}"}}}}"#,
+            device.id,
+            device.name,
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+}
+
+/// A command from Home Assistant for the light entity.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct LightCommand<'a> {
+    /// `ON` or `OFF`
+    pub state: &'a str,
+    pub brightness: Option<u8>,
+    pub color: Option<Color>,
+}
+
+impl<'a> LightCommand<'a> {
+    pub fn parse(json: &'a [u8]) -> Option<Self> {
+        serde_json_core::from_slice(json).ok().map(|(c, _)| c)
+    }
+}
+
+/// Write the light entity's state.
+pub fn light_state(out: &mut impl Write, on: bool, brightness: u8, color: Color) -> fmt::Result {
+    write!(
+        out,
+        r#"{{"state":"{}","brightness":{},"color_mode":"rgb","color":{{"r":{},"g":{},"b":{}}}}}"#,
+        if on { "ON" } else { "OFF" },
+        brightness,
+        color.r,
+        color.g,
+        color.b
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::String;
+
+    use super::*;
+
+    const DEVICE: Device = Device {
+        id: "utally-0a0b0c0d0e0f",
+        name: "µTally 0d0e0f",
+        prefix: "utally/cam1",
+    };
+
+    #[test]
+    fn test_config() {
+        let mut out = String::<64>::new();
+        Entity::Button.config_topic(&mut out, &DEVICE).unwrap();
+        assert_eq!(
+            out,
+            "homeassistant/binary_sensor/utally-0a0b0c0d0e0f/button/config"
+        );
+
+        let mut out = String::<512>::new();
+        Entity::Light.config(&mut out, &DEVICE).unwrap();
+        assert!(out.starts_with(
+            r#"{"name":"Light","unique_id":"utally-0a0b0c0d0e0f_light","state_topic":"utally/cam1/light","availability_topic":"utally/cam1/status","schema":"json","command_topic":"utally/cam1/light/set","#
+        ));
+        assert!(out.contains(
+            r#""device":{"identifiers":["utally-0a0b0c0d0e0f"],"name":"µTally 0d0e0f","#
+        ));
+    }
+
+    #[test]
+    fn test_light() {
+        assert_eq!(
+            LightCommand::parse(
+                br#"{"state":"ON","color":{"r":255,"g":0,"b":128},"brightness":64}"#
+            ),
+            Some(LightCommand {
+                state: "ON",
+                brightness: Some(64),
+                color: Some(Color::new(255, 0, 128))
+            })
+        );
+        assert_eq!(
+            LightCommand::parse(br#"{"state":"OFF"}"#),
+            Some(LightCommand {
+                state: "OFF",
+                brightness: None,
+                color: None
+            })
+        );
+
+        let mut out = String::<128>::new();
+        light_state(&mut out, true, 64, Color::new(255, 0, 128)).unwrap();
+        assert_eq!(
+            out,
+            r#"{"state":"ON","brightness":64,"color_mode":"rgb","color":{"r":255,"g":0,"b":128}}"#
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! MQTT packets, Home Assistant discovery and the payloads we take.
+
+use tally_rpc::rpc::Color;
+
+pub mod discovery;
+pub mod packet;
+
+/// Parse a colour override: `#rrggbb` to set one, or empty or `none` to clear it.
+pub fn parse_color(payload: &[u8]) -> Option<Option<Color>> {
+    if payload.is_empty() || payload == b"none" {
+        return Some(None);
+    }
+    let hex = payload.strip_prefix(b"#").unwrap_or(payload);
+    let hex = core::str::from_utf8(hex)
+        .ok()
+        .filter(|h| h.len() == 6 && h.bytes().all(|b| b.is_ascii_hexdigit()))?;
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(Some(Color::new(
+        (rgb >> 16) as u8,
+        (rgb >> 8) as u8,
+        rgb as u8,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color(b"#ff8000"), Some(Some(Color::new(255, 128, 0))));
+        assert_eq!(parse_color(b"0000ff"), Some(Some(Color::new(0, 0, 255))));
+        assert_eq!(parse_color(b""), Some(None));
+        assert_eq!(parse_color(b"none"), Some(None));
+        assert_eq!(parse_color(b"#fff"), None);
+        assert_eq!(parse_color(b"#gg0000"), None);
+    }
+}
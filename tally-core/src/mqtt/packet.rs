@@ -0,0 +1,324 @@
+//! MQTT 3.1.1 packets, just the ones a QoS 0 client needs.
+
+mod kind {
+    pub const CONNECT: u8 = 1;
+    pub const CONNACK: u8 = 2;
+    pub const PUBLISH: u8 = 3;
+    pub const SUBSCRIBE: u8 = 8;
+    pub const SUBACK: u8 = 9;
+    pub const PINGREQ: u8 = 12;
+    pub const PINGRESP: u8 = 13;
+    pub const DISCONNECT: u8 = 14;
+}
+
+pub const PINGREQ: [u8; 2] = [kind::PINGREQ << 4, 0];
+pub const DISCONNECT: [u8; 2] = [kind::DISCONNECT << 4, 0];
+
+/// Longest remaining length that fits in the 4 byte variable length encoding.
+const MAX_REMAINING: usize = 268_435_455;
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// The remaining length field is more than 4 bytes
+    BadLength,
+    /// Fields run past the end of the packet
+    TooShort,
+    /// A topic isn't valid UTF-8
+    BadTopic,
+}
+
+/// Writes into a fixed buffer, remembering if anything didn't fit.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+    overflow: bool,
+}
+
+impl<'a> Writer<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            overflow: false,
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        match self.buf.get_mut(self.pos..self.pos + bytes.len()) {
+            Some(out) => {
+                out.copy_from_slice(bytes);
+                self.pos += bytes.len();
+            }
+            None => self.overflow = true,
+        }
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    /// A length-prefixed string or binary field
+    fn field(&mut self, bytes: &[u8]) {
+        self.u16(bytes.len() as u16);
+        self.bytes(bytes);
+    }
+
+    fn remaining_length(&mut self, mut len: usize) {
+        loop {
+            let mut byte = (len % 128) as u8;
+            len /= 128;
+            if len > 0 {
+                byte |= 0x80;
+            }
+            self.bytes(&[byte]);
+            if len == 0 {
+                break;
+            }
+        }
+    }
+
+    /// Write a packet with a fixed header, given the length of everything `body` will write.
+    fn packet(&mut self, first: u8, len: usize, body: impl FnOnce(&mut Self)) -> Option<usize> {
+        self.bytes(&[first]);
+        self.remaining_length(len);
+        body(self);
+        (!self.overflow && len <= MAX_REMAINING).then_some(self.pos)
+    }
+}
+
+/// Sent by the broker on our behalf if we disappear without disconnecting.
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub retain: bool,
+}
+
+pub struct Connect<'a> {
+    pub client_id: &'a str,
+    /// Longest we'll go without sending anything, in seconds
+    pub keep_alive: u16,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a [u8]>,
+    pub will: Option<Will<'a>>,
+}
+
+impl Connect<'_> {
+    /// Encode into `out`, returning the length if it fits.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let mut flags = 0x02; // clean session
+        let mut len = 10 + 2 + self.client_id.len();
+        if let Some(will) = &self.will {
+            flags |= 0x04 | if will.retain { 0x20 } else { 0 };
+            len += 2 + will.topic.len() + 2 + will.payload.len();
+        }
+        if let Some(username) = self.username {
+            flags |= 0x80;
+            len += 2 + username.len();
+        }
+        if let Some(password) = self.password {
+            flags |= 0x40;
+            len += 2 + password.len();
+        }
+        Writer::new(out).packet(kind::CONNECT << 4, len, |w| {
+            w.field(b"MQTT");
+            w.bytes(&[4, flags]);
+            w.u16(self.keep_alive);
+            w.field(self.client_id.as_bytes());
+            if let Some(will) = &self.will {
+                w.field(will.topic.as_bytes());
+                w.field(will.payload);
+            }
+            if let Some(username) = self.username {
+                w.field(username.as_bytes());
+            }
+            if let Some(password) = self.password {
+                w.field(password);
+            }
+        })
+    }
+}
+
+/// Encode a QoS 0 publish into `out`, returning the length if it fits.
+pub fn publish(out: &mut [u8], topic: &str, payload: &[u8], retain: bool) -> Option<usize> {
+    Writer::new(out).packet(
+        kind::PUBLISH << 4 | u8::from(retain),
+        2 + topic.len() + payload.len(),
+        |w| {
+            w.field(topic.as_bytes());
+            w.bytes(payload);
+        },
+    )
+}
+
+/// Encode a subscription to `topics` at QoS 0 into `out`, returning the length if it fits.
+pub fn subscribe(out: &mut [u8], packet_id: u16, topics: &[&str]) -> Option<usize> {
+    let len = 2 + topics.iter().map(|t| 2 + t.len() + 1).sum::<usize>();
+    // The reserved flags have to be 0b0010
+    Writer::new(out).packet(kind::SUBSCRIBE << 4 | 0x02, len, |w| {
+        w.u16(packet_id);
+        for topic in topics {
+            w.field(topic.as_bytes());
+            w.bytes(&[0]);
+        }
+    })
+}
+
+/// The length of the packet at the start of `buf`, once enough of it has arrived to tell.
+pub fn packet_len(buf: &[u8]) -> Result<Option<usize>, DecodeError> {
+    let mut remaining = 0;
+    for i in 0..4 {
+        let Some(byte) = buf.get(1 + i) else {
+            return Ok(None);
+        };
+        remaining |= usize::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some(2 + i + remaining));
+        }
+    }
+    Err(DecodeError::BadLength)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Packet<'a> {
+    ConnAck {
+        /// 0 if the connection was accepted
+        return_code: u8,
+    },
+    Publish {
+        topic: &'a str,
+        payload: &'a [u8],
+    },
+    SubAck {
+        packet_id: u16,
+    },
+    PingResp,
+    /// Something a QoS 0 client doesn't expect
+    Other(u8),
+}
+
+impl<'a> Packet<'a> {
+    /// Decode a whole packet, as measured by [`packet_len`].
+    pub fn decode(packet: &'a [u8]) -> Result<Self, DecodeError> {
+        let first = *packet.first().ok_or(DecodeError::TooShort)?;
+        let header_len = 1
+            + packet[1..]
+                .iter()
+                .position(|b| b & 0x80 == 0)
+                .ok_or(DecodeError::TooShort)?
+            + 1;
+        let body = &packet[header_len..];
+        let u16_at = |i: usize| {
+            body.get(i..i + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or(DecodeError::TooShort)
+        };
+        Ok(match first >> 4 {
+            kind::CONNACK => Self::ConnAck {
+                return_code: *body.get(1).ok_or(DecodeError::TooShort)?,
+            },
+            kind::PUBLISH => {
+                let topic_len = usize::from(u16_at(0)?);
+                let topic = body.get(2..2 + topic_len).ok_or(DecodeError::TooShort)?;
+                let topic = core::str::from_utf8(topic).map_err(|_| DecodeError::BadTopic)?;
+                // QoS 1 and 2 messages have a packet ID
+                let qos = (first >> 1) & 0x03;
+                let payload_start = 2 + topic_len + if qos > 0 { 2 } else { 0 };
+                Self::Publish {
+                    topic,
+                    payload: body.get(payload_start..).ok_or(DecodeError::TooShort)?,
+                }
+            }
+            kind::SUBACK => Self::SubAck {
+                packet_id: u16_at(0)?,
+            },
+            kind::PINGRESP => Self::PingResp,
+            other => Self::Other(other),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect() {
+        let mut out = [0; 128];
+        let connect = Connect {
+            client_id: "utally",
+            keep_alive: 60,
+            username: Some("user"),
+            password: Some(b"pw"),
+            will: Some(Will {
+                topic: "t/status",
+                payload: b"offline",
+                retain: true,
+            }),
+        };
+        let len = connect.encode(&mut out).unwrap();
+        assert_eq!(
+            &out[..len],
+            b"\x10\x2f\x00\x04MQTT\x04\xe6\x00\x3c\x00\x06utally\x00\x08t/status\x00\x07offline\
+              \x00\x04user\x00\x02pw"
+        );
+        assert_eq!(connect.encode(&mut out[..len - 1]), None);
+    }
+
+    #[test]
+    fn test_publish_subscribe() {
+        let mut out = [0; 256];
+        let len = publish(&mut out, "a/b", b"on", true).unwrap();
+        assert_eq!(&out[..len], b"\x31\x07\x00\x03a/bon");
+
+        // Long enough for a two byte remaining length
+        let payload = [b'x'; 200];
+        let len = publish(&mut out, "a", &payload, false).unwrap();
+        assert_eq!(&out[..3], [0x30, 0xcb, 0x01]);
+        assert_eq!(packet_len(&out[..len]), Ok(Some(len)));
+
+        let len = subscribe(&mut out, 1, &["a/b", "c"]).unwrap();
+        assert_eq!(&out[..len], b"\x82\x0c\x00\x01\x00\x03a/b\x00\x00\x01c\x00");
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(packet_len(&[0x20]), Ok(None));
+        assert_eq!(packet_len(&[0x30, 0x80]), Ok(None));
+        assert_eq!(
+            packet_len(&[0x30, 0xff, 0xff, 0xff, 0xff, 0x01]),
+            Err(DecodeError::BadLength)
+        );
+
+        let connack = [0x20, 0x02, 0x00, 0x00];
+        assert_eq!(packet_len(&connack), Ok(Some(4)));
+        assert_eq!(
+            Packet::decode(&connack),
+            Ok(Packet::ConnAck { return_code: 0 })
+        );
+        assert_eq!(
+            Packet::decode(b"\x30\x10\x00\x07t/tallyprogram"),
+            Ok(Packet::Publish {
+                topic: "t/tally",
+                payload: b"program"
+            })
+        );
+        // QoS 1
+        assert_eq!(
+            Packet::decode(b"\x32\x0a\x00\x01t\x00\x07preview"),
+            Ok(Packet::Publish {
+                topic: "t",
+                payload: b"preview"
+            })
+        );
+        assert_eq!(
+            Packet::decode(b"\x30\x04\x00\x06t/"),
+            Err(DecodeError::TooShort)
+        );
+        assert_eq!(
+            Packet::decode(&[0x90, 0x03, 0x00, 0x01, 0x00]),
+            Ok(Packet::SubAck { packet_id: 1 })
+        );
+        assert_eq!(Packet::decode(&[0xd0, 0x00]), Ok(Packet::PingResp));
+    }
+}
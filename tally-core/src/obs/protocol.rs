@@ -0,0 +1,607 @@
+//! obs-websocket v5 messages, and following a scene or source's tally from them.
+//!
+//! Messages are parsed in place with `serde-json-core`, so nothing touches the heap. Strings
+//! are borrowed as-is, which means names containing JSON escapes won't match.
+
+use core::fmt::{self, Write};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use heapless::{String, Vec};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tally_rpc::rpc::ObsTarget;
+
+use crate::state::TallyState;
+
+const RPC_VERSION: u8 = 1;
+/// `EventSubscription::Scenes | EventSubscription::SceneItems`
+const EVENT_SUBSCRIPTIONS: u32 = (1 << 2) | (1 << 7);
+
+/// Longest scene name we keep track of.
+pub const MAX_NAME: usize = 64;
+
+mod op {
+    pub const HELLO: u8 = 0;
+    pub const IDENTIFY: u8 = 1;
+    pub const IDENTIFIED: u8 = 2;
+    pub const EVENT: u8 = 5;
+    pub const REQUEST: u8 = 6;
+    pub const REQUEST_RESPONSE: u8 = 7;
+}
+
+#[derive(Deserialize)]
+struct Op {
+    op: u8,
+}
+
+#[derive(Deserialize)]
+struct Envelope<T> {
+    d: T,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct Authentication<'a> {
+    pub challenge: &'a str,
+    pub salt: &'a str,
+}
+
+#[derive(Deserialize)]
+struct HelloData<'a> {
+    #[serde(borrow)]
+    authentication: Option<Authentication<'a>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventType<'a> {
+    event_type: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventData<T> {
+    event_data: T,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SceneChanged<'a> {
+    scene_name: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SceneItemEnableStateChanged<'a> {
+    scene_name: &'a str,
+    scene_item_id: u32,
+    scene_item_enabled: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestResponseData<'a> {
+    request_id: &'a str,
+    request_status: RequestStatus,
+    #[serde(borrow)]
+    response_data: Option<ResponseData<'a>>,
+}
+
+#[derive(Deserialize)]
+struct RequestStatus {
+    result: bool,
+}
+
+/// The union of the response data of the requests we make.
+#[derive(Deserialize, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseData<'a> {
+    #[serde(borrow)]
+    pub current_program_scene_name: Option<&'a str>,
+    #[serde(borrow)]
+    pub current_preview_scene_name: Option<&'a str>,
+    pub scene_item_id: Option<u32>,
+    pub scene_item_enabled: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Message<'a> {
+    Hello {
+        authentication: Option<Authentication<'a>>,
+    },
+    Identified,
+    CurrentProgramSceneChanged {
+        scene: &'a str,
+    },
+    CurrentPreviewSceneChanged {
+        scene: &'a str,
+    },
+    SceneItemEnableStateChanged {
+        scene: &'a str,
+        item_id: u32,
+        enabled: bool,
+    },
+    RequestResponse {
+        request_id: &'a str,
+        ok: bool,
+        data: ResponseData<'a>,
+    },
+    /// Something we don't care about
+    Other,
+}
+
+fn parse<'a, T: Deserialize<'a>>(json: &'a [u8]) -> serde_json_core::de::Result<T> {
+    serde_json_core::from_slice(json).map(|(t, _)| t)
+}
+
+impl<'a> Message<'a> {
+    pub fn parse(json: &'a [u8]) -> serde_json_core::de::Result<Self> {
+        Ok(match parse::<Op>(json)?.op {
+            op::HELLO => Self::Hello {
+                authentication: parse::<Envelope<HelloData>>(json)?.d.authentication,
+            },
+            op::IDENTIFIED => Self::Identified,
+            op::EVENT => match parse::<Envelope<EventType>>(json)?.d.event_type {
+                "CurrentProgramSceneChanged" => Self::CurrentProgramSceneChanged {
+                    scene: parse::<Envelope<EventData<SceneChanged>>>(json)?
+                        .d
+                        .event_data
+                        .scene_name,
+                },
+                "CurrentPreviewSceneChanged" => Self::CurrentPreviewSceneChanged {
+                    scene: parse::<Envelope<EventData<SceneChanged>>>(json)?
+                        .d
+                        .event_data
+                        .scene_name,
+                },
+                "SceneItemEnableStateChanged" => {
+                    let data = parse::<Envelope<EventData<SceneItemEnableStateChanged>>>(json)?
+                        .d
+                        .event_data;
+                    Self::SceneItemEnableStateChanged {
+                        scene: data.scene_name,
+                        item_id: data.scene_item_id,
+                        enabled: data.scene_item_enabled,
+                    }
+                }
+                _ => Self::Other,
+            },
+            op::REQUEST_RESPONSE => {
+                let d = parse::<Envelope<RequestResponseData>>(json)?.d;
+                Self::RequestResponse {
+                    request_id: d.request_id,
+                    ok: d.request_status.result,
+                    data: d.response_data.unwrap_or_default(),
+                }
+            }
+            _ => Self::Other,
+        })
+    }
+}
+
+/// base64(sha256(a + b))
+fn hash(a: &[u8], b: &[u8]) -> String<44> {
+    let digest = Sha256::new().chain_update(a).chain_update(b).finalize();
+    let mut out = [0; 44];
+    let len = BASE64.encode_slice(digest, &mut out).unwrap();
+    // base64 is always ASCII
+    String::try_from(core::str::from_utf8(&out[..len]).unwrap()).unwrap()
+}
+
+/// The `authentication` string to answer a hello's challenge with.
+pub fn auth_response(password: &str, auth: &Authentication) -> String<44> {
+    let secret = hash(password.as_bytes(), auth.salt.as_bytes());
+    hash(secret.as_bytes(), auth.challenge.as_bytes())
+}
+
+/// Write an identify message.
+pub fn identify<const N: usize>(out: &mut String<N>, authentication: Option<&str>) -> fmt::Result {
+    write!(
+        out,
+        r#"{{"op":{},"d":{{"rpcVersion":{},"eventSubscriptions":{}"#,
+        op::IDENTIFY,
+        RPC_VERSION,
+        EVENT_SUBSCRIPTIONS
+    )?;
+    if let Some(authentication) = authentication {
+        write!(out, r#","authentication":"{}""#, authentication)?;
+    }
+    out.write_str("}}")
+}
+
+/// A string, escaped for including in JSON.
+struct JsonStr<'a>(&'a str);
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bus {
+    Program,
+    Preview,
+}
+
+impl Bus {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Program => "program",
+            Self::Preview => "preview",
+        }
+    }
+}
+
+/// Named after the obs-websocket request types, which for what we need are all getters.
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Request<'a> {
+    GetCurrentProgramScene,
+    GetCurrentPreviewScene,
+    GetSceneItemId {
+        bus: Bus,
+        scene: &'a str,
+        source: &'a str,
+    },
+    GetSceneItemEnabled {
+        bus: Bus,
+        scene: &'a str,
+        item_id: u32,
+    },
+}
+
+impl Request<'_> {
+    /// Write the request message. The request ID says what the response is for.
+    pub fn write<const N: usize>(&self, out: &mut String<N>) -> fmt::Result {
+        write!(out, r#"{{"op":{},"d":{{"#, op::REQUEST)?;
+        match self {
+            Self::GetCurrentProgramScene => out.write_str(
+                r#""requestType":"GetCurrentProgramScene","requestId":"program-scene""#,
+            )?,
+            Self::GetCurrentPreviewScene => out.write_str(
+                r#""requestType":"GetCurrentPreviewScene","requestId":"preview-scene""#,
+            )?,
+            Self::GetSceneItemId { bus, scene, source } => write!(
+                out,
+                r#""requestType":"GetSceneItemId","requestId":"{}-item","requestData":{{"sceneName":"{}","sourceName":"{}"}}"#,
+                bus.name(),
+                JsonStr(scene),
+                JsonStr(source)
+            )?,
+            Self::GetSceneItemEnabled {
+                bus,
+                scene,
+                item_id,
+            } => write!(
+                out,
+                r#""requestType":"GetSceneItemEnabled","requestId":"{}-enabled","requestData":{{"sceneName":"{}","sceneItemId":{}}}"#,
+                bus.name(),
+                JsonStr(scene),
+                item_id
+            )?,
+        }
+        out.write_str("}}")
+    }
+}
+
+#[derive(Default)]
+struct BusState {
+    scene: String<MAX_NAME>,
+    /// Our source's ID in `scene`, if it's there
+    item_id: Option<u32>,
+    on: bool,
+}
+
+/// Follows the tally of a scene or source from obs-websocket messages.
+pub struct Follower {
+    target: ObsTarget,
+    program: BusState,
+    preview: BusState,
+    /// The tally last given by [`changed_tally`](Self::changed_tally)
+    reported: Option<TallyState>,
+}
+
+impl Follower {
+    pub fn new(target: ObsTarget) -> Self {
+        Self {
+            target,
+            program: BusState::default(),
+            preview: BusState::default(),
+            reported: None,
+        }
+    }
+
+    pub fn tally(&self) -> TallyState {
+        TallyState::new(self.program.on, self.preview.on)
+    }
+
+    /// The tally, if it's changed since the last call. The first call always gives it.
+    pub fn changed_tally(&mut self) -> Option<TallyState> {
+        let tally = self.tally();
+        (self.reported.replace(tally) != Some(tally)).then_some(tally)
+    }
+
+    fn bus(&mut self, bus: Bus) -> &mut BusState {
+        match bus {
+            Bus::Program => &mut self.program,
+            Bus::Preview => &mut self.preview,
+        }
+    }
+
+    /// Handle a message, returning any requests that need to be made as a result.
+    pub fn handle(&mut self, message: &Message) -> Vec<Request<'_>, 2> {
+        let mut requests = Vec::new();
+        match *message {
+            Message::Identified => {
+                requests.push(Request::GetCurrentProgramScene).unwrap();
+                requests.push(Request::GetCurrentPreviewScene).unwrap();
+            }
+            Message::CurrentProgramSceneChanged { scene } => {
+                requests.extend(self.scene_changed(Bus::Program, scene));
+            }
+            Message::CurrentPreviewSceneChanged { scene } => {
+                requests.extend(self.scene_changed(Bus::Preview, scene));
+            }
+            Message::SceneItemEnableStateChanged {
+                scene,
+                item_id,
+                enabled,
+            } => {
+                for bus in [&mut self.program, &mut self.preview] {
+                    if bus.scene == scene && bus.item_id == Some(item_id) {
+                        bus.on = enabled;
+                    }
+                }
+            }
+            Message::RequestResponse {
+                request_id,
+                ok,
+                ref data,
+            } => {
+                let Some((bus, what)) = request_id.split_once('-') else {
+                    return requests;
+                };
+                let bus = match bus {
+                    "program" => Bus::Program,
+                    "preview" => Bus::Preview,
+                    _ => return requests,
+                };
+                match what {
+                    "scene" => {
+                        let scene = match bus {
+                            Bus::Program => data.current_program_scene_name,
+                            Bus::Preview => data.current_preview_scene_name,
+                        };
+                        match scene {
+                            Some(scene) if ok => {
+                                requests.extend(self.scene_changed(bus, scene));
+                            }
+                            // No preview outside studio mode
+                            _ => self.bus(bus).on = false,
+                        }
+                    }
+                    "item" => {
+                        let state = match bus {
+                            Bus::Program => &mut self.program,
+                            Bus::Preview => &mut self.preview,
+                        };
+                        state.item_id = data.scene_item_id.filter(|_| ok);
+                        match state.item_id {
+                            Some(item_id) => requests
+                                .push(Request::GetSceneItemEnabled {
+                                    bus,
+                                    scene: &state.scene,
+                                    item_id,
+                                })
+                                .unwrap(),
+                            // Source isn't in the scene
+                            None => state.on = false,
+                        }
+                    }
+                    "enabled" => {
+                        if let (true, Some(enabled)) = (ok, data.scene_item_enabled) {
+                            self.bus(bus).on = enabled;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Message::Hello { .. } | Message::Other => {}
+        }
+        requests
+    }
+
+    /// Returns a request to find our source in the new scene, if we're following a source.
+    fn scene_changed(&mut self, bus: Bus, scene: &str) -> Option<Request<'_>> {
+        let state = match bus {
+            Bus::Program => &mut self.program,
+            Bus::Preview => &mut self.preview,
+        };
+        state.scene.clear();
+        // Names too long to store won't match anything
+        let _ = state.scene.push_str(scene);
+        state.item_id = None;
+        match &self.target {
+            ObsTarget::Scene(name) => {
+                state.on = name == scene;
+                None
+            }
+            // Keep showing the old state until we know
+            ObsTarget::Source(source) => Some(Request::GetSceneItemId {
+                bus,
+                scene: &state.scene,
+                source,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response<'a>(follower: &'a mut Follower, json: &str) -> Vec<Request<'a>, 2> {
+        follower.handle(&Message::parse(json.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_parse_hello() {
+        let json = br#"{"op":0,"d":{"obsWebSocketVersion":"5.1.0","rpcVersion":1,"authentication":{"challenge":"+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY=","salt":"lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI="}}}"#;
+        let Message::Hello {
+            authentication: Some(auth),
+        } = Message::parse(json).unwrap()
+        else {
+            panic!("expected hello with authentication");
+        };
+        assert_eq!(
+            auth_response("supersecretpassword", &auth),
+            "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4="
+        );
+
+        let json = br#"{"op":0,"d":{"obsWebSocketVersion":"5.1.0","rpcVersion":1}}"#;
+        assert_eq!(
+            Message::parse(json).unwrap(),
+            Message::Hello {
+                authentication: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_identify() {
+        let mut out = String::<128>::new();
+        identify(&mut out, Some("abc=")).unwrap();
+        assert_eq!(
+            out,
+            r#"{"op":1,"d":{"rpcVersion":1,"eventSubscriptions":132,"authentication":"abc="}}"#
+        );
+        out.clear();
+        identify(&mut out, None).unwrap();
+        assert_eq!(
+            out,
+            r#"{"op":1,"d":{"rpcVersion":1,"eventSubscriptions":132}}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let json = br#"{"op":5,"d":{"eventType":"CurrentProgramSceneChanged","eventIntent":4,"eventData":{"sceneName":"Wide","sceneUuid":"1234"}}}"#;
+        assert_eq!(
+            Message::parse(json).unwrap(),
+            Message::CurrentProgramSceneChanged { scene: "Wide" }
+        );
+        let json = br#"{"op":5,"d":{"eventType":"SceneItemEnableStateChanged","eventIntent":128,"eventData":{"sceneName":"Wide","sceneUuid":"1234","sceneItemId":3,"sceneItemEnabled":false}}}"#;
+        assert_eq!(
+            Message::parse(json).unwrap(),
+            Message::SceneItemEnableStateChanged {
+                scene: "Wide",
+                item_id: 3,
+                enabled: false
+            }
+        );
+        let json = br#"{"op":5,"d":{"eventType":"SceneNameChanged","eventIntent":4,"eventData":{"oldSceneName":"a","sceneName":"b"}}}"#;
+        assert_eq!(Message::parse(json).unwrap(), Message::Other);
+    }
+
+    #[test]
+    fn test_request() {
+        let mut out = String::<256>::new();
+        Request::GetSceneItemId {
+            bus: Bus::Preview,
+            scene: "Cam \"1\"",
+            source: "Camera",
+        }
+        .write(&mut out)
+        .unwrap();
+        assert_eq!(
+            out,
+            r#"{"op":6,"d":{"requestType":"GetSceneItemId","requestId":"preview-item","requestData":{"sceneName":"Cam \"1\"","sourceName":"Camera"}}}"#
+        );
+    }
+
+    #[test]
+    fn test_follow_scene() {
+        let mut follower = Follower::new(ObsTarget::Scene(String::try_from("Cam 1").unwrap()));
+        assert_eq!(
+            follower.handle(&Message::Identified),
+            [
+                Request::GetCurrentProgramScene,
+                Request::GetCurrentPreviewScene
+            ]
+        );
+        assert!(response(&mut follower, r#"{"op":7,"d":{"requestType":"GetCurrentProgramScene","requestId":"program-scene","requestStatus":{"result":true,"code":100},"responseData":{"currentProgramSceneName":"Cam 1","sceneName":"Cam 1"}}}"#).is_empty());
+        assert_eq!(follower.tally(), TallyState::Program);
+        assert_eq!(follower.changed_tally(), Some(TallyState::Program));
+        // Not in studio mode
+        assert!(response(&mut follower, r#"{"op":7,"d":{"requestType":"GetCurrentPreviewScene","requestId":"preview-scene","requestStatus":{"result":false,"code":506,"comment":"Studio mode is not active."}}}"#).is_empty());
+        assert_eq!(follower.tally(), TallyState::Program);
+        assert_eq!(follower.changed_tally(), None);
+
+        follower.handle(&Message::CurrentPreviewSceneChanged { scene: "Cam 1" });
+        assert_eq!(follower.tally(), TallyState::ProgramPreview);
+        follower.handle(&Message::CurrentProgramSceneChanged { scene: "Cam 2" });
+        assert_eq!(follower.tally(), TallyState::Preview);
+        assert_eq!(follower.changed_tally(), Some(TallyState::Preview));
+    }
+
+    #[test]
+    fn test_follow_source() {
+        let mut follower = Follower::new(ObsTarget::Source(String::try_from("Camera").unwrap()));
+        assert_eq!(
+            follower.handle(&Message::CurrentProgramSceneChanged { scene: "Wide" }),
+            [Request::GetSceneItemId {
+                bus: Bus::Program,
+                scene: "Wide",
+                source: "Camera"
+            }]
+        );
+        assert_eq!(
+            response(
+                &mut follower,
+                r#"{"op":7,"d":{"requestType":"GetSceneItemId","requestId":"program-item","requestStatus":{"result":true,"code":100},"responseData":{"sceneItemId":4}}}"#
+            ),
+            [Request::GetSceneItemEnabled {
+                bus: Bus::Program,
+                scene: "Wide",
+                item_id: 4
+            }]
+        );
+        assert!(response(&mut follower, r#"{"op":7,"d":{"requestType":"GetSceneItemEnabled","requestId":"program-enabled","requestStatus":{"result":true,"code":100},"responseData":{"sceneItemEnabled":true}}}"#).is_empty());
+        assert_eq!(follower.tally(), TallyState::Program);
+
+        // Hidden, then shown again
+        follower.handle(&Message::SceneItemEnableStateChanged {
+            scene: "Wide",
+            item_id: 4,
+            enabled: false,
+        });
+        assert_eq!(follower.tally(), TallyState::Off);
+        // Another item in the scene
+        follower.handle(&Message::SceneItemEnableStateChanged {
+            scene: "Wide",
+            item_id: 5,
+            enabled: true,
+        });
+        assert_eq!(follower.tally(), TallyState::Off);
+        follower.handle(&Message::SceneItemEnableStateChanged {
+            scene: "Wide",
+            item_id: 4,
+            enabled: true,
+        });
+        assert_eq!(follower.tally(), TallyState::Program);
+
+        // Switch to a scene without the source
+        follower.handle(&Message::CurrentProgramSceneChanged { scene: "Slides" });
+        assert!(response(&mut follower, r#"{"op":7,"d":{"requestType":"GetSceneItemId","requestId":"program-item","requestStatus":{"result":false,"code":600,"comment":"No scene items were found in the specified scene by that name or offset."}}}"#).is_empty());
+        assert_eq!(follower.tally(), TallyState::Off);
+    }
+}
@@ -0,0 +1,304 @@
+//! OSC 1.0 packets: messages, and bundles of them.
+//!
+//! Everything is bounds checked up front, since packets come from anyone on the network.
+
+/// How deep bundles can nest in bundles before we give up.
+const MAX_DEPTH: usize = 4;
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    TooShort,
+    /// A string isn't null terminated, or isn't UTF-8
+    BadString,
+    /// The packet is neither a message nor a bundle
+    BadPacket,
+    /// A type tag we don't know the size of
+    UnknownType(u8),
+    /// A bundle element's size doesn't fit the bundle
+    BadSize,
+    TooDeep,
+}
+
+/// Read a null terminated, padded string from the start of `buf`, returning it and the padded
+/// length.
+fn read_str(buf: &[u8]) -> Result<(&str, usize), DecodeError> {
+    let len = buf
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(DecodeError::BadString)?;
+    let s = core::str::from_utf8(&buf[..len]).map_err(|_| DecodeError::BadString)?;
+    Ok((s, padded(len + 1)))
+}
+
+fn padded(len: usize) -> usize {
+    len.next_multiple_of(4)
+}
+
+fn read_u32(buf: &[u8]) -> Result<u32, DecodeError> {
+    let bytes = buf.get(..4).ok_or(DecodeError::TooShort)?;
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Arg<'a> {
+    Int(i32),
+    Float(f32),
+    String(&'a str),
+    Blob(&'a [u8]),
+    True,
+    False,
+    Nil,
+    Impulse,
+    /// A 64-bit type we don't interpret
+    Other(u8),
+}
+
+impl Arg<'_> {
+    /// Numeric arguments as an integer, with floats from 0.0 to 1.0 mapped onto 0 to `max`.
+    pub fn scaled(&self, max: u8) -> Option<u8> {
+        match *self {
+            Arg::Int(i) => Some(i.clamp(0, max.into()) as u8),
+            Arg::Float(f) => Some((f.clamp(0.0, 1.0) * f32::from(max) + 0.5) as u8),
+            _ => None,
+        }
+    }
+}
+
+/// Decode one argument of type `tag` from the start of `buf`, returning it and its length.
+fn read_arg(tag: u8, buf: &[u8]) -> Result<(Arg<'_>, usize), DecodeError> {
+    Ok(match tag {
+        b'i' => (Arg::Int(read_u32(buf)? as i32), 4),
+        b'f' => (Arg::Float(f32::from_bits(read_u32(buf)?)), 4),
+        b's' | b'S' => {
+            let (s, len) = read_str(buf)?;
+            (Arg::String(s), len)
+        }
+        b'b' => {
+            let len = read_u32(buf)? as usize;
+            let blob = buf
+                .get(4..)
+                .and_then(|b| b.get(..len))
+                .ok_or(DecodeError::TooShort)?;
+            (Arg::Blob(blob), 4 + padded(len))
+        }
+        b'T' => (Arg::True, 0),
+        b'F' => (Arg::False, 0),
+        b'N' => (Arg::Nil, 0),
+        b'I' => (Arg::Impulse, 0),
+        b'h' | b't' | b'd' => {
+            buf.get(..8).ok_or(DecodeError::TooShort)?;
+            (Arg::Other(tag), 8)
+        }
+        b'c' | b'r' | b'm' => (Arg::Other(tag), 4),
+        other => return Err(DecodeError::UnknownType(other)),
+    })
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Message<'a> {
+    pub address: &'a str,
+    tags: &'a [u8],
+    args: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    pub fn decode(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        let (address, len) = read_str(buf)?;
+        if !address.starts_with('/') {
+            return Err(DecodeError::BadPacket);
+        }
+        let rest = buf.get(len..).ok_or(DecodeError::TooShort)?;
+        // The type tag string is optional in old implementations
+        let (tags, args) = match rest.first() {
+            Some(b',') => {
+                let (tags, len) = read_str(rest)?;
+                (
+                    &tags.as_bytes()[1..],
+                    rest.get(len..).ok_or(DecodeError::TooShort)?,
+                )
+            }
+            _ => (&[][..], rest),
+        };
+        let message = Self {
+            address,
+            tags,
+            args,
+        };
+        // Check all the arguments are there, so iterating over them can't fail
+        let mut pos = 0;
+        for tag in tags {
+            let (_, len) = read_arg(*tag, &args[pos..])?;
+            pos = (pos + len).min(args.len());
+        }
+        Ok(message)
+    }
+
+    pub fn args(&self) -> impl Iterator<Item = Arg<'a>> + use<'a> {
+        let mut pos = 0;
+        let args = self.args;
+        self.tags.iter().map_while(move |tag| {
+            let (arg, len) = read_arg(*tag, &args[pos..]).ok()?;
+            pos = (pos + len).min(args.len());
+            Some(arg)
+        })
+    }
+
+    /// The first argument, if there is one.
+    pub fn arg(&self) -> Option<Arg<'a>> {
+        self.args().next()
+    }
+}
+
+/// Call `f` for every message in `packet`, unpacking bundles. Time tags are ignored, and
+/// everything happens immediately.
+///
+/// Messages before a malformed part of a packet are still handled.
+pub fn for_each_message<'a>(
+    packet: &'a [u8],
+    f: &mut impl FnMut(Message<'a>),
+) -> Result<(), DecodeError> {
+    visit(packet, f, 0)
+}
+
+fn visit<'a>(
+    packet: &'a [u8],
+    f: &mut impl FnMut(Message<'a>),
+    depth: usize,
+) -> Result<(), DecodeError> {
+    if let Some(mut elements) = packet.strip_prefix(b"#bundle\0") {
+        if depth >= MAX_DEPTH {
+            return Err(DecodeError::TooDeep);
+        }
+        // Skip the time tag
+        elements = elements.get(8..).ok_or(DecodeError::TooShort)?;
+        while !elements.is_empty() {
+            let size = read_u32(elements)? as usize;
+            if !size.is_multiple_of(4) {
+                return Err(DecodeError::BadSize);
+            }
+            let element = elements
+                .get(4..)
+                .and_then(|e| e.get(..size))
+                .ok_or(DecodeError::BadSize)?;
+            visit(element, f, depth + 1)?;
+            elements = &elements[4 + size..];
+        }
+        Ok(())
+    } else {
+        f(Message::decode(packet)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::Vec;
+
+    use super::*;
+
+    fn messages(packet: &[u8]) -> (Vec<Message<'_>, 4>, Result<(), DecodeError>) {
+        let mut found = Vec::new();
+        let result = for_each_message(packet, &mut |m| found.push(m).unwrap());
+        (found, result)
+    }
+
+    #[test]
+    fn test_message() {
+        let packet = b"/tally/color\0\0\0\0,iif\0\0\0\0\0\0\0\xff\0\0\0\x80\x3f\x00\x00\x00";
+        let message = Message::decode(packet).unwrap();
+        assert_eq!(message.address, "/tally/color");
+        let args: Vec<Arg, 4> = message.args().collect();
+        assert_eq!(args, [Arg::Int(255), Arg::Int(128), Arg::Float(0.5)]);
+        assert_eq!(args[2].scaled(255), Some(128));
+        assert_eq!(Arg::Int(-1).scaled(255), Some(0));
+
+        let message = Message::decode(b"/tally/state\0\0\0\0,s\0\0program\0").unwrap();
+        assert_eq!(message.arg(), Some(Arg::String("program")));
+
+        let message = Message::decode(b"/identify\0\0\0").unwrap();
+        assert_eq!(message.arg(), None);
+    }
+
+    #[test]
+    fn test_malformed() {
+        // Missing argument data
+        assert_eq!(
+            Message::decode(b"/a\0\0,ii\0\0\0\0\x01"),
+            Err(DecodeError::TooShort)
+        );
+        // Unterminated address
+        assert_eq!(Message::decode(b"/tally"), Err(DecodeError::BadString));
+        assert_eq!(Message::decode(b"tally\0\0\0"), Err(DecodeError::BadPacket));
+        assert_eq!(
+            Message::decode(b"/a\0\0,x\0\0"),
+            Err(DecodeError::UnknownType(b'x'))
+        );
+        // Blob longer than the packet
+        assert_eq!(
+            Message::decode(b"/a\0\0,b\0\0\x7f\xff\xff\xff\0\0"),
+            Err(DecodeError::TooShort)
+        );
+        assert_eq!(messages(b"").1, Err(DecodeError::BadString));
+    }
+
+    #[test]
+    fn test_bundle() {
+        let mut inner = Vec::<u8, 64>::new();
+        inner
+            .extend_from_slice(b"#bundle\0\0\0\0\0\0\0\0\x01")
+            .unwrap();
+        inner
+            .extend_from_slice(b"\0\0\0\x0c/identify\0\0\0")
+            .unwrap();
+
+        let mut packet = Vec::<u8, 128>::new();
+        packet
+            .extend_from_slice(b"#bundle\0\0\0\0\0\0\0\0\x01")
+            .unwrap();
+        packet
+            .extend_from_slice(b"\0\0\0\x18/tally/state\0\0\0\0,i\0\0\0\0\0\x02")
+            .unwrap();
+        packet
+            .extend_from_slice(&(inner.len() as u32).to_be_bytes())
+            .unwrap();
+        packet.extend_from_slice(&inner).unwrap();
+
+        let (found, result) = messages(&packet);
+        assert_eq!(result, Ok(()));
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].address, "/tally/state");
+        assert_eq!(found[0].arg(), Some(Arg::Int(2)));
+        assert_eq!(found[1].address, "/identify");
+
+        // Element size past the end
+        let mut bad = packet.clone();
+        bad[19] = 0x40;
+        assert_eq!(messages(&bad), (Vec::new(), Err(DecodeError::BadSize)));
+        // Truncated after the first element
+        let (found, result) = messages(&packet[..packet.len() - 4]);
+        assert_eq!(found.len(), 1);
+        assert_eq!(result, Err(DecodeError::BadSize));
+        // Truncated anywhere, which mustn't panic
+        for len in 0..packet.len() {
+            let _ = messages(&packet[..len]);
+        }
+    }
+
+    #[test]
+    fn test_deep_bundle() {
+        // A bundle containing itself, as far as the buffer goes
+        let mut packet = Vec::<u8, 160>::new();
+        for i in 0..6 {
+            packet
+                .extend_from_slice(b"#bundle\0\0\0\0\0\0\0\0\x01")
+                .unwrap();
+            let remaining = (5 - i) * 20 + 12;
+            packet
+                .extend_from_slice(&(remaining as u32).to_be_bytes())
+                .unwrap();
+        }
+        packet.extend_from_slice(b"/identify\0\0\0").unwrap();
+        assert_eq!(messages(&packet).1, Err(DecodeError::TooDeep));
+    }
+}
@@ -0,0 +1,46 @@
+//! Roland Smart Tally responses.
+
+use crate::state::TallyState;
+
+/// Map a Smart Tally status body onto a tally state.
+fn parse_status(body: &[u8]) -> Option<TallyState> {
+    match body.trim_ascii() {
+        b"onair" => Some(TallyState::Program),
+        b"selected" => Some(TallyState::Preview),
+        b"unselected" => Some(TallyState::Off),
+        _ => None,
+    }
+}
+
+/// Pull the tally out of a whole HTTP response.
+pub fn parse_response(response: &[u8]) -> Option<TallyState> {
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let mut status_line = response[..end].split(|b| *b == b' ');
+    if !status_line.next()?.starts_with(b"HTTP/1.") || status_line.next()? != b"200" {
+        return None;
+    }
+    parse_status(&response[end + 4..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nonair\r\n";
+        assert_eq!(parse_response(response), Some(TallyState::Program));
+        assert_eq!(
+            parse_response(b"HTTP/1.0 200 OK\r\n\r\nselected"),
+            Some(TallyState::Preview)
+        );
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\n\r\nunselected"),
+            Some(TallyState::Off)
+        );
+        assert_eq!(parse_response(b"HTTP/1.1 404 Not Found\r\n\r\nonair"), None);
+        assert_eq!(parse_response(b"HTTP/1.1 200 OK\r\n\r\nsomething"), None);
+        // Still waiting for the headers to finish
+        assert_eq!(parse_response(b"HTTP/1.1 200 OK\r\n"), None);
+    }
+}
@@ -0,0 +1,87 @@
+use tally_rpc::rpc::Tally;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TallyState {
+    #[default]
+    Off,
+    Preview,
+    Program,
+    ProgramPreview,
+    /// The source that was reporting the tally has gone away
+    LostSignal,
+}
+
+impl TallyState {
+    pub fn new(program: bool, preview: bool) -> Self {
+        match (program, preview) {
+            (false, false) => Self::Off,
+            (false, true) => Self::Preview,
+            (true, false) => Self::Program,
+            (true, true) => Self::ProgramPreview,
+        }
+    }
+
+    /// The name text-based protocols use for the state.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Preview => "preview",
+            Self::Program => "program",
+            Self::ProgramPreview => "program_preview",
+            Self::LostSignal => "lost_signal",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::Off,
+            Self::Preview,
+            Self::Program,
+            Self::ProgramPreview,
+            Self::LostSignal,
+        ]
+        .into_iter()
+        .find(|t| t.name() == name)
+    }
+}
+
+impl From<Tally> for TallyState {
+    fn from(tally: Tally) -> Self {
+        match tally {
+            Tally::Off => Self::Off,
+            Tally::Preview => Self::Preview,
+            Tally::Program => Self::Program,
+            Tally::ProgramPreview => Self::ProgramPreview,
+            Tally::LostSignal => Self::LostSignal,
+        }
+    }
+}
+
+impl From<TallyState> for Tally {
+    fn from(state: TallyState) -> Self {
+        match state {
+            TallyState::Off => Self::Off,
+            TallyState::Preview => Self::Preview,
+            TallyState::Program => Self::Program,
+            TallyState::ProgramPreview => Self::ProgramPreview,
+            TallyState::LostSignal => Self::LostSignal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tally_names() {
+        assert_eq!(TallyState::from_name("program"), Some(TallyState::Program));
+        assert_eq!(
+            TallyState::from_name("program_preview"),
+            Some(TallyState::ProgramPreview)
+        );
+        assert_eq!(TallyState::from_name("Program"), None);
+        assert_eq!(TallyState::Preview.name(), "preview");
+    }
+}
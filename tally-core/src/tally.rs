@@ -0,0 +1,724 @@
+use bondrewd::Bitfields;
+use heapless::Vec;
+use tally_rpc::rpc::TslColourTally;
+
+use crate::state::TallyState;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// Not enough bytes for a whole message
+    TooShort,
+    /// The header bit of the address byte wasn't set
+    BadHeader,
+    /// A v4.0 message's checksum didn't match its contents
+    BadChecksum,
+}
+
+/// A single TSL UMD v3.1 display message.
+///
+/// On the wire this is an address byte (with the top bit set), a control byte carrying the four
+/// tally bits and brightness, and 16 bytes of ASCII display text.
+#[derive(Bitfields, Clone, Debug, PartialEq, Eq)]
+#[bondrewd(enforce_bytes = 18)]
+pub struct TSL31Message {
+    #[bondrewd(bit_length = 1)]
+    __: bool,
+    #[bondrewd(bit_length = 7)]
+    address: u8,
+    #[bondrewd(bit_length = 2)]
+    ___: u8,
+    #[bondrewd(bit_length = 2)]
+    brightness: u8,
+    tally_4: bool,
+    tally_3: bool,
+    tally_2: bool,
+    tally_1: bool,
+    #[bondrewd(endianness = "be")]
+    data: [u8; 16],
+}
+
+impl TSL31Message {
+    pub const LEN: usize = 18;
+
+    /// Decode a message from the start of `buf`.
+    pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        let bytes: [u8; Self::LEN] = buf
+            .get(..Self::LEN)
+            .ok_or(DecodeError::TooShort)?
+            .try_into()
+            .unwrap();
+        if bytes[0] & 0x80 == 0 {
+            return Err(DecodeError::BadHeader);
+        }
+        Ok(Self::from_bytes(bytes))
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Display text, with trailing padding removed.
+    pub fn text(&self) -> &str {
+        core::str::from_utf8(&self.data)
+            .unwrap_or_default()
+            .trim_end_matches([' ', '\0'])
+    }
+
+    /// By convention tally 1 is program (red) and tally 2 is preview (green).
+    pub fn tally(&self) -> TallyState {
+        TallyState::new(self.tally_1, self.tally_2)
+    }
+}
+
+/// A TSL UMD v4.0 colour tally value.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TallyColour {
+    Off,
+    Red,
+    Green,
+    Amber,
+}
+
+impl From<u8> for TallyColour {
+    fn from(value: u8) -> Self {
+        match value & 0b11 {
+            0 => Self::Off,
+            1 => Self::Red,
+            2 => Self::Green,
+            _ => Self::Amber,
+        }
+    }
+}
+
+impl From<TallyColour> for TallyState {
+    fn from(value: TallyColour) -> Self {
+        match value {
+            TallyColour::Off => Self::Off,
+            TallyColour::Red => Self::Program,
+            TallyColour::Green => Self::Preview,
+            TallyColour::Amber => Self::ProgramPreview,
+        }
+    }
+}
+
+/// One display's worth of v4.0 colour tally XDATA.
+#[derive(Bitfields, Clone, Debug, PartialEq, Eq, Default)]
+#[bondrewd(enforce_bytes = 1)]
+pub struct TSL40XByte {
+    #[bondrewd(bit_length = 2)]
+    __: u8,
+    #[bondrewd(bit_length = 2)]
+    left: u8,
+    #[bondrewd(bit_length = 2)]
+    text: u8,
+    #[bondrewd(bit_length = 2)]
+    right: u8,
+}
+
+impl TSL40XByte {
+    pub fn colour(&self, which: TslColourTally) -> TallyColour {
+        match which {
+            TslColourTally::Left => self.left,
+            TslColourTally::Text => self.text,
+            TslColourTally::Right => self.right,
+        }
+        .into()
+    }
+}
+
+/// A TSL UMD v4.0 message: a v3.1 message followed by a checksum, a version/byte count (VBC)
+/// byte and colour tally XDATA for the left and right displays.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TSL40Message {
+    pub base: TSL31Message,
+    pub minor_version: u8,
+    pub display_left: TSL40XByte,
+    pub display_right: TSL40XByte,
+}
+
+impl TSL40Message {
+    /// Decode a message from the start of `buf`, returning it along with the number of bytes
+    /// it occupied.
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let base = TSL31Message::decode(buf)?;
+        let (checksum, vbc) = match buf.get(TSL31Message::LEN..TSL31Message::LEN + 2) {
+            Some(&[checksum, vbc]) => (checksum, vbc),
+            _ => return Err(DecodeError::TooShort),
+        };
+        if checksum != tsl40_checksum(&buf[..TSL31Message::LEN]) {
+            return Err(DecodeError::BadChecksum);
+        }
+        let len = TSL31Message::LEN + 2 + usize::from(vbc & 0x0f);
+        let xdata = buf
+            .get(TSL31Message::LEN + 2..len)
+            .ok_or(DecodeError::TooShort)?;
+        let xbyte = |i: usize| {
+            xdata
+                .get(i)
+                .map(|b| TSL40XByte::from_bytes([*b]))
+                .unwrap_or_default()
+        };
+        Ok((
+            Self {
+                base,
+                minor_version: (vbc >> 4) & 0b111,
+                display_left: xbyte(0),
+                display_right: xbyte(1),
+            },
+            len,
+        ))
+    }
+
+    /// The tally state given by the chosen colour tally of the left display.
+    pub fn tally(&self, which: TslColourTally) -> TallyState {
+        self.display_left.colour(which).into()
+    }
+}
+
+/// 2's complement of the modulo 128 sum of `bytes`.
+fn tsl40_checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |sum, b| sum.wrapping_add(*b))
+        .wrapping_neg()
+        & 0x7f
+}
+
+/// A TSL UMD message of either version.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TSLMessage {
+    V31(TSL31Message),
+    V40(TSL40Message),
+}
+
+impl TSLMessage {
+    /// Decode a message from the start of `buf`, returning it along with the number of bytes
+    /// it occupied.
+    ///
+    /// Versions are told apart by the byte following the v3.1 portion: a v4.0 checksum always
+    /// has its top bit clear, whereas the address byte of a following v3.1 message has it set.
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+        match buf.get(TSL31Message::LEN) {
+            Some(b) if b & 0x80 == 0 => {
+                TSL40Message::decode(buf).map(|(msg, len)| (Self::V40(msg), len))
+            }
+            _ => TSL31Message::decode(buf).map(|msg| (Self::V31(msg), TSL31Message::LEN)),
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        match self {
+            Self::V31(msg) => msg.address(),
+            Self::V40(msg) => msg.base.address(),
+        }
+    }
+
+    pub fn tally(&self, colour_tally: TslColourTally) -> TallyState {
+        match self {
+            Self::V31(msg) => msg.tally(),
+            Self::V40(msg) => msg.tally(colour_tally),
+        }
+    }
+}
+
+/// Screen or display index meaning "all".
+pub const TSL5_BROADCAST: u16 = 0xffff;
+
+const TSL5_DLE: u8 = 0xfe;
+const TSL5_STX: u8 = 0x02;
+
+/// A TSL UMD v5.0 packet.
+///
+/// Each packet is addressed to a screen and carries one or more display messages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TSL5Packet<'a> {
+    pub version: u8,
+    pub screen: u16,
+    utf16: bool,
+    screen_control: bool,
+    messages: &'a [u8],
+}
+
+impl<'a> TSL5Packet<'a> {
+    /// Decode a single packet, without any DLE/STX framing.
+    pub fn decode(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        let pbc = match buf.get(..2) {
+            Some(&[lo, hi]) => usize::from(u16::from_le_bytes([lo, hi])),
+            _ => return Err(DecodeError::TooShort),
+        };
+        let packet = buf.get(2..2 + pbc).ok_or(DecodeError::TooShort)?;
+        let &[version, flags, screen_lo, screen_hi, ref messages @ ..] = packet else {
+            return Err(DecodeError::TooShort);
+        };
+        Ok(Self {
+            version,
+            screen: u16::from_le_bytes([screen_lo, screen_hi]),
+            utf16: flags & 0b01 != 0,
+            screen_control: flags & 0b10 != 0,
+            messages,
+        })
+    }
+
+    /// The display messages in this packet.
+    ///
+    /// Iteration stops after the first malformed message.
+    pub fn messages(&self) -> TSL5DisplayMessages<'a> {
+        TSL5DisplayMessages {
+            // Screen control packets don't carry display messages
+            rest: if self.screen_control {
+                &[]
+            } else {
+                self.messages
+            },
+            utf16: self.utf16,
+        }
+    }
+}
+
+pub struct TSL5DisplayMessages<'a> {
+    rest: &'a [u8],
+    utf16: bool,
+}
+
+impl<'a> Iterator for TSL5DisplayMessages<'a> {
+    type Item = Result<TSL5DisplayMessage<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+            let &[
+                index_lo,
+                index_hi,
+                control_lo,
+                control_hi,
+                len_lo,
+                len_hi,
+                ref rest @ ..,
+            ] = self.rest
+            else {
+                self.rest = &[];
+                return Some(Err(DecodeError::TooShort));
+            };
+            let len = usize::from(u16::from_le_bytes([len_lo, len_hi]));
+            if rest.len() < len {
+                self.rest = &[];
+                return Some(Err(DecodeError::TooShort));
+            }
+            let (data, rest) = rest.split_at(len);
+            self.rest = rest;
+            let control = u16::from_le_bytes([control_lo, control_hi]);
+            if control & 0x8000 != 0 {
+                // Control data rather than display data, which the spec doesn't define yet.
+                continue;
+            }
+            return Some(Ok(TSL5DisplayMessage {
+                index: u16::from_le_bytes([index_lo, index_hi]),
+                right: (control as u8).into(),
+                text_tally: ((control >> 2) as u8).into(),
+                left: ((control >> 4) as u8).into(),
+                brightness: ((control >> 6) & 0b11) as u8,
+                text: if self.utf16 {
+                    TSL5Text::Utf16(data)
+                } else {
+                    TSL5Text::Ascii(data)
+                },
+            }));
+        }
+    }
+}
+
+/// A single TSL UMD v5.0 display message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TSL5DisplayMessage<'a> {
+    pub index: u16,
+    pub left: TallyColour,
+    pub text_tally: TallyColour,
+    pub right: TallyColour,
+    /// 0 (off) to 3 (full)
+    pub brightness: u8,
+    pub text: TSL5Text<'a>,
+}
+
+impl TSL5DisplayMessage<'_> {
+    pub fn colour(&self, which: TslColourTally) -> TallyColour {
+        match which {
+            TslColourTally::Left => self.left,
+            TslColourTally::Text => self.text_tally,
+            TslColourTally::Right => self.right,
+        }
+    }
+
+    pub fn tally(&self, which: TslColourTally) -> TallyState {
+        self.colour(which).into()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TSL5Text<'a> {
+    Ascii(&'a [u8]),
+    Utf16(&'a [u8]),
+}
+
+impl<'a> TSL5Text<'a> {
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        let (ascii, utf16) = match *self {
+            Self::Ascii(bytes) => (bytes, &[][..]),
+            Self::Utf16(bytes) => (&[][..], bytes),
+        };
+        ascii.iter().map(|b| char::from(*b)).chain(
+            char::decode_utf16(
+                utf16
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]])),
+            )
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+        )
+    }
+}
+
+/// Reassembles TSL UMD v5.0 packets from a DLE/STX framed TCP stream.
+///
+/// Each packet is preceded by DLE/STX, and any DLE within the packet is doubled.
+pub struct TSL5Deframer<const N: usize> {
+    buf: Vec<u8, N>,
+    in_packet: bool,
+    escape: bool,
+}
+
+impl<const N: usize> Default for TSL5Deframer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TSL5Deframer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            in_packet: false,
+            escape: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.in_packet = false;
+        self.escape = false;
+    }
+
+    /// Feed the next byte from the stream, returning a packet if it is now complete.
+    ///
+    /// Packets too big for the buffer are dropped.
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        if self.escape {
+            self.escape = false;
+            match byte {
+                TSL5_STX => {
+                    self.buf.clear();
+                    self.in_packet = true;
+                    return None;
+                }
+                TSL5_DLE => {}
+                _ => {
+                    // Not valid framing, wait for the next packet
+                    self.in_packet = false;
+                    return None;
+                }
+            }
+        } else if byte == TSL5_DLE {
+            self.escape = true;
+            return None;
+        }
+        if !self.in_packet {
+            return None;
+        }
+        if self.buf.push(byte).is_err() {
+            self.in_packet = false;
+            return None;
+        }
+        let pbc = match *self.buf.as_slice() {
+            [lo, hi, ..] => usize::from(u16::from_le_bytes([lo, hi])),
+            _ => return None,
+        };
+        if self.buf.len() == pbc + 2 {
+            self.in_packet = false;
+            Some(&self.buf)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(address: u8, control: u8, text: &str) -> [u8; 18] {
+        let mut buf = [b' '; 18];
+        buf[0] = 0x80 | address;
+        buf[1] = control;
+        buf[2..2 + text.len()].copy_from_slice(text.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_tsl_decode() {
+        let mut data = [b' '; 16];
+        data[..5].copy_from_slice(b"hello");
+        assert_eq!(
+            TSL31Message::decode(&frame(1, 0b0011_0001, "hello")),
+            Ok(TSL31Message {
+                __: true,
+                ___: 0,
+                address: 1,
+                tally_1: true,
+                tally_2: false,
+                tally_3: false,
+                tally_4: false,
+                brightness: 3,
+                data,
+            })
+        );
+    }
+
+    #[test]
+    fn test_tsl_tally() {
+        let msg = TSL31Message::decode(&frame(0x7e, 0b0000_0010, "CAM 2")).unwrap();
+        assert_eq!(msg.address(), 0x7e);
+        assert_eq!(msg.text(), "CAM 2");
+        assert_eq!(msg.tally(), TallyState::Preview);
+
+        let msg = TSL31Message::decode(&frame(3, 0b0011_0011, "")).unwrap();
+        assert_eq!(msg.tally(), TallyState::ProgramPreview);
+        assert_eq!(msg.text(), "");
+
+        // Tallies 3 and 4 don't mean anything to us
+        let msg = TSL31Message::decode(&frame(3, 0b0011_1100, "")).unwrap();
+        assert_eq!(msg.tally(), TallyState::Off);
+    }
+
+    #[test]
+    fn test_tsl_decode_errors() {
+        assert_eq!(
+            TSL31Message::decode(&frame(1, 0, "")[..17]),
+            Err(DecodeError::TooShort)
+        );
+        let mut buf = frame(1, 0, "");
+        buf[0] &= 0x7f;
+        assert_eq!(TSL31Message::decode(&buf), Err(DecodeError::BadHeader));
+    }
+
+    fn frame40(address: u8, text: &str, left: u8, right: u8) -> [u8; 22] {
+        let mut buf = [0; 22];
+        buf[..18].copy_from_slice(&frame(address, 0b0011_0000, text));
+        buf[18] = tsl40_checksum(&buf[..18]);
+        buf[19] = 0x02;
+        buf[20] = left;
+        buf[21] = right;
+        buf
+    }
+
+    #[test]
+    fn test_tsl40_checksum() {
+        let buf = frame40(5, "CAM 5", 0, 0);
+        let sum: u32 = buf[..19].iter().map(|b| u32::from(*b)).sum();
+        assert_eq!(sum % 128, 0);
+        assert_eq!(buf[18] & 0x80, 0);
+    }
+
+    #[test]
+    fn test_tsl40_decode() {
+        // Left display: LH red, text green, RH amber. Right display: all off.
+        let (msg, len) = TSL40Message::decode(&frame40(5, "CAM 5", 0b0001_1011, 0)).unwrap();
+        assert_eq!(len, 22);
+        assert_eq!(msg.base.address(), 5);
+        assert_eq!(msg.base.text(), "CAM 5");
+        assert_eq!(msg.minor_version, 0);
+        assert_eq!(
+            msg.display_left.colour(TslColourTally::Left),
+            TallyColour::Red
+        );
+        assert_eq!(
+            msg.display_left.colour(TslColourTally::Text),
+            TallyColour::Green
+        );
+        assert_eq!(
+            msg.display_left.colour(TslColourTally::Right),
+            TallyColour::Amber
+        );
+        assert_eq!(msg.display_right, TSL40XByte::default());
+        assert_eq!(msg.tally(TslColourTally::Left), TallyState::Program);
+        assert_eq!(msg.tally(TslColourTally::Text), TallyState::Preview);
+        assert_eq!(msg.tally(TslColourTally::Right), TallyState::ProgramPreview);
+    }
+
+    #[test]
+    fn test_tsl40_decode_errors() {
+        let mut buf = frame40(5, "CAM 5", 0b0001_0000, 0);
+        buf[18] ^= 0x01;
+        assert_eq!(TSL40Message::decode(&buf), Err(DecodeError::BadChecksum));
+        assert_eq!(
+            TSL40Message::decode(&frame40(5, "CAM 5", 0, 0)[..21]),
+            Err(DecodeError::TooShort)
+        );
+    }
+
+    #[test]
+    fn test_tsl_version_detect() {
+        let mut buf = [0; 18 + 22];
+        buf[..18].copy_from_slice(&frame(1, 0b0000_0001, "ONE"));
+        buf[18..].copy_from_slice(&frame40(2, "TWO", 0b0000_1000, 0));
+
+        let (msg, len) = TSLMessage::decode(&buf).unwrap();
+        assert!(matches!(msg, TSLMessage::V31(_)));
+        assert_eq!(len, 18);
+        assert_eq!(msg.address(), 1);
+        assert_eq!(msg.tally(TslColourTally::Text), TallyState::Program);
+
+        let (msg, len) = TSLMessage::decode(&buf[18..]).unwrap();
+        assert!(matches!(msg, TSLMessage::V40(_)));
+        assert_eq!(len, 22);
+        assert_eq!(msg.address(), 2);
+        assert_eq!(msg.tally(TslColourTally::Text), TallyState::Preview);
+    }
+
+    fn dmsg(buf: &mut Vec<u8, 64>, index: u16, control: u16, text: &[u8]) {
+        buf.extend_from_slice(&index.to_le_bytes()).unwrap();
+        buf.extend_from_slice(&control.to_le_bytes()).unwrap();
+        buf.extend_from_slice(&(text.len() as u16).to_le_bytes())
+            .unwrap();
+        buf.extend_from_slice(text).unwrap();
+    }
+
+    fn packet5(flags: u8, screen: u16, messages: &[u8]) -> Vec<u8, 64> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(messages.len() as u16 + 4).to_le_bytes())
+            .unwrap();
+        buf.push(0).unwrap();
+        buf.push(flags).unwrap();
+        buf.extend_from_slice(&screen.to_le_bytes()).unwrap();
+        buf.extend_from_slice(messages).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_tsl5_decode() {
+        let mut messages = Vec::new();
+        // Brightness 3, LH red, text green, RH amber
+        dmsg(&mut messages, 1, 0b1101_1011, b"CAM 1");
+        // Brightness 1, LH green
+        dmsg(&mut messages, 2, 0b0110_0000, b"CAM 2");
+        let buf = packet5(0, 3, &messages);
+
+        let packet = TSL5Packet::decode(&buf).unwrap();
+        assert_eq!(packet.version, 0);
+        assert_eq!(packet.screen, 3);
+        let mut messages = packet.messages();
+
+        let msg = messages.next().unwrap().unwrap();
+        assert_eq!(msg.index, 1);
+        assert_eq!(msg.brightness, 3);
+        assert_eq!(msg.left, TallyColour::Red);
+        assert_eq!(msg.text_tally, TallyColour::Green);
+        assert_eq!(msg.right, TallyColour::Amber);
+        assert!(msg.text.chars().eq("CAM 1".chars()));
+        assert_eq!(msg.tally(TslColourTally::Left), TallyState::Program);
+
+        let msg = messages.next().unwrap().unwrap();
+        assert_eq!(msg.index, 2);
+        assert_eq!(msg.brightness, 1);
+        assert_eq!(msg.tally(TslColourTally::Left), TallyState::Preview);
+        assert_eq!(msg.tally(TslColourTally::Right), TallyState::Off);
+
+        assert!(messages.next().is_none());
+    }
+
+    #[test]
+    fn test_tsl5_utf16() {
+        let text: Vec<u8, 64> = "Kamera ü"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let mut messages = Vec::new();
+        dmsg(&mut messages, 7, 0, &text);
+        let buf = packet5(0b01, 0, &messages);
+        let packet = TSL5Packet::decode(&buf).unwrap();
+        let msg = packet.messages().next().unwrap().unwrap();
+        assert!(msg.text.chars().eq("Kamera ü".chars()));
+    }
+
+    #[test]
+    fn test_tsl5_skips_control() {
+        let mut messages = Vec::new();
+        dmsg(&mut messages, 1, 0x8000, &[1, 2, 3]);
+        dmsg(&mut messages, 2, 0b01_0000, b"");
+        let buf = packet5(0, 0, &messages);
+        let packet = TSL5Packet::decode(&buf).unwrap();
+        let indexes: Vec<u16, 4> = packet.messages().map(|m| m.unwrap().index).collect();
+        assert_eq!(indexes, [2]);
+
+        // Screen control packets have no display messages at all
+        let buf = packet5(0b10, 0, &messages);
+        assert_eq!(TSL5Packet::decode(&buf).unwrap().messages().count(), 0);
+    }
+
+    #[test]
+    fn test_tsl5_decode_errors() {
+        assert_eq!(TSL5Packet::decode(&[4, 0, 0]), Err(DecodeError::TooShort));
+        let mut messages = Vec::new();
+        dmsg(&mut messages, 1, 0, b"CAM 1");
+        let mut buf = packet5(0, 0, &messages);
+        // Claim the text is longer than it is
+        let len = buf.len();
+        buf[len - 7] = 6;
+        let packet = TSL5Packet::decode(&buf).unwrap();
+        let mut messages = packet.messages();
+        assert_eq!(messages.next(), Some(Err(DecodeError::TooShort)));
+        assert_eq!(messages.next(), None);
+    }
+
+    #[test]
+    fn test_tsl5_deframe() {
+        let mut messages = Vec::new();
+        // Control word with a DLE in it
+        dmsg(&mut messages, 0xfe, 0, b"A");
+        let packet = packet5(0, 0, &messages);
+
+        // Start with some junk that isn't part of a packet
+        let mut stream: Vec<u8, 64> = Vec::from_slice(&[0x00, 0x13]).unwrap();
+        for _ in 0..2 {
+            stream.extend_from_slice(&[TSL5_DLE, TSL5_STX]).unwrap();
+            for b in &packet {
+                stream.push(*b).unwrap();
+                if *b == TSL5_DLE {
+                    stream.push(TSL5_DLE).unwrap();
+                }
+            }
+        }
+
+        let mut deframer = TSL5Deframer::<64>::new();
+        let mut found = 0;
+        for b in stream {
+            if let Some(p) = deframer.push(b) {
+                assert_eq!(p, packet.as_slice());
+                found += 1;
+            }
+        }
+        assert_eq!(found, 2);
+    }
+
+    #[test]
+    fn test_tsl5_deframe_overflow() {
+        let mut deframer = TSL5Deframer::<8>::new();
+        let mut messages = Vec::new();
+        dmsg(&mut messages, 1, 0, b"too long to fit");
+        let packet = packet5(0, 0, &messages);
+        assert!(deframer.push(TSL5_DLE).is_none());
+        assert!(deframer.push(TSL5_STX).is_none());
+        assert!(packet.iter().all(|b| deframer.push(*b).is_none()));
+    }
+}
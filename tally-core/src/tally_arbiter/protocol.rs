@@ -0,0 +1,387 @@
+//! Tally Arbiter's listener client protocol: socket.io v4 events over Engine.IO v4, over a
+//! WebSocket.
+//!
+//! Like the OBS client, JSON is parsed in place with `serde-json-core` and strings are
+//! borrowed as-is.
+
+use core::fmt;
+
+use heapless::{String, Vec};
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{IgnoredAny, SeqAccess, Visitor},
+};
+
+use crate::state::TallyState;
+
+/// Most buses we keep track of. Tally Arbiter has preview, program and a few aux by default.
+const MAX_BUSES: usize = 8;
+/// Most bus states in one `device_states` event.
+const MAX_STATES: usize = 24;
+/// Longest ID we keep. Tally Arbiter's are 8 hex digits, but leave room for others.
+pub const MAX_ID: usize = 40;
+
+/// An Engine.IO packet, and the socket.io packet inside it if it's a message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Packet<'a> {
+    /// The server has accepted the connection
+    Open,
+    Ping,
+    /// We're connected to the socket.io namespace
+    Connected,
+    Disconnected,
+    Event {
+        /// Set if the server wants an acknowledgement
+        ack: Option<u32>,
+        json: &'a [u8],
+    },
+    Other,
+}
+
+impl<'a> Packet<'a> {
+    pub fn parse(text: &'a [u8]) -> Self {
+        match text {
+            [b'0', ..] => Self::Open,
+            [b'1', ..] | [b'4', b'1', ..] => Self::Disconnected,
+            [b'2', ..] => Self::Ping,
+            [b'4', b'0', ..] => Self::Connected,
+            [b'4', b'2', rest @ ..] => {
+                let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+                let ack = core::str::from_utf8(&rest[..digits])
+                    .ok()
+                    .and_then(|id| id.parse().ok());
+                Self::Event {
+                    ack,
+                    json: &rest[digits..],
+                }
+            }
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BusType {
+    Preview,
+    Program,
+    /// Aux buses, which we've no way of showing
+    Other,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct BusOption<'a> {
+    pub id: &'a str,
+    #[serde(rename = "type")]
+    pub bus_type: &'a str,
+}
+
+/// Whether a JSON array has anything in it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct NonEmpty(bool);
+
+impl<'de> Deserialize<'de> for NonEmpty {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NonEmptyVisitor;
+
+        impl<'de> Visitor<'de> for NonEmptyVisitor {
+            type Value = NonEmpty;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an array")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<NonEmpty, A::Error> {
+                let any = seq.next_element::<IgnoredAny>()?.is_some();
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(NonEmpty(any))
+            }
+        }
+
+        deserializer.deserialize_seq(NonEmptyVisitor)
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceState<'a> {
+    pub device_id: Option<&'a str>,
+    pub bus_id: &'a str,
+    /// The sources putting the device on this bus
+    #[serde(default)]
+    pub sources: NonEmpty,
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// The server events we handle.
+///
+/// The lists make this big, but an event only lives on the stack while it's handled, and boxing
+/// them would need a heap this crate otherwise does without.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    BusOptions(Vec<BusOption<'a>, MAX_BUSES>),
+    DeviceStates(Vec<DeviceState<'a>, MAX_STATES>),
+    Flash,
+    /// Tally Arbiter moved this listener from one device to another
+    Reassign {
+        old: &'a str,
+        new: &'a str,
+    },
+    Other,
+}
+
+impl<'de> Deserialize<'de> for Event<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EventVisitor;
+
+        impl<'de> Visitor<'de> for EventVisitor {
+            type Value = Event<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an event name and its arguments")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Event<'de>, A::Error> {
+                let missing = || serde::de::Error::invalid_length(0, &self);
+                let event = match seq.next_element::<&str>()?.ok_or_else(missing)? {
+                    "bus_options" => Event::BusOptions(seq.next_element()?.unwrap_or_default()),
+                    "device_states" => Event::DeviceStates(seq.next_element()?.unwrap_or_default()),
+                    "flash" => Event::Flash,
+                    "reassign" => Event::Reassign {
+                        old: seq.next_element()?.ok_or_else(missing)?,
+                        new: seq.next_element()?.ok_or_else(missing)?,
+                    },
+                    _ => Event::Other,
+                };
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(event)
+            }
+        }
+
+        deserializer.deserialize_seq(EventVisitor)
+    }
+}
+
+impl<'a> Event<'a> {
+    pub fn parse(json: &'a [u8]) -> serde_json_core::de::Result<Self> {
+        serde_json_core::from_slice(json).map(|(event, _)| event)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListenerClient<'a> {
+    device_id: &'a str,
+    listener_type: &'a str,
+    can_be_reassigned: bool,
+    can_be_flashed: bool,
+    supports_chat: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReassignObject<'a> {
+    old_device_id: &'a str,
+    new_device_id: &'a str,
+}
+
+/// Write a socket.io event into `out`, returning its length.
+fn event(out: &mut [u8], args: &impl Serialize) -> serde_json_core::ser::Result<usize> {
+    let prefix = b"42";
+    out.get_mut(..prefix.len())
+        .ok_or(serde_json_core::ser::Error::BufferFull)?
+        .copy_from_slice(prefix);
+    Ok(prefix.len() + serde_json_core::to_slice(args, &mut out[prefix.len()..])?)
+}
+
+/// Register as a listener for `device_id`, shown in Tally Arbiter as `name`.
+pub fn listener_connect(
+    out: &mut [u8],
+    device_id: &str,
+    name: &str,
+) -> serde_json_core::ser::Result<usize> {
+    let client = ListenerClient {
+        device_id,
+        listener_type: name,
+        can_be_reassigned: true,
+        can_be_flashed: true,
+        supports_chat: false,
+    };
+    event(out, &("listenerclient_connect", client))
+}
+
+/// Ask for the bus options, which we need to make sense of device states.
+pub fn bus_options(out: &mut [u8]) -> serde_json_core::ser::Result<usize> {
+    event(out, &["bus_options"])
+}
+
+/// Confirm we've moved to another device.
+pub fn reassigned(out: &mut [u8], old: &str, new: &str) -> serde_json_core::ser::Result<usize> {
+    let reassign = ReassignObject {
+        old_device_id: old,
+        new_device_id: new,
+    };
+    event(out, &("listener_reassign_object", reassign))
+}
+
+/// Acknowledge event `id`, with no arguments.
+pub fn ack(out: &mut [u8], id: u32) -> Option<usize> {
+    use core::fmt::Write;
+
+    let mut text = String::<16>::new();
+    write!(text, "43{}[]", id).ok()?;
+    out.get_mut(..text.len())?.copy_from_slice(text.as_bytes());
+    Some(text.len())
+}
+
+/// Tracks the tally of the device we're listening for.
+pub struct Listener {
+    device_id: String<MAX_ID>,
+    buses: Vec<(String<MAX_ID>, BusType), MAX_BUSES>,
+    /// The buses the device was last on
+    active: Vec<String<MAX_ID>, MAX_STATES>,
+}
+
+impl Listener {
+    pub fn new(device_id: &str) -> Self {
+        Self {
+            device_id: device_id.try_into().unwrap_or_default(),
+            buses: Vec::new(),
+            active: Vec::new(),
+        }
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// Handle a bus options or device states event, returning the tally if it might have
+    /// changed.
+    pub fn handle(&mut self, event: &Event) -> Option<TallyState> {
+        match event {
+            Event::BusOptions(options) => {
+                self.buses = options
+                    .iter()
+                    .filter_map(|bus| {
+                        let bus_type = match bus.bus_type {
+                            "preview" => BusType::Preview,
+                            "program" => BusType::Program,
+                            _ => BusType::Other,
+                        };
+                        Some((bus.id.try_into().ok()?, bus_type))
+                    })
+                    .collect();
+            }
+            Event::DeviceStates(states) => {
+                self.active = states
+                    .iter()
+                    .filter(|s| s.device_id.is_none_or(|id| id == self.device_id))
+                    .filter(|s| s.active || s.sources.0)
+                    .filter_map(|s| s.bus_id.try_into().ok())
+                    .collect();
+            }
+            _ => return None,
+        }
+        Some(self.tally())
+    }
+
+    pub fn tally(&self) -> TallyState {
+        let on = |bus_type| {
+            self.buses
+                .iter()
+                .any(|(id, t)| *t == bus_type && self.active.contains(id))
+        };
+        TallyState::new(on(BusType::Program), on(BusType::Preview))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        assert_eq!(Packet::parse(b"0{\"sid\":\"x\"}"), Packet::Open);
+        assert_eq!(Packet::parse(b"2"), Packet::Ping);
+        assert_eq!(Packet::parse(b"40{\"sid\":\"y\"}"), Packet::Connected);
+        assert_eq!(
+            Packet::parse(b"42[\"flash\"]"),
+            Packet::Event {
+                ack: None,
+                json: b"[\"flash\"]"
+            }
+        );
+        assert_eq!(
+            Packet::parse(b"4213[\"flash\"]"),
+            Packet::Event {
+                ack: Some(13),
+                json: b"[\"flash\"]"
+            }
+        );
+        assert_eq!(Packet::parse(b"41"), Packet::Disconnected);
+    }
+
+    #[test]
+    fn test_events() {
+        assert_eq!(Event::parse(b"[\"flash\"]"), Ok(Event::Flash));
+        assert_eq!(
+            Event::parse(b"[\"reassign\",\"abc\",\"def\",{\"x\":1}]"),
+            Ok(Event::Reassign {
+                old: "abc",
+                new: "def"
+            })
+        );
+        assert_eq!(
+            Event::parse(b"[\"messaging\",\"server\",\"hi\"]"),
+            Ok(Event::Other)
+        );
+        assert!(Event::parse(b"[]").is_err());
+    }
+
+    #[test]
+    fn test_listener() {
+        let mut listener = Listener::new("dev1");
+        let buses = Event::parse(
+            br##"["bus_options",[
+                {"id":"b1","label":"Preview","type":"preview","color":"#3fe481","priority":50},
+                {"id":"b2","label":"Program","type":"program","color":"#e43f3f","priority":200},
+                {"id":"b3","label":"Aux 1","type":"aux","color":"#0000ff","priority":100}]]"##,
+        )
+        .unwrap();
+        assert_eq!(listener.handle(&buses), Some(TallyState::Off));
+        let states = Event::parse(
+            br#"["device_states",[
+                {"deviceId":"dev1","busId":"b1","sources":["s1"],"active":true},
+                {"deviceId":"dev1","busId":"b2","sources":[],"active":false},
+                {"deviceId":"dev1","busId":"b3","sources":["s2"],"active":true},
+                {"deviceId":"dev2","busId":"b2","sources":["s3"],"active":true}]]"#,
+        )
+        .unwrap();
+        assert_eq!(listener.handle(&states), Some(TallyState::Preview));
+        let states = Event::parse(br#"["device_states",[{"busId":"b2","sources":["s1"]}]]"#);
+        assert_eq!(listener.handle(&states.unwrap()), Some(TallyState::Program));
+        assert_eq!(listener.handle(&Event::Flash), None);
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut out = [0; 192];
+        let len = listener_connect(&mut out, "dev1", "µTally").unwrap();
+        assert_eq!(
+            &out[..len],
+            "42[\"listenerclient_connect\",{\"deviceId\":\"dev1\",\"listenerType\":\"µTally\",\
+             \"canBeReassigned\":true,\"canBeFlashed\":true,\"supportsChat\":false}]"
+                .as_bytes()
+        );
+        let len = bus_options(&mut out).unwrap();
+        assert_eq!(&out[..len], b"42[\"bus_options\"]");
+        let len = reassigned(&mut out, "a", "b").unwrap();
+        assert_eq!(
+            &out[..len],
+            b"42[\"listener_reassign_object\",{\"oldDeviceId\":\"a\",\"newDeviceId\":\"b\"}]"
+        );
+        let len = ack(&mut out, 7).unwrap();
+        assert_eq!(&out[..len], b"437[]");
+    }
+}
@@ -0,0 +1,253 @@
+//! Just enough streaming XML to follow TriCaster's `NTK_states` pushes, e.g.
+//!
+//! ```xml
+//! <shortcut_states>
+//!   <shortcut_state name="program_tally" value="input1|ddr2" type="" sender="" />
+//!   <shortcut_state name="preview_tally" value="input3" type="" sender="" />
+//! </shortcut_states>
+//! ```
+//!
+//! Bytes are fed in one at a time as they arrive and only one tag is buffered, so there's no
+//! need to hold a whole document.
+
+use heapless::Vec;
+
+use crate::state::TallyState;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TagKind {
+    /// `<name ...>`
+    Open,
+    /// `</name>`
+    Close,
+    /// `<name ... />`
+    Empty,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Tag<'a> {
+    pub kind: TagKind,
+    pub name: &'a [u8],
+    /// Everything after the name, unparsed
+    attrs: &'a [u8],
+}
+
+impl<'a> Tag<'a> {
+    fn parse(tag: &'a [u8]) -> Option<Self> {
+        let (kind, tag) = if let Some(tag) = tag.strip_prefix(b"/") {
+            (TagKind::Close, tag)
+        } else if let Some(tag) = tag.strip_suffix(b"/") {
+            (TagKind::Empty, tag)
+        } else {
+            (TagKind::Open, tag)
+        };
+        let name_len = tag
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .unwrap_or(tag.len());
+        if name_len == 0 {
+            return None;
+        }
+        Some(Self {
+            kind,
+            name: &tag[..name_len],
+            attrs: &tag[name_len..],
+        })
+    }
+
+    /// The raw value of attribute `name`. Entities aren't decoded.
+    pub fn attr(&self, name: &[u8]) -> Option<&'a [u8]> {
+        let mut rest = self.attrs;
+        loop {
+            rest = rest.trim_ascii_start();
+            let eq = rest.iter().position(|b| *b == b'=')?;
+            let key = rest[..eq].trim_ascii();
+            rest = rest[eq + 1..].trim_ascii_start();
+            let quote = *rest.first().filter(|q| matches!(q, b'"' | b'\''))?;
+            let len = rest[1..].iter().position(|b| *b == quote)?;
+            let value = &rest[1..1 + len];
+            if key == name {
+                return Some(value);
+            }
+            rest = &rest[len + 2..];
+        }
+    }
+}
+
+/// Splits a byte stream into tags, skipping text, comments and declarations.
+pub struct Tokenizer<const N: usize> {
+    buf: Vec<u8, N>,
+    in_tag: bool,
+    /// The quote character of the attribute value we're in, if any
+    quote: Option<u8>,
+    /// The current tag didn't fit in `buf`, so gets dropped
+    overflow: bool,
+}
+
+impl<const N: usize> Default for Tokenizer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Tokenizer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            in_tag: false,
+            quote: None,
+            overflow: false,
+        }
+    }
+
+    /// Feed the next byte, returning a tag if one is now complete.
+    pub fn push(&mut self, byte: u8) -> Option<Tag<'_>> {
+        if !self.in_tag {
+            if byte == b'<' {
+                self.in_tag = true;
+                self.quote = None;
+                self.overflow = false;
+                self.buf.clear();
+            }
+            return None;
+        }
+        match (self.quote, byte) {
+            (Some(q), b) if b == q => self.quote = None,
+            (None, b'"' | b'\'') => self.quote = Some(byte),
+            (None, b'>') => {
+                self.in_tag = false;
+                if self.overflow || matches!(self.buf.first(), Some(b'?' | b'!')) {
+                    return None;
+                }
+                return Tag::parse(&self.buf);
+            }
+            _ => {}
+        }
+        if self.buf.push(byte).is_err() {
+            self.overflow = true;
+        }
+        None
+    }
+}
+
+/// Whether `|` separated `list` contains `name`, ignoring case.
+fn contains(list: &[u8], name: &[u8]) -> bool {
+    list.split(|b| *b == b'|')
+        .any(|item| item.trim_ascii().eq_ignore_ascii_case(name))
+}
+
+/// Tracks one input's tally through the states TriCaster pushes.
+pub struct Follower<'a> {
+    input: &'a [u8],
+    program: bool,
+    preview: bool,
+    /// Inside a `shortcut_states` element, so hold off until all of it has arrived
+    in_states: bool,
+}
+
+impl<'a> Follower<'a> {
+    /// Follow `input`, as TriCaster names it, e.g. `input3` or `ddr1`.
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input: input.as_bytes(),
+            program: false,
+            preview: false,
+            in_states: false,
+        }
+    }
+
+    /// Handle a tag, returning the tally if it might have changed.
+    pub fn handle(&mut self, tag: &Tag) -> Option<TallyState> {
+        match (tag.kind, tag.name) {
+            (TagKind::Open, b"shortcut_states") => {
+                self.in_states = true;
+                None
+            }
+            (TagKind::Close, b"shortcut_states") => {
+                self.in_states = false;
+                Some(TallyState::new(self.program, self.preview))
+            }
+            (TagKind::Open | TagKind::Empty, b"shortcut_state") => {
+                let on = contains(tag.attr(b"value")?, self.input);
+                match tag.attr(b"name")? {
+                    b"program_tally" => self.program = on,
+                    b"preview_tally" => self.preview = on,
+                    _ => return None,
+                }
+                (!self.in_states).then(|| TallyState::new(self.program, self.preview))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn follow(follower: &mut Follower, xml: &[u8]) -> Option<TallyState> {
+        let mut tokenizer = Tokenizer::<128>::new();
+        let mut last = None;
+        for b in xml {
+            if let Some(tally) = tokenizer.push(*b).and_then(|tag| follower.handle(&tag)) {
+                last = Some(tally);
+            }
+        }
+        last
+    }
+
+    #[test]
+    fn test_tag() {
+        let mut tokenizer = Tokenizer::<64>::new();
+        let xml = b"<?xml version=\"1.0\"?>text<!-- <a> --><a x='1>2' y = \"b\" /></a>";
+        let mut found = 0;
+        for b in xml {
+            if let Some(tag) = tokenizer.push(*b) {
+                assert_eq!(tag.name, b"a");
+                if found == 0 {
+                    assert_eq!(tag.kind, TagKind::Empty);
+                    assert_eq!(tag.attr(b"x"), Some(&b"1>2"[..]));
+                    assert_eq!(tag.attr(b"y"), Some(&b"b"[..]));
+                    assert_eq!(tag.attr(b"z"), None);
+                } else {
+                    assert_eq!(tag.kind, TagKind::Close);
+                }
+                found += 1;
+            }
+        }
+        assert_eq!(found, 2);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut tokenizer = Tokenizer::<8>::new();
+        let mut found = 0;
+        for b in b"<much_too_long_tag/><ok/>" {
+            if let Some(tag) = tokenizer.push(*b) {
+                assert_eq!(tag.name, b"ok");
+                found += 1;
+            }
+        }
+        assert_eq!(found, 1);
+    }
+
+    #[test]
+    fn test_follower() {
+        let mut follower = Follower::new("input3");
+        let states = b"<shortcut_states>\
+            <shortcut_state name=\"program_tally\" value=\"input1|ddr2\" type=\"\" sender=\"\" />\
+            <shortcut_state name=\"preview_tally\" value=\"INPUT3\" type=\"\" sender=\"\" />\
+            </shortcut_states>";
+        assert_eq!(follow(&mut follower, states), Some(TallyState::Preview));
+        let program = b"<shortcut_state name=\"program_tally\" value=\"input3|input13\" />";
+        assert_eq!(
+            follow(&mut follower, program),
+            Some(TallyState::ProgramPreview)
+        );
+        let other = b"<shortcut_state name=\"record_toggle\" value=\"1\" />";
+        assert_eq!(follow(&mut follower, other), None);
+        // Only a whole name counts
+        let mut follower = Follower::new("input1");
+        assert_eq!(follow(&mut follower, program), Some(TallyState::Off));
+    }
+}
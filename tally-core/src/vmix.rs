@@ -0,0 +1,87 @@
+//! vMix TCP API tally lines.
+
+use heapless::Vec;
+
+use crate::state::TallyState;
+
+/// Splits a byte stream into `\r\n` or `\n` terminated lines.
+pub struct LineBuffer<const N: usize> {
+    buf: Vec<u8, N>,
+    complete: bool,
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            complete: false,
+        }
+    }
+
+    /// Feed the next byte, returning a line (without its terminator) if one is now complete.
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        if self.complete {
+            self.buf.clear();
+            self.complete = false;
+        }
+        if byte == b'\n' {
+            self.complete = true;
+            return Some(self.buf.strip_suffix(b"\r").unwrap_or(&self.buf));
+        }
+        // Anything that doesn't fit is dropped
+        let _ = self.buf.push(byte);
+        None
+    }
+}
+
+/// Parse a `TALLY OK <states>` line, returning the tally of (1-based) `input`, if present.
+///
+/// Each input gets one character: `0` off, `1` program and `2` preview.
+pub fn parse_tally(line: &[u8], input: u16) -> Option<TallyState> {
+    let states = line.strip_prefix(b"TALLY OK ")?;
+    match states.get(usize::from(input).checked_sub(1)?)? {
+        b'0' => Some(TallyState::Off),
+        b'1' => Some(TallyState::Program),
+        b'2' => Some(TallyState::Preview),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tally() {
+        let line = b"TALLY OK 0120";
+        assert_eq!(parse_tally(line, 1), Some(TallyState::Off));
+        assert_eq!(parse_tally(line, 2), Some(TallyState::Program));
+        assert_eq!(parse_tally(line, 3), Some(TallyState::Preview));
+        assert_eq!(parse_tally(line, 4), Some(TallyState::Off));
+        assert_eq!(parse_tally(line, 5), None);
+        assert_eq!(parse_tally(line, 0), None);
+        assert_eq!(parse_tally(b"SUBSCRIBE OK TALLY", 1), None);
+    }
+
+    #[test]
+    fn test_line_buffer() {
+        let mut lines = LineBuffer::<16>::new();
+        let mut found: Vec<Vec<u8, 16>, 4> = Vec::new();
+        for b in b"SUBSCRIBE OK TALLY\r\nTALLY OK 0120\r\nTALLY OK 1\n" {
+            if let Some(line) = lines.push(*b) {
+                found.push(Vec::from_slice(line).unwrap()).unwrap();
+            }
+        }
+        assert_eq!(found.len(), 3);
+        // Truncated
+        assert_eq!(found[0], b"SUBSCRIBE OK TAL");
+        assert_eq!(found[1], b"TALLY OK 0120");
+        assert_eq!(found[2], b"TALLY OK 1");
+    }
+}
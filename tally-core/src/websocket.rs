@@ -0,0 +1,181 @@
+//! Just enough of a WebSocket (RFC 6455) client to talk to obs-websocket and socket.io.
+
+use core::fmt::{self, Write};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use heapless::String;
+
+pub mod opcode {
+    pub const CONTINUATION: u8 = 0x0;
+    pub const TEXT: u8 = 0x1;
+    pub const CLOSE: u8 = 0x8;
+    pub const PING: u8 = 0x9;
+    pub const PONG: u8 = 0xa;
+}
+
+/// Write the HTTP upgrade request that opens the connection, asking for `protocol` if set.
+pub fn handshake<const N: usize>(
+    out: &mut String<N>,
+    host: &str,
+    path: &str,
+    protocol: Option<&str>,
+    key: [u8; 16],
+) -> fmt::Result {
+    let mut encoded = [0; 24];
+    BASE64.encode_slice(key, &mut encoded).unwrap();
+    write!(
+        out,
+        "GET {path} HTTP/1.1\r\n\
+         Host: {host}\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\n",
+        // base64 is always ASCII
+        core::str::from_utf8(&encoded).unwrap()
+    )?;
+    if let Some(protocol) = protocol {
+        write!(out, "Sec-WebSocket-Protocol: {protocol}\r\n")?;
+    }
+    out.write_str("Sec-WebSocket-Version: 13\r\n\r\n")
+}
+
+/// Length of the handshake response in `buf` if it's complete, and whether it accepted the
+/// upgrade.
+pub fn handshake_response(buf: &[u8]) -> Option<(usize, bool)> {
+    let end = buf.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    Some((end, buf.starts_with(b"HTTP/1.1 101")))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub fin: bool,
+    pub opcode: u8,
+    /// Length of the header itself
+    pub header_len: usize,
+    pub payload_len: u64,
+}
+
+impl FrameHeader {
+    /// Decode a server frame's header, if enough of it is in `buf`.
+    ///
+    /// Servers don't mask their frames, so we don't handle masked ones.
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let [b0, b1, ..] = *buf else {
+            return None;
+        };
+        let (header_len, payload_len) = match b1 & 0x7f {
+            126 => (
+                4,
+                u16::from_be_bytes(buf.get(2..4)?.try_into().unwrap()) as u64,
+            ),
+            127 => (10, u64::from_be_bytes(buf.get(2..10)?.try_into().unwrap())),
+            len => (2, len as u64),
+        };
+        Some(Self {
+            fin: b0 & 0x80 != 0,
+            opcode: b0 & 0x0f,
+            header_len,
+            payload_len,
+        })
+    }
+}
+
+/// Write a single, masked client frame into `out`, returning its length, or `None` if it
+/// doesn't fit.
+pub fn encode_frame(out: &mut [u8], opcode: u8, payload: &[u8], mask: [u8; 4]) -> Option<usize> {
+    let header_len = match payload.len() {
+        0..126 => 2,
+        126..=0xffff => 4,
+        _ => 10,
+    } + 4;
+    let len = header_len + payload.len();
+    let out = out.get_mut(..len)?;
+    out[0] = 0x80 | opcode;
+    match header_len - 4 {
+        2 => out[1] = 0x80 | payload.len() as u8,
+        4 => {
+            out[1] = 0x80 | 126;
+            out[2..4].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        _ => {
+            out[1] = 0x80 | 127;
+            out[2..10].copy_from_slice(&(payload.len() as u64).to_be_bytes());
+        }
+    }
+    out[header_len - 4..header_len].copy_from_slice(&mask);
+    for (i, (o, p)) in out[header_len..].iter_mut().zip(payload).enumerate() {
+        *o = p ^ mask[i % 4];
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake() {
+        let mut out = String::<256>::new();
+        let key = *b"the sample nonce";
+        handshake(
+            &mut out,
+            "10.0.0.2:4455",
+            "/",
+            Some("obswebsocket.json"),
+            key,
+        )
+        .unwrap();
+        assert!(out.contains("Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n"));
+        assert!(out.contains("Sec-WebSocket-Protocol: obswebsocket.json\r\n"));
+        assert!(out.ends_with("\r\n\r\n"));
+        out.clear();
+        handshake(&mut out, "10.0.0.2", "/socket.io/", None, key).unwrap();
+        assert!(!out.contains("Sec-WebSocket-Protocol"));
+
+        let response = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+            Connection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n\x81\x02{}";
+        assert_eq!(handshake_response(&response[..20]), None);
+        assert_eq!(
+            handshake_response(response),
+            Some((response.len() - 4, true))
+        );
+        assert_eq!(
+            handshake_response(b"HTTP/1.1 400 Bad Request\r\n\r\n"),
+            Some((28, false))
+        );
+    }
+
+    #[test]
+    fn test_frames() {
+        // Examples from RFC 6455 section 5.7
+        let mut out = [0; 16];
+        let len = encode_frame(&mut out, opcode::TEXT, b"Hello", [0x37, 0xfa, 0x21, 0x3d]);
+        assert_eq!(
+            &out[..len.unwrap()],
+            [
+                0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58
+            ]
+        );
+        assert_eq!(encode_frame(&mut out, opcode::TEXT, &[0; 16], [0; 4]), None);
+
+        assert_eq!(
+            FrameHeader::decode(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]),
+            Some(FrameHeader {
+                fin: true,
+                opcode: opcode::TEXT,
+                header_len: 2,
+                payload_len: 5
+            })
+        );
+        assert_eq!(
+            FrameHeader::decode(&[0x01, 0x7e, 0x01, 0x00]),
+            Some(FrameHeader {
+                fin: false,
+                opcode: opcode::TEXT,
+                header_len: 4,
+                payload_len: 256
+            })
+        );
+        assert_eq!(FrameHeader::decode(&[0x82, 0x7f, 0, 0, 0, 0]), None);
+    }
+}
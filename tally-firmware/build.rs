@@ -1,3 +1,15 @@
+use std::{env, fs, io::Write, path::Path};
+
+use flate2::{Compression, write::GzEncoder};
+
 fn main() {
     println!("cargo:rustc-link-arg-bins=-Tlinkall.x");
+
+    // The web UI is served gzipped straight out of flash
+    println!("cargo:rerun-if-changed=web/index.html");
+    let html = fs::read("web/index.html").unwrap();
+    let mut gz = GzEncoder::new(Vec::new(), Compression::best());
+    gz.write_all(&html).unwrap();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("index.html.gz");
+    fs::write(out, gz.finish().unwrap()).unwrap();
 }
@@ -0,0 +1,78 @@
+//! Arbitration between the sources reporting a tally.
+//!
+//! Each protocol reports its own view through [`state::set`] and friends, and [`arbitrate`]
+//! works out what the device shows: the source with the highest configured priority wins, and
+//! among equal priorities the most recent report. A lost signal only shows if no source has a
+//! real tally. Colour overrides are arbitrated the same way, on their own, as they're shown over
+//! the tally.
+//!
+//! Sources that stop reporting for longer than their timeout in the
+//! [`SourceTimeouts`](tally_rpc::rpc::SourceTimeouts) lose
+//! their signal, as do ones that report losing it themselves, e.g. when a connection drops.
+//!
+//! The [`TallyMapping`](tally_rpc::rpc::TallyMapping) is worked out here too, from the
+//! [`Matches`](tally_core::mapping::Matches) the protocols it mentions report, and takes part as
+//! [`TallySource::Mapping`].
+
+use core::cell::RefCell;
+
+use embassy_futures::select::{Either3, select3};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    signal::Signal,
+};
+use embassy_time::{Instant, Timer};
+use tally_core::arbitration::{Pending, Sources};
+use tally_rpc::rpc::TallySource;
+
+use crate::{config::CONFIG, state};
+
+pub use tally_core::arbitration::Change;
+
+/// Changes not yet arbitrated. Newer reports replace older ones from the same source, so
+/// bursts can't lose the latest state.
+static PENDING: Mutex<CriticalSectionRawMutex, RefCell<Pending>> =
+    Mutex::new(RefCell::new(Pending::new()));
+/// Wakes [`arbitrate`] when there's something pending.
+static REPORTED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Hand a change from `source` to arbitration.
+pub fn report(source: TallySource, change: Change) {
+    PENDING.lock(|pending| pending.borrow_mut().push(source, change, Instant::now()));
+    REPORTED.signal(());
+}
+
+/// Apply reports as they come in and publish the outcome.
+#[embassy_executor::task]
+pub async fn arbitrate() {
+    let mut config = CONFIG.receiver().unwrap();
+    let mut sources = Sources::new();
+    let initial = config.get().await;
+    let mut priorities = initial.priorities;
+    let mut timeouts = initial.failsafe.timeouts;
+    sources.set_mapping(initial.mapping, Instant::now());
+    loop {
+        let deadline = sources.next_deadline(&timeouts);
+        match select3(REPORTED.wait(), config.changed(), wait_until(deadline)).await {
+            Either3::First(()) => PENDING.lock(|pending| pending.take()).apply(&mut sources),
+            Either3::Second(config) => {
+                priorities = config.priorities;
+                timeouts = config.failsafe.timeouts;
+                sources.set_mapping(config.mapping, Instant::now());
+            }
+            Either3::Third(()) => {}
+        }
+        sources.expire(&timeouts, Instant::now());
+        state::publish(sources.tally(&priorities));
+        state::publish_tally_source(sources.tally_source(&priorities));
+        state::publish_color_override(sources.color_override(&priorities));
+        state::publish_lost_sources(sources.lost_sources());
+    }
+}
+
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => Timer::at(deadline).await,
+        None => core::future::pending().await,
+    }
+}
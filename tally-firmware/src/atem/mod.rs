@@ -0,0 +1,205 @@
+use embassy_futures::select::{Either, select};
+use embassy_net::{
+    IpEndpoint, Ipv4Address, Stack,
+    udp::{PacketMetadata, RecvError, SendError, UdpSocket},
+};
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use static_cell::ConstStaticCell;
+use tally_core::atem::codec::{Command, Session, SessionState, TransitionTracker};
+use tally_rpc::rpc::{Bus, MappedProtocol, TallySource};
+
+use crate::{
+    config::CONFIG,
+    mapping::Inputs,
+    state::{self, OutputStatus, TallyState},
+};
+
+const ATEM_PORT: u16 = 9910;
+
+/// How long to wait for a reply to our hello before sending another.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(1);
+/// Once connected the switcher pings us regularly, so if it's been this long it's gone.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+// The initial state dump arrives as a burst of large packets.
+static RX_META: ConstStaticCell<[PacketMetadata; 8]> =
+    ConstStaticCell::new([PacketMetadata::EMPTY; 8]);
+static RX_BUF: ConstStaticCell<[u8; 8192]> = ConstStaticCell::new([0; 8192]);
+static TX_META: ConstStaticCell<[PacketMetadata; 4]> =
+    ConstStaticCell::new([PacketMetadata::EMPTY; 4]);
+static TX_BUF: ConstStaticCell<[u8; 128]> = ConstStaticCell::new([0; 128]);
+static BUF: ConstStaticCell<[u8; 1500]> = ConstStaticCell::new([0; 1500]);
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Error {
+    /// The switcher stopped talking to us
+    Timeout,
+    Send(SendError),
+    Receive(RecvError),
+}
+
+/// Connect to the configured ATEM switcher and follow the tally of the configured input.
+#[embassy_executor::task]
+pub async fn atem_client(stack: Stack<'static>) {
+    let rx_meta = RX_META.take();
+    let rx_buf = RX_BUF.take();
+    let tx_meta = TX_META.take();
+    let tx_buf = TX_BUF.take();
+    let buf = BUF.take();
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let current = config.get().await;
+        let Some(atem) = current.atem else {
+            state::clear(TallySource::Atem);
+            config.changed().await;
+            continue;
+        };
+        let mut inputs = Inputs::new(current.mapping.as_ref(), MappedProtocol::Atem);
+        let mut socket = UdpSocket::new(
+            stack,
+            &mut rx_meta[..],
+            &mut rx_buf[..],
+            &mut tx_meta[..],
+            &mut tx_buf[..],
+        );
+        // Any local port will do
+        if let Err(e) = socket.bind(0) {
+            defmt::error!("ATEM: failed to bind: {:?}", e);
+            config.changed().await;
+            continue;
+        }
+        let remote = IpEndpoint::new(Ipv4Address::from(atem.ip).into(), ATEM_PORT);
+        match select(
+            run_session(&mut socket, remote, atem.input, &mut inputs, buf),
+            config.changed(),
+        )
+        .await
+        {
+            Either::First(e) => {
+                defmt::warn!("ATEM: session ended: {:?}", e);
+                state::lost(TallySource::Atem);
+                if let Some(inputs) = &mut inputs {
+                    inputs.lost();
+                }
+                reset_state();
+                Timer::after(RECONNECT_DELAY).await;
+            }
+            Either::Second(_) => reset_state(),
+        }
+    }
+}
+
+/// Forget anything the last session told us that's not a plain tally.
+fn reset_state() {
+    state::set_transition(None);
+    state::set_output_status(OutputStatus::default());
+}
+
+async fn run_session(
+    socket: &mut UdpSocket<'_>,
+    remote: IpEndpoint,
+    input: u16,
+    inputs: &mut Option<Inputs>,
+    buf: &mut [u8],
+) -> Error {
+    let mut session = Session::new(Instant::now().as_ticks() as u16);
+    let mut transition = TransitionTracker::default();
+    let mut output_status = OutputStatus::default();
+    defmt::info!("ATEM: connecting to {}", remote);
+    loop {
+        let connecting = session.state == SessionState::Connecting;
+        if connecting && let Err(e) = socket.send_to(&session.hello(), remote).await {
+            return Error::Send(e);
+        }
+        let timeout = if connecting {
+            HELLO_TIMEOUT
+        } else {
+            RECEIVE_TIMEOUT
+        };
+        let (len, meta) = match with_timeout(timeout, socket.recv_from(buf)).await {
+            Ok(Ok(r)) => r,
+            Ok(Err(e)) => return Error::Receive(e),
+            // Send another hello
+            Err(_) if connecting => continue,
+            Err(_) => return Error::Timeout,
+        };
+        if meta.endpoint != remote {
+            continue;
+        }
+        let received = match session.receive(&buf[..len]) {
+            Ok(r) => r,
+            Err(e) => {
+                defmt::warn!("ATEM: bad packet: {:?}", e);
+                continue;
+            }
+        };
+        if let Some(reply) = received.reply
+            && let Err(e) = socket.send_to(&reply, remote).await
+        {
+            return Error::Send(e);
+        }
+        for command in received.commands.into_iter().flatten() {
+            match command {
+                Ok(Command::Version { major, minor }) => {
+                    defmt::info!("ATEM: protocol version {}.{}", major, minor)
+                }
+                Ok(Command::InitComplete) => {
+                    defmt::info!("ATEM: connected, session {:x}", session.session_id());
+                    session.state = SessionState::Connected;
+                }
+                Ok(Command::TallyByIndex(tally)) => {
+                    handle_tally(|i| tally.get(i), input, inputs, &mut transition)
+                }
+                Ok(Command::TallyBySource(tally)) => {
+                    handle_tally(|i| tally.get(i), input, inputs, &mut transition)
+                }
+                // Tally follows the first M/E
+                Ok(Command::TransitionPosition {
+                    me: 0,
+                    in_transition,
+                    position,
+                    ..
+                }) => state::set_transition(transition.position(in_transition, position)),
+                Ok(Command::RecordingStatus { recording }) => {
+                    output_status.recording = recording;
+                    state::set_output_status(output_status);
+                }
+                Ok(Command::StreamingStatus { streaming }) => {
+                    output_status.streaming = streaming;
+                    state::set_output_status(output_status);
+                }
+                Ok(Command::TransitionPosition { .. } | Command::Other(..)) => {}
+                Err(e) => defmt::warn!("ATEM: bad command: {:?}", e),
+            }
+        }
+    }
+}
+
+/// Follow the configured input's tally, and report the mapping's inputs if it mentions us.
+///
+/// Transitions follow the configured input, or with a mapping the input of its first selector
+/// on program, which is what takes us on and off air.
+fn handle_tally(
+    tally: impl Fn(u16) -> Option<TallyState>,
+    input: u16,
+    inputs: &mut Option<Inputs>,
+    transition: &mut TransitionTracker,
+) {
+    let tracked = match inputs {
+        Some(inputs) => inputs.first_input(Bus::Program),
+        None => Some(input),
+    };
+    if let Some(state) = tracked.and_then(&tally) {
+        transition.tally(state);
+    }
+    match inputs {
+        Some(inputs) => inputs.report(tally),
+        None => {
+            if let Some(followed) = tally(input) {
+                state::set(TallySource::Atem, followed);
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use embassy_time::{Duration, Timer};
+
+/// Exponential backoff for reconnecting to things.
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub const fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            next: min,
+        }
+    }
+
+    /// Call after a successful connection.
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+
+    /// Wait out the current delay, doubling it for next time.
+    pub async fn wait(&mut self) {
+        Timer::after(self.next).await;
+        self.next = (self.next * 2).min(self.max);
+    }
+}
@@ -0,0 +1,104 @@
+//! Receiver for the native µTally tally broadcast, see [`tally_rpc::broadcast`].
+
+use embassy_futures::select::select;
+use embassy_net::{
+    Ipv4Address, Stack,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::Instant;
+use static_cell::ConstStaticCell;
+use tally_core::broadcast::Follower;
+use tally_rpc::{
+    broadcast::{self, Error, TallyBroadcast},
+    rpc::{MappedProtocol, TallySource},
+};
+
+use crate::{config::CONFIG, mapping::Inputs, state, state::TallyState};
+
+static RX_META: ConstStaticCell<[PacketMetadata; 4]> =
+    ConstStaticCell::new([PacketMetadata::EMPTY; 4]);
+static RX_BUF: ConstStaticCell<[u8; 4 * broadcast::MAX_LEN]> =
+    ConstStaticCell::new([0; 4 * broadcast::MAX_LEN]);
+static BUF: ConstStaticCell<[u8; broadcast::MAX_LEN]> =
+    ConstStaticCell::new([0; broadcast::MAX_LEN]);
+
+/// Listen for tally broadcasts on the configured group and follow our camera.
+#[embassy_executor::task]
+pub async fn broadcast_receiver(stack: Stack<'static>) {
+    let rx_meta = RX_META.take();
+    let rx_buf = RX_BUF.take();
+    let buf = BUF.take();
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let current = config.get().await;
+        let Some(bc) = current.broadcast else {
+            state::clear(TallySource::Broadcast);
+            config.changed().await;
+            continue;
+        };
+        let mut inputs = Inputs::new(current.mapping.as_ref(), MappedProtocol::Broadcast);
+        let mut socket = UdpSocket::new(stack, &mut rx_meta[..], &mut rx_buf[..], &mut [], &mut []);
+        if let Err(e) = socket.bind(bc.port) {
+            defmt::error!("Broadcast: failed to bind: {:?}", e);
+            config.changed().await;
+            continue;
+        }
+        let group = Ipv4Address::from(bc.group);
+        if let Err(e) = stack.join_multicast_group(group) {
+            defmt::warn!("Broadcast: failed to join {}: {:?}", group, e);
+        }
+        let mut follower = Follower::new(bc.camera);
+        select(
+            receive(&mut socket, &mut follower, &mut inputs, buf),
+            config.changed(),
+        )
+        .await;
+        let _ = stack.leave_multicast_group(group);
+        if follower.overriding() {
+            state::set_color_override(TallySource::Broadcast, None);
+        }
+        if let Some(inputs) = &mut inputs {
+            inputs.lost();
+        }
+    }
+}
+
+async fn receive(
+    socket: &mut UdpSocket<'_>,
+    follower: &mut Follower,
+    inputs: &mut Option<Inputs>,
+    buf: &mut [u8],
+) -> ! {
+    loop {
+        let len = match socket.recv_from(buf).await {
+            Ok((len, _)) => len,
+            Err(e) => {
+                defmt::warn!("Broadcast: receive failed: {:?}", e);
+                continue;
+            }
+        };
+        let broadcast = match TallyBroadcast::decode(&buf[..len]) {
+            Ok(broadcast) => broadcast,
+            Err(Error::Version(version)) => {
+                defmt::warn!("Broadcast: unsupported version {}", version);
+                continue;
+            }
+            Err(_) => {
+                defmt::warn!("Broadcast: bad packet");
+                continue;
+            }
+        };
+        if let Some(update) = follower.handle(&broadcast, Instant::now()) {
+            match inputs {
+                Some(inputs) => inputs.report(|camera| {
+                    let (program, preview) = broadcast.tally(u8::try_from(camera).ok()?)?;
+                    Some(TallyState::new(program, preview))
+                }),
+                None => state::set(TallySource::Broadcast, update.tally),
+            }
+            if let Some(color) = update.color {
+                state::set_color_override(TallySource::Broadcast, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use tally_rpc::rpc::Config;
+
+/// Max number of tasks that can hold a receiver on [`CONFIG`].
+const CONFIG_RECEIVERS: usize = 20;
+
+/// The running device configuration.
+///
+/// Tasks should hold a receiver and re-read their section of the config whenever it changes,
+/// so that changes apply without a reboot.
+pub static CONFIG: Watch<CriticalSectionRawMutex, Config, CONFIG_RECEIVERS> = Watch::new();
+
+/// Set the running configuration, notifying all receivers.
+pub fn set(config: Config) {
+    CONFIG.sender().send(config);
+}
@@ -0,0 +1,157 @@
+use embassy_futures::select::{Either3, select, select3};
+use embassy_net::{
+    IpAddress, Ipv4Address, Stack,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::{Duration, Instant, Timer};
+use static_cell::ConstStaticCell;
+use tally_core::dmx::codec::{self, Arbiter, ArtDmx, DecodeError, SacnPacket};
+use tally_rpc::rpc::DmxConfig;
+
+use crate::{config::CONFIG, leds::PIXELS, state};
+
+/// What Art-Net data counts as, for arbitrating with sACN.
+const ARTNET_PRIORITY: u8 = 100;
+
+// Desks send continuously, often several times per frame
+static SACN_RX_META: ConstStaticCell<[PacketMetadata; 4]> =
+    ConstStaticCell::new([PacketMetadata::EMPTY; 4]);
+static SACN_RX_BUF: ConstStaticCell<[u8; 4 * codec::SACN_MAX_LEN]> =
+    ConstStaticCell::new([0; 4 * codec::SACN_MAX_LEN]);
+static SACN_BUF: ConstStaticCell<[u8; codec::SACN_MAX_LEN]> =
+    ConstStaticCell::new([0; codec::SACN_MAX_LEN]);
+static ARTNET_RX_META: ConstStaticCell<[PacketMetadata; 4]> =
+    ConstStaticCell::new([PacketMetadata::EMPTY; 4]);
+static ARTNET_RX_BUF: ConstStaticCell<[u8; 4 * codec::ARTNET_MAX_LEN]> =
+    ConstStaticCell::new([0; 4 * codec::ARTNET_MAX_LEN]);
+static ARTNET_BUF: ConstStaticCell<[u8; codec::ARTNET_MAX_LEN]> =
+    ConstStaticCell::new([0; codec::ARTNET_MAX_LEN]);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Sacn([u8; 16]),
+    ArtNet(IpAddress),
+}
+
+/// Listen for sACN and Art-Net on the configured universe, and show it on the pixels.
+#[embassy_executor::task]
+pub async fn dmx_receiver(stack: Stack<'static>) {
+    let sacn_rx_meta = SACN_RX_META.take();
+    let sacn_rx_buf = SACN_RX_BUF.take();
+    let sacn_buf = SACN_BUF.take();
+    let artnet_rx_meta = ARTNET_RX_META.take();
+    let artnet_rx_buf = ARTNET_RX_BUF.take();
+    let artnet_buf = ARTNET_BUF.take();
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let Some(dmx) = config.get().await.dmx else {
+            config.changed().await;
+            continue;
+        };
+        let mut sacn = UdpSocket::new(
+            stack,
+            &mut sacn_rx_meta[..],
+            &mut sacn_rx_buf[..],
+            &mut [],
+            &mut [],
+        );
+        let mut artnet = UdpSocket::new(
+            stack,
+            &mut artnet_rx_meta[..],
+            &mut artnet_rx_buf[..],
+            &mut [],
+            &mut [],
+        );
+        if let Err(e) = sacn
+            .bind(codec::SACN_PORT)
+            .and_then(|_| artnet.bind(codec::ARTNET_PORT))
+        {
+            defmt::error!("DMX: failed to bind: {:?}", e);
+            config.changed().await;
+            continue;
+        }
+        let group = Ipv4Address::from(codec::sacn_multicast_group(dmx.universe));
+        if let Err(e) = stack.join_multicast_group(group) {
+            defmt::warn!("DMX: failed to join {}: {:?}", group, e);
+        }
+        select(
+            receive(&mut sacn, &mut artnet, &dmx, sacn_buf, artnet_buf),
+            config.changed(),
+        )
+        .await;
+        let _ = stack.leave_multicast_group(group);
+        state::set_dmx_pixels(None);
+    }
+}
+
+async fn receive(
+    sacn: &mut UdpSocket<'_>,
+    artnet: &mut UdpSocket<'_>,
+    dmx: &DmxConfig,
+    sacn_buf: &mut [u8],
+    artnet_buf: &mut [u8],
+) -> ! {
+    let mut arbiter = Arbiter::default();
+    let mut last_shown: Option<Instant> = None;
+    loop {
+        let hold = async {
+            match last_shown {
+                Some(at) if dmx.hold_ms > 0 => {
+                    Timer::at(at + Duration::from_millis(dmx.hold_ms.into())).await
+                }
+                _ => core::future::pending().await,
+            }
+        };
+        let received = match select3(sacn.recv_from(sacn_buf), artnet.recv_from(artnet_buf), hold)
+            .await
+        {
+            Either3::First(Ok((len, _))) => match SacnPacket::decode(&sacn_buf[..len]) {
+                Ok(p) if p.universe != dmx.universe || p.preview || p.priority < dmx.priority => {
+                    None
+                }
+                Ok(p) if p.terminated => {
+                    arbiter.terminate(Source::Sacn(p.cid));
+                    None
+                }
+                Ok(p) => Some((Source::Sacn(p.cid), p.priority, p.data)),
+                Err(DecodeError::NotDmx) => None,
+                Err(e) => {
+                    defmt::warn!("DMX: bad sACN packet: {:?}", e);
+                    None
+                }
+            },
+            Either3::Second(Ok((len, meta))) => match ArtDmx::decode(&artnet_buf[..len]) {
+                Ok(p) if p.port_address != dmx.artnet_universe => None,
+                Ok(_) if ARTNET_PRIORITY < dmx.priority => None,
+                Ok(p) => Some((Source::ArtNet(meta.endpoint.addr), ARTNET_PRIORITY, p.data)),
+                Err(DecodeError::NotDmx) => None,
+                Err(e) => {
+                    defmt::warn!("DMX: bad Art-Net packet: {:?}", e);
+                    None
+                }
+            },
+            Either3::First(Err(e)) | Either3::Second(Err(e)) => {
+                defmt::warn!("DMX: receive failed: {:?}", e);
+                None
+            }
+            Either3::Third(()) => {
+                // Nothing's been sent for a while, give the pixels back
+                state::set_dmx_pixels(None);
+                arbiter = Arbiter::default();
+                last_shown = None;
+                None
+            }
+        };
+        if let Some((source, priority, data)) = received {
+            let now = Instant::now();
+            if arbiter.accept(source, priority, now) {
+                state::set_dmx_pixels(Some(codec::map_pixels::<PIXELS>(
+                    data,
+                    dmx.start_address,
+                    dmx.mode,
+                )));
+                last_shown = Some(now);
+            }
+        }
+    }
+}
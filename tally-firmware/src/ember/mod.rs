@@ -0,0 +1,163 @@
+//! Ember+ consumer, following one boolean or integer parameter in a provider's tree, e.g. a
+//! tally parameter on a Lawo console.
+
+use embassy_futures::select::{Either, select};
+use embassy_net::{
+    IpEndpoint, Ipv4Address, Stack,
+    tcp::{self, ConnectError, TcpSocket},
+};
+use embassy_time::Duration;
+use embedded_io_async::Write;
+use heapless::Vec;
+use static_cell::ConstStaticCell;
+use tally_core::ember::{
+    glow::{self, Value},
+    s101::{self, Assembler, Decoder, Frame},
+};
+use tally_rpc::rpc::TallySource;
+
+use crate::{backoff::Backoff, config::CONFIG, state, state::TallyState};
+
+/// Longest frame we take. Providers split messages into frames of around 1 kB.
+const MAX_FRAME: usize = 1100;
+/// Longest message we take. Listing the parameter's siblings can get long, and anything bigger
+/// is dropped.
+const MAX_MESSAGE: usize = 4096;
+
+static RX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+static TX_BUF: ConstStaticCell<[u8; 256]> = ConstStaticCell::new([0; 256]);
+static BUF: ConstStaticCell<[u8; 256]> = ConstStaticCell::new([0; 256]);
+static DECODER: ConstStaticCell<Decoder<MAX_FRAME>> = ConstStaticCell::new(Decoder::new());
+static ASSEMBLER: ConstStaticCell<Assembler<MAX_MESSAGE>> = ConstStaticCell::new(Assembler::new());
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Error {
+    Connect(ConnectError),
+    Tcp(tcp::Error),
+    Closed,
+}
+
+/// Connect to an Ember+ provider and follow the configured parameter, reconnecting when the
+/// connection drops.
+#[embassy_executor::task]
+pub async fn ember_client(stack: Stack<'static>) {
+    let rx_buf = RX_BUF.take();
+    let tx_buf = TX_BUF.take();
+    let buf = BUF.take();
+    let decoder = DECODER.take();
+    let assembler = ASSEMBLER.take();
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let Some(ember) = config.get().await.ember else {
+            state::clear(TallySource::Ember);
+            config.changed().await;
+            continue;
+        };
+        let Some(path) = glow::parse_path(&ember.path) else {
+            defmt::warn!("Ember+: bad parameter path {}", ember.path.as_str());
+            config.changed().await;
+            continue;
+        };
+        let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut tx_buf[..]);
+        // Nothing is pushed unless the parameter changes, apart from keepalives
+        socket.set_keep_alive(Some(Duration::from_secs(5)));
+        socket.set_timeout(Some(Duration::from_secs(15)));
+        let remote = IpEndpoint::new(Ipv4Address::from(ember.ip).into(), ember.port);
+        *decoder = Decoder::new();
+        *assembler = Assembler::new();
+        let result = select(
+            run_session(
+                &mut socket,
+                remote,
+                &path,
+                decoder,
+                assembler,
+                buf,
+                &mut backoff,
+            ),
+            config.changed(),
+        )
+        .await;
+        socket.abort();
+        let _ = socket.flush().await;
+        if let Either::First(e) = result {
+            defmt::warn!("Ember+: session with {} ended: {:?}", remote, e);
+            state::lost(TallySource::Ember);
+            backoff.wait().await;
+        }
+    }
+}
+
+async fn run_session(
+    socket: &mut TcpSocket<'_>,
+    remote: IpEndpoint,
+    path: &[u32],
+    decoder: &mut Decoder<MAX_FRAME>,
+    assembler: &mut Assembler<MAX_MESSAGE>,
+    buf: &mut [u8],
+    backoff: &mut Backoff,
+) -> Error {
+    if let Err(e) = socket.connect(remote).await {
+        return Error::Connect(e);
+    }
+    defmt::info!("Ember+: connected to {}", remote);
+    backoff.reset();
+
+    // Providers send value changes to consumers that have asked for the parent node's
+    // children. Some also want the parameter itself subscribed to.
+    let (parent, _) = path.split_at(path.len() - 1);
+    let requests = [
+        (parent, true, glow::command::GET_DIRECTORY),
+        (path, false, glow::command::SUBSCRIBE),
+    ];
+    for (path, node, command) in requests {
+        let mut message = Vec::<u8, 128>::new();
+        // Paths are limited to a length that fits
+        glow::request(&mut message, path, node, command).unwrap();
+        let len = s101::ember(buf, &message).unwrap();
+        if let Err(e) = socket.write_all(&buf[..len]).await {
+            return Error::Tcp(e);
+        }
+    }
+
+    loop {
+        let len = match socket.read(buf).await {
+            Ok(0) => return Error::Closed,
+            Ok(len) => len,
+            Err(e) => return Error::Tcp(e),
+        };
+        for b in &buf[..len] {
+            match decoder.push(*b) {
+                Some(Ok(Frame::KeepaliveRequest)) => {
+                    let mut response = [0; 16];
+                    let len = s101::keepalive_response(&mut response).unwrap();
+                    if let Err(e) = socket.write_all(&response[..len]).await {
+                        return Error::Tcp(e);
+                    }
+                }
+                Some(Ok(Frame::Ember { flags, payload })) => match assembler.push(flags, payload) {
+                    Some(Ok(message)) => handle_message(message, path),
+                    Some(Err(e)) => defmt::warn!("Ember+: dropped message: {:?}", e),
+                    None => {}
+                },
+                Some(Ok(_)) | None => {}
+                Some(Err(e)) => defmt::warn!("Ember+: bad frame: {:?}", e),
+            }
+        }
+    }
+}
+
+fn handle_message(message: &[u8], path: &[u32]) {
+    match glow::find_value(message, path) {
+        Ok(Some(Value::Boolean(on))) => state::set(TallySource::Ember, TallyState::new(on, false)),
+        Ok(Some(Value::Integer(bits))) => state::set(
+            TallySource::Ember,
+            TallyState::new(bits & 1 != 0, bits & 2 != 0),
+        ),
+        Ok(Some(Value::Other)) => defmt::warn!("Ember+: parameter isn't a boolean or integer"),
+        Ok(None) => {}
+        Err(e) => defmt::warn!("Ember+: bad message: {:?}", e),
+    }
+}
@@ -0,0 +1,48 @@
+//! GPI (contact closure) tally inputs, for mixers without a network tally protocol.
+
+use embassy_futures::select::{select, select_array};
+use embassy_time::Delay;
+use esp_hal::gpio::Input;
+use tally_core::gpi::{Gpi, Levels, levels};
+use tally_rpc::rpc::{GPI_PINS, TallySource};
+
+use crate::{config::CONFIG, state};
+
+/// Drive the tally and talkback from the inputs. With no program or preview input the GPI
+/// source is cleared, so a tally left from before the config changed doesn't stick.
+fn apply(levels: Levels) {
+    match levels.tally {
+        Some(tally) => state::set(TallySource::Gpi, tally),
+        None => state::clear(TallySource::Gpi),
+    }
+    state::set_talkback(levels.talkback);
+}
+
+/// Follow the GPI inputs, as configured.
+#[embassy_executor::task]
+pub async fn gpi_inputs(mut pins: [Input<'static>; GPI_PINS]) {
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let mut gpi_config = config.get().await.gpi.into_iter();
+        let mut gpis = pins.each_mut().map(|pin| {
+            gpi_config
+                .next()
+                .flatten()
+                .map(|c| Gpi::new(pin, c).unwrap())
+        });
+        apply(levels(&gpis));
+        let run = async {
+            loop {
+                select_array(gpis.each_mut().map(|gpi| async move {
+                    match gpi {
+                        Some(gpi) => gpi.changed(&mut Delay).await.unwrap(),
+                        None => core::future::pending().await,
+                    }
+                }))
+                .await;
+                apply(levels(&gpis));
+            }
+        };
+        select(run, config.changed()).await;
+    }
+}
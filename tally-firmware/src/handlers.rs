@@ -0,0 +1,62 @@
+//! Request handlers shared by the postcard-rpc and HTTP APIs, so the two can't drift apart.
+
+use embassy_time::Duration;
+use esp_hal::efuse::Efuse;
+use tally_rpc::rpc::{
+    AppearancePreset, Color, Config, InfoResponse, StatusResponse, Tally, TallyAppearances,
+    TallySource,
+};
+
+use crate::{config, config::CONFIG, state};
+
+pub fn info() -> InfoResponse<'static> {
+    let version = |v: &str| v.parse().unwrap_or(0);
+    InfoResponse {
+        name: "µTally",
+        mac: Efuse::read_base_mac_address(),
+        fw_version: (
+            version(env!("CARGO_PKG_VERSION_MAJOR")),
+            version(env!("CARGO_PKG_VERSION_MINOR")),
+            version(env!("CARGO_PKG_VERSION_PATCH")),
+        ),
+    }
+}
+
+pub fn get_config() -> Config {
+    CONFIG.try_get().unwrap_or_default()
+}
+
+pub fn set_config(config: Config) {
+    config::set(config);
+}
+
+pub fn apply_preset(preset: AppearancePreset) {
+    let mut config = get_config();
+    config.appearances = TallyAppearances::preset(preset);
+    config::set(config);
+}
+
+pub fn set_tally(tally: Tally) {
+    state::set(TallySource::Api, tally.into());
+}
+
+pub fn set_color(color: Option<Color>) {
+    state::set_color_override(TallySource::Api, color);
+}
+
+pub fn set_brightness(brightness: u8) {
+    state::set_brightness(brightness);
+}
+
+pub fn identify() {
+    state::identify(Duration::from_secs(5));
+}
+
+pub fn status() -> StatusResponse {
+    StatusResponse {
+        tally: state::TALLY.try_get().map(Tally::from),
+        color: state::COLOR_OVERRIDE.try_get().flatten(),
+        brightness: state::BRIGHTNESS.try_get().unwrap_or(u8::MAX),
+        lost_sources: state::LOST_SOURCES.try_get().unwrap_or_default(),
+    }
+}
@@ -0,0 +1,289 @@
+//! Web UI and JSON API over HTTP:
+//!
+//! - `GET /` serves the configuration page from `web/index.html`
+//! - `GET /api/info`
+//! - `GET /api/status` for what the device is showing
+//! - `GET`/`PUT /api/config`, or `/api/config/<field>` for one field of it
+//! - `POST /api/tally` with a state name, e.g. `"program"`
+//! - `POST /api/color` with `{"r":255,"g":0,"b":0}`, or `null` to go back to the tally
+//! - `POST /api/brightness` with 0-255
+//! - `POST /api/identify` to flash the LEDs
+//! - `POST /api/preset` with a preset name, e.g. `"ColourBlind"`, to replace the appearances
+
+use core::fmt::Write as _;
+
+use embassy_net::{IpListenEndpoint, Stack, tcp::TcpSocket};
+use embassy_time::Duration;
+use embedded_io_async::Write;
+use heapless::String;
+use serde::{Deserialize, Serialize};
+use static_cell::ConstStaticCell;
+use tally_core::http::request::{self, Method, ParseError, Request, Status};
+use tally_rpc::rpc::Config;
+
+use crate::{backoff::Backoff, handlers};
+
+const HTTP_PORT: u16 = 80;
+const JSON: &str = "application/json";
+
+/// The web UI, gzipped by the build script.
+static INDEX_HTML_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/index.html.gz"));
+
+static RX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+static TX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+// Big enough for a whole config
+static REQUEST_BUF: ConstStaticCell<[u8; 8192]> = ConstStaticCell::new([0; 8192]);
+static RESPONSE_BUF: ConstStaticCell<[u8; 8192]> = ConstStaticCell::new([0; 8192]);
+
+/// Lists every field of [`Config`] that can be read and written on its own. Destructuring
+/// makes sure new fields can't be forgotten.
+macro_rules! config_fields {
+    ($($field:ident),* $(,)?) => {
+        #[allow(dead_code)]
+        fn all_fields_listed(config: Config) {
+            let Config { $($field: _),* } = config;
+        }
+
+        fn get_field(
+            config: &Config,
+            field: &str,
+            out: &mut [u8],
+        ) -> Option<serde_json_core::ser::Result<usize>> {
+            match field {
+                $(stringify!($field) => Some(serde_json_core::to_slice(&config.$field, out)),)*
+                _ => None,
+            }
+        }
+
+        fn set_field(
+            config: &mut Config,
+            field: &str,
+            json: &[u8],
+        ) -> Option<serde_json_core::de::Result<()>> {
+            match field {
+                $(stringify!($field) => Some(
+                    serde_json_core::from_slice(json).map(|(value, _)| config.$field = value),
+                ),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+config_fields!(
+    eth,
+    eth_leds,
+    tsl,
+    atem,
+    vmix,
+    obs,
+    roland,
+    tricaster,
+    ember,
+    mqtt,
+    tally_arbiter,
+    dmx,
+    osc,
+    broadcast,
+    gpi,
+    status,
+    appearances,
+    zones,
+    priorities,
+    mapping,
+    failsafe,
+    brightness,
+);
+
+/// Serve the HTTP API, one connection at a time.
+#[embassy_executor::task]
+pub async fn http_server(stack: Stack<'static>) {
+    let rx_buf = RX_BUF.take();
+    let tx_buf = TX_BUF.take();
+    let buf = REQUEST_BUF.take();
+    let out = RESPONSE_BUF.take();
+    let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(5));
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut tx_buf[..]);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+        if let Err(e) = socket.accept(IpListenEndpoint::from(HTTP_PORT)).await {
+            defmt::warn!("HTTP: accept failed: {:?}", e);
+            backoff.wait().await;
+            continue;
+        }
+        backoff.reset();
+        if let Err(e) = serve(&mut socket, buf, out).await {
+            defmt::warn!("HTTP: connection failed: {:?}", e);
+        }
+        socket.close();
+        let _ = socket.flush().await;
+    }
+}
+
+async fn serve(
+    socket: &mut TcpSocket<'_>,
+    buf: &mut [u8],
+    out: &mut [u8],
+) -> Result<(), embassy_net::tcp::Error> {
+    let mut filled = 0;
+    let request = loop {
+        match Request::parse(&buf[..filled], buf.len()) {
+            Ok(Some(request)) if filled >= request.header_len + request.content_length => {
+                break request;
+            }
+            Ok(_) => {}
+            Err(ParseError::TooLarge) => {
+                return respond(socket, Status::PayloadTooLarge, JSON, None, b"").await;
+            }
+            Err(ParseError::BadRequest) => {
+                return respond(socket, Status::BadRequest, JSON, None, b"").await;
+            }
+        }
+        match socket.read(&mut buf[filled..]).await? {
+            0 => return Ok(()),
+            len => filled += len,
+        }
+    };
+    let body = &buf[request.header_len..request.header_len + request.content_length];
+    defmt::debug!("HTTP: {} {}", request.method, request.path);
+    if request.path == "/" {
+        return match request.method {
+            Method::Get => {
+                let html = "text/html; charset=utf-8";
+                respond(socket, Status::Ok, html, Some("gzip"), INDEX_HTML_GZ).await
+            }
+            _ => respond(socket, Status::MethodNotAllowed, JSON, None, b"").await,
+        };
+    }
+    let (status, len) = route(request.method, request.path, body, out);
+    respond(socket, status, JSON, None, &out[..len]).await
+}
+
+async fn respond(
+    socket: &mut TcpSocket<'_>,
+    status: Status,
+    content_type: &str,
+    content_encoding: Option<&str>,
+    body: &[u8],
+) -> Result<(), embassy_net::tcp::Error> {
+    let mut head = String::<160>::new();
+    request::write_head(
+        &mut head,
+        status,
+        content_type,
+        content_encoding,
+        body.len(),
+    )
+    .unwrap();
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(body).await?;
+    socket.flush().await
+}
+
+/// Serialize `value` into `out` as the response body.
+fn json(value: &impl Serialize, out: &mut [u8]) -> (Status, usize) {
+    match serde_json_core::to_slice(value, out) {
+        Ok(len) => (Status::Ok, len),
+        Err(_) => (Status::InternalServerError, 0),
+    }
+}
+
+/// Parse a request body, or write an error about it into `out`.
+fn parse<'a, T: Deserialize<'a>>(body: &'a [u8], out: &mut [u8]) -> Result<T, (Status, usize)> {
+    serde_json_core::from_slice(body)
+        .map(|(value, _)| value)
+        .map_err(|e| error(e, out))
+}
+
+fn error(e: serde_json_core::de::Error, out: &mut [u8]) -> (Status, usize) {
+    let mut message = String::<64>::new();
+    let _ = write!(message, "{}", e);
+    match serde_json_core::to_slice(&message, out) {
+        Ok(len) => (Status::BadRequest, len),
+        Err(_) => (Status::BadRequest, 0),
+    }
+}
+
+/// Handle a request, writing the response body into `out`.
+fn route(method: Method, path: &str, body: &[u8], out: &mut [u8]) -> (Status, usize) {
+    const NO_CONTENT: (Status, usize) = (Status::NoContent, 0);
+    match (method, path) {
+        (Method::Get, "/api/info") => json(&handlers::info(), out),
+        (Method::Get, "/api/status") => json(&handlers::status(), out),
+        (Method::Get, "/api/config") => json(&handlers::get_config(), out),
+        (Method::Put, "/api/config") => match parse(body, out) {
+            Ok(config) => {
+                handlers::set_config(config);
+                NO_CONTENT
+            }
+            Err(e) => e,
+        },
+        (Method::Post, "/api/tally") => match parse(body, out) {
+            Ok(tally) => {
+                handlers::set_tally(tally);
+                NO_CONTENT
+            }
+            Err(e) => e,
+        },
+        (Method::Post, "/api/color") => match parse(body, out) {
+            Ok(color) => {
+                handlers::set_color(color);
+                NO_CONTENT
+            }
+            Err(e) => e,
+        },
+        (Method::Post, "/api/brightness") => match parse(body, out) {
+            Ok(brightness) => {
+                handlers::set_brightness(brightness);
+                NO_CONTENT
+            }
+            Err(e) => e,
+        },
+        (Method::Post, "/api/identify") => {
+            handlers::identify();
+            NO_CONTENT
+        }
+        (Method::Post, "/api/preset") => match parse(body, out) {
+            Ok(preset) => {
+                handlers::apply_preset(preset);
+                NO_CONTENT
+            }
+            Err(e) => e,
+        },
+        (method, path) => {
+            if let Some(field) = path.strip_prefix("/api/config/") {
+                let mut config = handlers::get_config();
+                match method {
+                    Method::Get => match get_field(&config, field, out) {
+                        Some(Ok(len)) => (Status::Ok, len),
+                        Some(Err(_)) => (Status::InternalServerError, 0),
+                        None => (Status::NotFound, 0),
+                    },
+                    Method::Put => match set_field(&mut config, field, body) {
+                        Some(Ok(())) => {
+                            handlers::set_config(config);
+                            NO_CONTENT
+                        }
+                        Some(Err(e)) => error(e, out),
+                        None => (Status::NotFound, 0),
+                    },
+                    _ => (Status::MethodNotAllowed, 0),
+                }
+            } else if matches!(
+                path,
+                "/api/info"
+                    | "/api/status"
+                    | "/api/config"
+                    | "/api/tally"
+                    | "/api/color"
+                    | "/api/brightness"
+                    | "/api/identify"
+                    | "/api/preset"
+            ) {
+                (Status::MethodNotAllowed, 0)
+            } else {
+                (Status::NotFound, 0)
+            }
+        }
+    }
+}
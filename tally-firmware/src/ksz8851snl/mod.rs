@@ -236,7 +236,9 @@ impl<SPI: SpiDevice> Chip<SPI> {
             .with_receive_tcp_frame_checksum_check_enable(false)
             .with_receive_ip_frame_checksum_check_enable(false)
             .with_receive_flow_control_enable(false)
-            .with_receive_broadcast_enable(false)
+            // Art-Net is usually broadcast, and sACN multicast
+            .with_receive_broadcast_enable(true)
+            .with_receive_multicast_enable(true)
             .with_receive_unicast_enable(true);
         self.dev.write_register(rxcr).await.unwrap();
 
@@ -13,7 +13,19 @@ use smart_leds::{
     hsv::{Hsv, hsv2rgb},
 };
 
-const PIXELS: usize = 10;
+use tally_rpc::rpc::{
+    Animation, Appearance, Config, MappedProtocol, PixelZone, StatusConfig, Tally, TallySource,
+};
+
+use crate::{
+    config::CONFIG,
+    state::{
+        BRIGHTNESS, COLOR_OVERRIDE, DMX_PIXELS, IDENTIFY, OUTPUT_STATUS, OutputStatus, TALKBACK,
+        TALLY, TALLY_SOURCE, TRANSITION, TallyState,
+    },
+};
+
+pub const PIXELS: usize = 10;
 
 trait Animator<const PIXELS: usize> {
     fn next(&mut self) -> [RGB8; PIXELS];
@@ -53,6 +65,82 @@ fn pulse<const PIXELS: usize>(color: Hsv, phase: f32) -> [RGB8; PIXELS] {
     [hsv2rgb(color); PIXELS]
 }
 
+/// Linear crossfade from `from` (level 0) to `to` (level 255).
+fn blend(from: RGB8, to: RGB8, level: u8) -> RGB8 {
+    let mix = |a: u8, b: u8| {
+        ((u16::from(a) * u16::from(u8::MAX - level) + u16::from(b) * u16::from(level))
+            / u16::from(u8::MAX)) as u8
+    };
+    RGB8::new(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
+
+fn scale(color: RGB8, factor: f32) -> RGB8 {
+    let scale = |c: u8| (f32::from(c) * factor) as u8;
+    RGB8::new(scale(color.r), scale(color.g), scale(color.b))
+}
+
+/// The colour `pixel` of the `pixels` showing an appearance should be at time `now`.
+fn animate(appearance: &Appearance, now: Instant, pixel: usize, pixels: usize) -> RGB8 {
+    let color = RGB8::new(appearance.color.r, appearance.color.g, appearance.color.b);
+    let color = scale(color, f32::from(appearance.brightness) / f32::from(u8::MAX));
+    let period = u64::from(appearance.period_ms.max(1));
+    // How far through the period we are, from 0 to 1
+    let position = (now.as_millis() % period) as f32 / period as f32;
+    match appearance.animation {
+        Animation::Solid => color,
+        Animation::Breathe => scale(color, (1.0 - (position * TAU).cos()) / 2.0),
+        Animation::Blink if position < 0.5 => color,
+        Animation::Blink => RGB8::default(),
+        Animation::Chase => {
+            let lit = (pixels / 3).max(1);
+            let head = (position * pixels as f32) as usize;
+            if (pixel + pixels - head) % pixels < lit {
+                color
+            } else {
+                RGB8::default()
+            }
+        }
+    }
+}
+
+/// The pixels `zone` covers, cut short at the end of the strip.
+fn zone_pixels<'a>(zone: &PixelZone, frame: &'a mut [RGB8; PIXELS]) -> &'a mut [RGB8] {
+    let first = usize::from(zone.first_pixel).min(PIXELS);
+    let last = (first + usize::from(zone.pixel_count)).min(PIXELS);
+    &mut frame[first..last]
+}
+
+fn status_appearance(config: &StatusConfig, status: OutputStatus) -> Option<&Appearance> {
+    if status.recording && config.recording.is_some() {
+        config.recording.as_ref()
+    } else if status.streaming {
+        config.streaming.as_ref()
+    } else {
+        None
+    }
+}
+
+/// Whether the tally showing comes from the ATEM, directly or through the mapping, so an ATEM
+/// transition should crossfade it.
+fn follows_atem(config: &Config, source: Option<TallySource>, tally: Option<TallyState>) -> bool {
+    let from_atem = match source {
+        Some(TallySource::Atem) => true,
+        Some(TallySource::Mapping) => config.mapping.as_ref().is_some_and(|mapping| {
+            mapping
+                .selectors
+                .iter()
+                .any(|s| s.protocol == MappedProtocol::Atem)
+        }),
+        _ => false,
+    };
+    // A lost signal or an input that's off has nowhere to fade from
+    from_atem
+        && matches!(
+            tally,
+            Some(TallyState::Preview | TallyState::Program | TallyState::ProgramPreview)
+        )
+}
+
 #[embassy_executor::task]
 pub async fn led_animator(rmt: RMT, pin: AnyPin) {
     let freq = 80u32.MHz();
@@ -61,22 +149,100 @@ pub async fn led_animator(rmt: RMT, pin: AnyPin) {
     let mut led = SmartLedsAdapter::new(rmt.channel0, pin, rmt_buffer);
     let mut start = Instant::now();
     let speed = Duration::from_secs(3);
+    let mut config = CONFIG.receiver().unwrap();
+    let mut led_config = config.get().await;
     loop {
+        if let Some(c) = config.try_changed() {
+            led_config = c;
+        }
+        let status_config = &led_config.status;
         let now = Instant::now();
         if start + speed < now {
             // We've completed a cycle, start again
             start = now;
         }
         let phase: f32 = ((now - start).as_millis() as f32 * TAU) / speed.as_millis() as f32;
-        led.write(pulse::<PIXELS>(
+        let idle = pulse::<1>(
             Hsv {
                 hue: ((phase / TAU) * u8::MAX as f32) as u8,
                 sat: 255,
                 val: 255,
             },
             phase,
-        ))
-        .unwrap();
+        )[0];
+        let color_override = COLOR_OVERRIDE.try_get().flatten();
+        let tally = TALLY.try_get();
+        let transition = TRANSITION
+            .try_get()
+            .flatten()
+            .filter(|_| follows_atem(&led_config, TALLY_SOURCE.try_get().flatten(), tally));
+        let status = OUTPUT_STATUS.try_get().unwrap_or_default();
+        // Pixels outside every zone stay dark
+        let mut frame = [RGB8::default(); PIXELS];
+        for zone in &led_config.zones {
+            let appearances = zone.appearances.as_ref().unwrap_or(&led_config.appearances);
+            let status = zone
+                .status
+                .then(|| status_appearance(status_config, status))
+                .flatten();
+            let pixels = zone_pixels(zone, &mut frame);
+            let count = pixels.len();
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                let show = |appearance: &Appearance| animate(appearance, now, i, count);
+                *pixel = if let Some(appearance) = status {
+                    show(appearance)
+                } else if let Some(color) = color_override {
+                    RGB8::new(color.r, color.g, color.b)
+                } else {
+                    match (tally, transition) {
+                        // Crossfade in step with the transition
+                        (_, Some(level)) => blend(
+                            show(appearances.get(zone.shown(Tally::Preview))),
+                            show(appearances.get(zone.shown(Tally::Program))),
+                            level,
+                        ),
+                        // Including a lost signal, so a stale tally isn't left showing
+                        (Some(state), None) => {
+                            show(appearances.get(zone.shown(Tally::from(state))))
+                        }
+                        (None, None) => match &appearances.idle {
+                            Some(appearance) => show(appearance),
+                            // Show we're alive
+                            None => idle,
+                        },
+                    }
+                };
+            }
+        }
+        // Blink white over everything while someone's calling on talkback
+        if TALKBACK.try_get().unwrap_or(false) && (now.as_millis() / 250) % 2 == 0 {
+            frame = [RGB8::new(255, 255, 255); PIXELS];
+        }
+        // Runtime brightness scales within the configured maximum
+        let brightness = u16::from(BRIGHTNESS.try_get().unwrap_or(u8::MAX))
+            * u16::from(led_config.brightness)
+            / u16::from(u8::MAX);
+        if brightness < u16::from(u8::MAX) {
+            let factor = f32::from(brightness) / f32::from(u8::MAX);
+            frame.iter_mut().for_each(|p| *p = scale(*p, factor));
+        }
+        // The lighting desk owns the pixels
+        if let Some(pixels) = DMX_PIXELS.try_get().flatten() {
+            frame = pixels.map(|c| RGB8::new(c.r, c.g, c.b));
+        }
+        if IDENTIFY
+            .try_get()
+            .flatten()
+            .is_some_and(|until| now < until)
+        {
+            let on = (now.as_millis() / 125) % 2 == 0;
+            frame = [if on {
+                RGB8::new(255, 255, 255)
+            } else {
+                RGB8::default()
+            }; PIXELS];
+        }
+        led.write(frame).unwrap();
         Timer::after(Duration::from_millis(20)).await;
     }
 }
@@ -2,11 +2,32 @@
 #![no_std]
 #![no_main]
 
+mod arbitration;
+mod atem;
+mod backoff;
+mod broadcast;
+mod config;
+mod dmx;
+mod ember;
+mod gpi;
+mod handlers;
+mod http;
 mod ksz8851snl;
 mod leds;
+mod mapping;
+mod mqtt;
+mod obs;
+mod osc;
+mod roland;
 #[cfg(feature = "prpc")]
 mod rpc;
-mod tally;
+mod state;
+mod storage;
+mod tally_arbiter;
+mod tricaster;
+mod tsl;
+mod vmix;
+mod websocket;
 
 use core::u8;
 use embedded_hal_bus::spi::ExclusiveDevice;
@@ -49,6 +70,12 @@ macro_rules! mk_static {
     }};
 }
 
+/// Sockets on the ethernet stack with every protocol enabled: TSL 3.1, TSL 5 UDP
+/// and TCP, HTTP, postcard-rpc, DHCP, ATEM, vMix, OBS, Roland, TriCaster, Ember+, MQTT,
+/// Tally Arbiter, OSC, the tally broadcast, and sACN and Art-Net for DMX. Adding a socket past
+/// this panics, so count any new ones here.
+const ETH_SOCKETS: usize = 18;
+
 const SSID: Option<&str> = option_env!("SSID");
 const PASSWORD: Option<&str> = option_env!("PASSWORD");
 
@@ -62,6 +89,12 @@ async fn main(spawner: Spawner) {
 
     esp_alloc::heap_allocator!(72 * 1024);
 
+    let mut store = storage::ConfigStore::take();
+    let device_config = store.load().unwrap_or_default();
+    let eth_config = embassy_net::Config::from(device_config.eth.clone());
+    config::set(device_config);
+    spawner.must_spawn(storage::persist_config(store));
+
     let timg0 = TimerGroup::new(peripherals.TIMG0);
     let mut rng = Rng::new(peripherals.RNG);
 
@@ -83,7 +116,7 @@ async fn main(spawner: Spawner) {
     // Init network stack
     let (stack, runner) = embassy_net::new(
         wifi_interface,
-        config.clone(),
+        config,
         mk_static!(StackResources<3>, StackResources::<3>::new()),
         seed,
     );
@@ -122,14 +155,42 @@ async fn main(spawner: Spawner) {
     spawner.spawn(eth_driver_runner_task(netrunner)).unwrap();
     let (eth_stack, eth_runner) = embassy_net::new(
         netdev,
-        config,
-        mk_static!(StackResources<3>, StackResources::<3>::new()),
+        eth_config,
+        mk_static!(
+            StackResources<ETH_SOCKETS>,
+            StackResources::<ETH_SOCKETS>::new()
+        ),
         seed,
     );
     spawner.spawn(eth_runner_task(eth_runner)).unwrap();
     spawner.must_spawn(net_task(eth_stack));
+    spawner.must_spawn(eth_config_task(eth_stack));
+    spawner.must_spawn(arbitration::arbitrate());
+    spawner.must_spawn(tsl::tsl_listener(eth_stack));
+    spawner.must_spawn(tsl::tsl5_udp_listener(eth_stack));
+    spawner.must_spawn(tsl::tsl5_tcp_listener(eth_stack));
+    spawner.must_spawn(atem::atem_client(eth_stack));
+    spawner.must_spawn(vmix::vmix_client(eth_stack));
+    spawner.must_spawn(obs::obs_client(eth_stack, rng.clone()));
+    spawner.must_spawn(roland::roland_client(eth_stack));
+    spawner.must_spawn(tricaster::tricaster_client(eth_stack));
+    spawner.must_spawn(ember::ember_client(eth_stack));
+    spawner.must_spawn(mqtt::mqtt_client(eth_stack, mac));
+    spawner.must_spawn(tally_arbiter::tally_arbiter_client(eth_stack, rng.clone()));
+    spawner.must_spawn(dmx::dmx_receiver(eth_stack));
+    spawner.must_spawn(osc::osc_server(eth_stack));
+    spawner.must_spawn(broadcast::broadcast_receiver(eth_stack));
+    spawner.must_spawn(http::http_server(eth_stack));
+    #[cfg(feature = "prpc")]
+    spawner.must_spawn(rpc::rpc_server(eth_stack, spawner));
 
     let mut btn = Input::new(peripherals.GPIO0, Pull::None);
+    // GPI inputs, for contact closures to ground
+    let gpi_pins = [
+        Input::new(peripherals.GPIO4, Pull::Up),
+        Input::new(peripherals.GPIO5, Pull::Up),
+    ];
+    spawner.must_spawn(gpi::gpi_inputs(gpi_pins));
 
     spawner
         .spawn(leds::led_animator(
@@ -141,7 +202,9 @@ async fn main(spawner: Spawner) {
     loop {
         btn.wait_for_low().await;
         defmt::info!("Button!");
+        state::set_button(true);
         btn.wait_for_high().await;
+        state::set_button(false);
     }
 }
 
@@ -151,16 +214,31 @@ async fn net_task(eth_stack: Stack<'static>) {
         defmt::info!("Waiting for ethernet link up...");
         eth_stack.wait_link_up().await;
         defmt::info!("Link up!");
-        defmt::info!("Waiting for dhcp...");
+        defmt::info!("Waiting for an address...");
         eth_stack.wait_config_up().await;
         if let Some(c) = eth_stack.config_v4() {
-            defmt::info!("DHCP: {}", c.address);
+            defmt::info!("Address: {}", c.address);
         }
         eth_stack.wait_link_down().await;
         defmt::info!("Link down :(");
     }
 }
 
+/// Apply the configured addressing to the ethernet stack whenever it changes.
+#[embassy_executor::task]
+async fn eth_config_task(eth_stack: Stack<'static>) {
+    let mut config = config::CONFIG.receiver().unwrap();
+    let mut current = config.get().await.eth;
+    loop {
+        let eth = config.changed().await.eth;
+        if eth != current {
+            defmt::info!("Applying new ethernet config");
+            eth_stack.set_config_v4(embassy_net::Config::from(eth.clone()).ipv4);
+            current = eth;
+        }
+    }
+}
+
 #[embassy_executor::task]
 async fn wifi_runner_task(mut runner: Runner<'static, WifiDevice<'static, WifiStaDevice>>) {
     runner.run().await
@@ -0,0 +1,36 @@
+//! Reporting the inputs of protocols a [`TallyMapping`] mentions, see [`tally_core::mapping`].
+
+use tally_core::mapping;
+use tally_rpc::rpc::{Bus, MappedProtocol, TallyMapping, TallySource};
+
+use crate::{
+    arbitration::{self, Change},
+    state::TallyState,
+};
+
+/// One protocol's selectors in the mapping.
+pub struct Inputs(mapping::Inputs);
+
+impl Inputs {
+    /// `None` when the mapping doesn't mention `protocol`, which then follows its own input.
+    pub fn new(mapping: Option<&TallyMapping>, protocol: MappedProtocol) -> Option<Self> {
+        mapping::Inputs::new(mapping, protocol).map(Self)
+    }
+
+    /// Report the selectors' inputs, looked up with `tally`, for arbitration. Every update is
+    /// reported, changed or not, so the mapping doesn't time out while the protocol is talking.
+    pub fn report(&mut self, tally: impl Fn(u16) -> Option<TallyState>) {
+        let matches = self.0.update(tally);
+        arbitration::report(TallySource::Mapping, Change::Matches(matches));
+    }
+
+    /// The input of the first selector on `bus`.
+    pub fn first_input(&self, bus: Bus) -> Option<u16> {
+        self.0.first_input(bus)
+    }
+
+    /// The protocol lost its signal, so its selectors are unknown until it reports again.
+    pub fn lost(&mut self) {
+        arbitration::report(TallySource::Mapping, Change::Forget(self.0.forget()));
+    }
+}
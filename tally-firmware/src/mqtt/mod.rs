@@ -0,0 +1,377 @@
+use core::{convert::Infallible, fmt::Write as _};
+
+use embassy_futures::select::{Either, Either4, select, select4};
+use embassy_net::{
+    IpEndpoint, Ipv4Address, Stack,
+    tcp::{self, ConnectError, TcpSocket},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Receiver};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_io_async::Write;
+use heapless::String;
+use static_cell::ConstStaticCell;
+use tally_core::mqtt::{
+    discovery::{self, Device, Entity, LightCommand},
+    packet::{self, Connect, DecodeError, Packet, Will},
+    parse_color,
+};
+use tally_rpc::rpc::{Color, MqttConfig, TallySource};
+
+use crate::{
+    backoff::Backoff,
+    config::CONFIG,
+    state::{self, BRIGHTNESS, COLOR_OVERRIDE, TALLY, TALLY_RECEIVERS, TallyState},
+};
+
+/// Keep alive we ask the broker for. We ping at half this.
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const SUBSCRIBE_ID: u16 = 1;
+
+const STATUS_ONLINE: &[u8] = b"online";
+const STATUS_OFFLINE: &[u8] = b"offline";
+
+/// Room for a prefix plus the longest topic suffix.
+type Topic = String<48>;
+
+static RX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+static TX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+static BUF: ConstStaticCell<[u8; 512]> = ConstStaticCell::new([0; 512]);
+// Big enough for a discovery config
+static OUT_BUF: ConstStaticCell<[u8; 768]> = ConstStaticCell::new([0; 768]);
+/// Longest payload we publish.
+const MAX_PAYLOAD: usize = 640;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Error {
+    Connect(ConnectError),
+    Tcp(tcp::Error),
+    Decode(DecodeError),
+    /// The broker refused our connection, with this return code
+    Refused(u8),
+    /// No reply to our last ping
+    Timeout,
+    /// A packet we wanted to send didn't fit our buffer
+    TooLong,
+    Closed,
+}
+
+/// This device's topics, under the configured prefix.
+struct Topics {
+    status: Topic,
+    tally: Topic,
+    tally_set: Topic,
+    color_set: Topic,
+    light: Topic,
+    light_set: Topic,
+    button: Topic,
+}
+
+impl Topics {
+    fn new(prefix: &str) -> Self {
+        let topic = |suffix| {
+            let mut topic = Topic::new();
+            // A too-long prefix just truncates the topic
+            let _ = write!(topic, "{}/{}", prefix, suffix);
+            topic
+        };
+        Self {
+            status: topic("status"),
+            tally: topic("tally"),
+            tally_set: topic("tally/set"),
+            color_set: topic("color/set"),
+            light: topic("light"),
+            light_set: topic("light/set"),
+            button: topic("button"),
+        }
+    }
+}
+
+/// State we publish, which outlives a single session.
+struct Published {
+    tally: Receiver<'static, CriticalSectionRawMutex, TallyState, TALLY_RECEIVERS>,
+    button: Receiver<'static, CriticalSectionRawMutex, bool, TALLY_RECEIVERS>,
+    /// The colour the light entity turns on with
+    light_color: Color,
+}
+
+/// Connect to the configured MQTT broker, taking tally and colour overrides from it and
+/// publishing our status, reconnecting when the connection drops.
+#[embassy_executor::task]
+pub async fn mqtt_client(stack: Stack<'static>, mac: [u8; 6]) {
+    let rx_buf = RX_BUF.take();
+    let tx_buf = TX_BUF.take();
+    let buf = BUF.take();
+    let out = OUT_BUF.take();
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    let mut config = CONFIG.receiver().unwrap();
+    let mut client_id = String::<20>::new();
+    let _ = write!(client_id, "utally-");
+    for b in mac {
+        let _ = write!(client_id, "{:02x}", b);
+    }
+    let mut name = String::<16>::new();
+    let _ = write!(name, "µTally {:02x}{:02x}{:02x}", mac[3], mac[4], mac[5]);
+    let mut published = Published {
+        tally: TALLY.receiver().unwrap(),
+        button: state::BUTTON.receiver().unwrap(),
+        light_color: Color::new(255, 255, 255),
+    };
+    loop {
+        let Some(mqtt) = config.get().await.mqtt else {
+            state::clear(TallySource::Mqtt);
+            config.changed().await;
+            continue;
+        };
+        let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut tx_buf[..]);
+        socket.set_timeout(Some(KEEP_ALIVE));
+        let remote = IpEndpoint::new(Ipv4Address::from(mqtt.ip).into(), mqtt.port);
+        let topics = Topics::new(&mqtt.prefix);
+        let device = Device {
+            id: &client_id,
+            name: &name,
+            prefix: &mqtt.prefix,
+        };
+        let result = select(
+            run_session(
+                &mut socket,
+                remote,
+                &mqtt,
+                &device,
+                &topics,
+                buf,
+                out,
+                &mut backoff,
+                &mut published,
+            ),
+            config.changed(),
+        )
+        .await;
+        if let Either::Second(_) = result {
+            // Leave cleanly, so the broker doesn't publish our will
+            let _ = publish(&mut socket, out, &topics.status, STATUS_OFFLINE, true).await;
+            let _ = socket.write_all(&packet::DISCONNECT).await;
+            socket.close();
+        } else {
+            socket.abort();
+        }
+        let _ = socket.flush().await;
+        // Overrides shouldn't outlive the connection that set them
+        state::set_color_override(TallySource::Mqtt, None);
+        if let Either::First(Err(e)) = result {
+            defmt::warn!("MQTT: session with {} ended: {:?}", remote, e);
+            state::lost(TallySource::Mqtt);
+            backoff.wait().await;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_session(
+    socket: &mut TcpSocket<'_>,
+    remote: IpEndpoint,
+    mqtt: &MqttConfig,
+    device: &Device<'_>,
+    topics: &Topics,
+    buf: &mut [u8],
+    out: &mut [u8],
+    backoff: &mut Backoff,
+    published: &mut Published,
+) -> Result<Infallible, Error> {
+    socket.connect(remote).await.map_err(Error::Connect)?;
+    let username = (!mqtt.username.is_empty()).then_some(mqtt.username.as_str());
+    let connect = Connect {
+        client_id: device.id,
+        keep_alive: KEEP_ALIVE.as_secs() as u16,
+        username,
+        password: username.map(|_| mqtt.password.as_bytes()),
+        will: Some(Will {
+            topic: &topics.status,
+            payload: STATUS_OFFLINE,
+            retain: true,
+        }),
+    };
+    let len = connect.encode(out).ok_or(Error::TooLong)?;
+    socket.write_all(&out[..len]).await.map_err(Error::Tcp)?;
+
+    let mut connected = false;
+    let mut filled = 0;
+    let mut next_ping = Instant::now() + KEEP_ALIVE / 2;
+    let mut awaiting_pong = false;
+    loop {
+        while let Some(len) = packet::packet_len(&buf[..filled])
+            .map_err(Error::Decode)?
+            .filter(|len| *len <= filled)
+        {
+            match Packet::decode(&buf[..len]) {
+                Ok(Packet::ConnAck { return_code: 0 }) => {
+                    defmt::info!("MQTT: connected to {}", remote);
+                    backoff.reset();
+                    connected = true;
+                    let topics_in = [
+                        topics.tally_set.as_str(),
+                        topics.color_set.as_str(),
+                        topics.light_set.as_str(),
+                    ];
+                    let len =
+                        packet::subscribe(out, SUBSCRIBE_ID, &topics_in).ok_or(Error::TooLong)?;
+                    socket.write_all(&out[..len]).await.map_err(Error::Tcp)?;
+                    if mqtt.home_assistant {
+                        announce(socket, out, device).await?;
+                    }
+                    publish(socket, out, &topics.status, STATUS_ONLINE, true).await?;
+                    if let Some(tally) = published.tally.try_get() {
+                        publish_tally(socket, out, topics, tally).await?;
+                    }
+                    let pressed = published.button.try_get().unwrap_or_default();
+                    publish_button(socket, out, topics, pressed).await?;
+                    publish_light(socket, out, topics, published.light_color).await?;
+                }
+                Ok(Packet::ConnAck { return_code }) => return Err(Error::Refused(return_code)),
+                Ok(Packet::Publish { topic, payload }) => {
+                    if topic == topics.tally_set {
+                        if let Some(tally) = core::str::from_utf8(payload)
+                            .ok()
+                            .and_then(TallyState::from_name)
+                        {
+                            state::set(TallySource::Mqtt, tally);
+                        } else {
+                            defmt::warn!("MQTT: bad tally state");
+                        }
+                    } else if topic == topics.color_set {
+                        if let Some(color) = parse_color(payload) {
+                            if let Some(color) = color {
+                                published.light_color = color;
+                            }
+                            state::set_color_override(TallySource::Mqtt, color);
+                            publish_light(socket, out, topics, published.light_color).await?;
+                        } else {
+                            defmt::warn!("MQTT: bad colour");
+                        }
+                    } else if topic == topics.light_set {
+                        if let Some(command) = LightCommand::parse(payload) {
+                            if let Some(brightness) = command.brightness {
+                                state::set_brightness(brightness);
+                            }
+                            if let Some(color) = command.color {
+                                published.light_color = color;
+                            }
+                            let on = command.state != "OFF";
+                            state::set_color_override(
+                                TallySource::Mqtt,
+                                on.then_some(published.light_color),
+                            );
+                            publish_light(socket, out, topics, published.light_color).await?;
+                        } else {
+                            defmt::warn!("MQTT: bad light command");
+                        }
+                    }
+                }
+                Ok(Packet::PingResp) => awaiting_pong = false,
+                Ok(Packet::SubAck { .. } | Packet::Other(_)) => {}
+                Err(e) => defmt::warn!("MQTT: bad packet: {:?}", e),
+            }
+            buf.copy_within(len..filled, 0);
+            filled -= len;
+        }
+        if filled == buf.len() {
+            // Nothing we subscribe to should be this big
+            return Err(Error::TooLong);
+        }
+
+        match select4(
+            socket.read(&mut buf[filled..]),
+            Timer::at(next_ping),
+            published.tally.changed(),
+            published.button.changed(),
+        )
+        .await
+        {
+            Either4::First(Ok(0)) => return Err(Error::Closed),
+            Either4::First(Ok(len)) => filled += len,
+            Either4::First(Err(e)) => return Err(Error::Tcp(e)),
+            Either4::Second(_) => {
+                if awaiting_pong {
+                    return Err(Error::Timeout);
+                }
+                socket
+                    .write_all(&packet::PINGREQ)
+                    .await
+                    .map_err(Error::Tcp)?;
+                awaiting_pong = true;
+                next_ping += KEEP_ALIVE / 2;
+            }
+            // Sent once we're connected
+            Either4::Third(_) | Either4::Fourth(_) if !connected => {}
+            Either4::Third(tally) => publish_tally(socket, out, topics, tally).await?,
+            Either4::Fourth(pressed) => publish_button(socket, out, topics, pressed).await?,
+        }
+    }
+}
+
+async fn publish(
+    socket: &mut TcpSocket<'_>,
+    out: &mut [u8],
+    topic: &str,
+    payload: &[u8],
+    retain: bool,
+) -> Result<(), Error> {
+    let len = packet::publish(out, topic, payload, retain).ok_or(Error::TooLong)?;
+    socket.write_all(&out[..len]).await.map_err(Error::Tcp)
+}
+
+/// Publish Home Assistant discovery configs for all our entities.
+async fn announce(
+    socket: &mut TcpSocket<'_>,
+    out: &mut [u8],
+    device: &Device<'_>,
+) -> Result<(), Error> {
+    for entity in Entity::ALL {
+        let mut topic = String::<96>::new();
+        let mut payload = String::<MAX_PAYLOAD>::new();
+        entity
+            .config_topic(&mut topic, device)
+            .map_err(|_| Error::TooLong)?;
+        entity
+            .config(&mut payload, device)
+            .map_err(|_| Error::TooLong)?;
+        publish(socket, out, &topic, payload.as_bytes(), true).await?;
+    }
+    Ok(())
+}
+
+async fn publish_tally(
+    socket: &mut TcpSocket<'_>,
+    out: &mut [u8],
+    topics: &Topics,
+    tally: TallyState,
+) -> Result<(), Error> {
+    let payload = tally.name().as_bytes();
+    publish(socket, out, &topics.tally, payload, true).await
+}
+
+async fn publish_button(
+    socket: &mut TcpSocket<'_>,
+    out: &mut [u8],
+    topics: &Topics,
+    pressed: bool,
+) -> Result<(), Error> {
+    let payload: &[u8] = if pressed { b"ON" } else { b"OFF" };
+    publish(socket, out, &topics.button, payload, false).await
+}
+
+/// Publish the light entity's state, which is on while a colour override is showing.
+async fn publish_light(
+    socket: &mut TcpSocket<'_>,
+    out: &mut [u8],
+    topics: &Topics,
+    light_color: Color,
+) -> Result<(), Error> {
+    let brightness = BRIGHTNESS.try_get().unwrap_or(u8::MAX);
+    let mut payload = String::<MAX_PAYLOAD>::new();
+    let _ = match COLOR_OVERRIDE.try_get().flatten() {
+        Some(color) => discovery::light_state(&mut payload, true, brightness, color),
+        None => discovery::light_state(&mut payload, false, brightness, light_color),
+    };
+    publish(socket, out, &topics.light, payload.as_bytes(), true).await
+}
@@ -0,0 +1,124 @@
+use embassy_futures::select::{Either, select};
+use embassy_net::{IpEndpoint, Ipv4Address, Stack, tcp::TcpSocket};
+use embassy_time::Duration;
+use esp_hal::rng::Rng;
+use heapless::String;
+use static_cell::ConstStaticCell;
+use tally_core::obs::protocol::{self, Follower, Message};
+use tally_rpc::rpc::{ObsConfig, TallySource};
+
+use crate::{
+    backoff::Backoff,
+    config::CONFIG,
+    state,
+    websocket::{Error, WebSocket},
+};
+
+/// Longest JSON message we send.
+const MAX_MESSAGE: usize = 384;
+
+static RX_BUF: ConstStaticCell<[u8; 2048]> = ConstStaticCell::new([0; 2048]);
+static TX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+// Holds one whole incoming frame. OBS sends some large events (e.g. scene lists) we don't care
+// about, which get skipped.
+static FRAME_BUF: ConstStaticCell<[u8; 4096]> = ConstStaticCell::new([0; 4096]);
+static OUT_BUF: ConstStaticCell<[u8; MAX_MESSAGE + 8]> = ConstStaticCell::new([0; MAX_MESSAGE + 8]);
+
+/// Connect to obs-websocket and follow the configured scene or source's tally, reconnecting
+/// when the connection drops.
+#[embassy_executor::task]
+pub async fn obs_client(stack: Stack<'static>, mut rng: Rng) {
+    let rx_buf = RX_BUF.take();
+    let tx_buf = TX_BUF.take();
+    let buf = FRAME_BUF.take();
+    let out = OUT_BUF.take();
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let Some(obs) = config.get().await.obs else {
+            state::clear(TallySource::Obs);
+            config.changed().await;
+            continue;
+        };
+        let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut tx_buf[..]);
+        socket.set_keep_alive(Some(Duration::from_secs(5)));
+        socket.set_timeout(Some(Duration::from_secs(15)));
+        let remote = IpEndpoint::new(Ipv4Address::from(obs.ip).into(), obs.port);
+        let result = select(
+            run_session(&mut socket, remote, &obs, buf, out, &mut rng, &mut backoff),
+            config.changed(),
+        )
+        .await;
+        socket.abort();
+        let _ = socket.flush().await;
+        if let Either::First(e) = result {
+            defmt::warn!("OBS: session with {} ended: {:?}", remote, e);
+            state::lost(TallySource::Obs);
+            backoff.wait().await;
+        }
+    }
+}
+
+async fn run_session(
+    socket: &mut TcpSocket<'_>,
+    remote: IpEndpoint,
+    obs: &ObsConfig,
+    buf: &mut [u8],
+    out: &mut [u8],
+    rng: &mut Rng,
+    backoff: &mut Backoff,
+) -> Error {
+    let mut ws = WebSocket::new("OBS", socket, out, rng);
+    let filled = match ws
+        .connect(remote, "/", Some("obswebsocket.json"), buf)
+        .await
+    {
+        Ok(filled) => filled,
+        Err(e) => return e,
+    };
+    backoff.reset();
+
+    let mut follower = Follower::new(obs.target.clone());
+    ws.run(buf, filled, async |ws, json| {
+        handle_message(ws, json, obs, &mut follower).await
+    })
+    .await
+}
+
+async fn handle_message(
+    ws: &mut WebSocket<'_, '_>,
+    json: &[u8],
+    obs: &ObsConfig,
+    follower: &mut Follower,
+) -> Result<(), Error> {
+    let message = match Message::parse(json) {
+        Ok(message) => message,
+        Err(_) => {
+            defmt::warn!("OBS: bad message");
+            return Ok(());
+        }
+    };
+    let mut text = String::<MAX_MESSAGE>::new();
+    if let Message::Hello { authentication } = &message {
+        let auth = authentication
+            .as_ref()
+            .map(|auth| protocol::auth_response(&obs.password, auth));
+        protocol::identify(&mut text, auth.as_deref()).unwrap();
+        return ws.send_text(text.as_bytes()).await;
+    }
+    if message == Message::Identified {
+        defmt::info!("OBS: identified");
+    }
+    for request in follower.handle(&message) {
+        text.clear();
+        if request.write(&mut text).is_err() {
+            defmt::warn!("OBS: request too long");
+            continue;
+        }
+        ws.send_text(text.as_bytes()).await?;
+    }
+    if let Some(tally) = follower.changed_tally() {
+        state::set(TallySource::Obs, tally);
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! OSC control of the tally. Messages can arrive alone or in bundles:
+//!
+//! - `/tally/state` with a state name (`off`, `preview`, `program` or `program_preview`), or an
+//!   int with bit 0 for program and bit 1 for preview
+//! - `/tally/color r g b` shows a colour instead of the tally, as ints from 0 to 255 or floats
+//!   from 0 to 1. With no arguments, goes back to the tally.
+//! - `/tally/brightness` with an int from 0 to 255 or a float from 0 to 1
+//! - `/identify` flashes the LEDs, for a number of seconds if given
+
+use embassy_futures::select::select;
+use embassy_net::{
+    Stack,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::Duration;
+use static_cell::ConstStaticCell;
+use tally_core::osc::codec::{self, Arg, Message};
+use tally_rpc::rpc::{Color, TallySource};
+
+use crate::{config::CONFIG, state, state::TallyState};
+
+const IDENTIFY_DEFAULT: Duration = Duration::from_secs(5);
+
+static RX_META: ConstStaticCell<[PacketMetadata; 8]> =
+    ConstStaticCell::new([PacketMetadata::EMPTY; 8]);
+static RX_BUF: ConstStaticCell<[u8; 2048]> = ConstStaticCell::new([0; 2048]);
+static BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+
+/// Listen for OSC commands on the configured port.
+#[embassy_executor::task]
+pub async fn osc_server(stack: Stack<'static>) {
+    let rx_meta = RX_META.take();
+    let rx_buf = RX_BUF.take();
+    let buf = BUF.take();
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let Some(osc) = config.get().await.osc else {
+            state::clear(TallySource::Osc);
+            config.changed().await;
+            continue;
+        };
+        let mut socket = UdpSocket::new(stack, &mut rx_meta[..], &mut rx_buf[..], &mut [], &mut []);
+        if let Err(e) = socket.bind(osc.port) {
+            defmt::error!("OSC: failed to bind: {:?}", e);
+            config.changed().await;
+            continue;
+        }
+        select(receive(&mut socket, buf), config.changed()).await;
+    }
+}
+
+async fn receive(socket: &mut UdpSocket<'_>, buf: &mut [u8]) -> ! {
+    loop {
+        let len = match socket.recv_from(buf).await {
+            Ok((len, _)) => len,
+            Err(e) => {
+                defmt::warn!("OSC: receive failed: {:?}", e);
+                continue;
+            }
+        };
+        if let Err(e) = codec::for_each_message(&buf[..len], &mut handle) {
+            defmt::warn!("OSC: bad packet: {:?}", e);
+        }
+    }
+}
+
+fn handle(message: Message) {
+    match message.address {
+        "/tally/state" => {
+            let tally = match message.arg() {
+                Some(Arg::String(name)) => TallyState::from_name(name),
+                Some(Arg::Int(bits)) => Some(TallyState::new(bits & 1 != 0, bits & 2 != 0)),
+                _ => None,
+            };
+            if let Some(tally) = tally {
+                state::set(TallySource::Osc, tally);
+            } else {
+                defmt::warn!("OSC: bad tally state");
+            }
+        }
+        "/tally/color" => {
+            let mut args = message.args().map(|a| a.scaled(u8::MAX));
+            match (args.next(), args.next(), args.next()) {
+                (None, ..) => state::set_color_override(TallySource::Osc, None),
+                (Some(Some(r)), Some(Some(g)), Some(Some(b))) => {
+                    state::set_color_override(TallySource::Osc, Some(Color::new(r, g, b)))
+                }
+                _ => defmt::warn!("OSC: bad colour"),
+            }
+        }
+        "/tally/brightness" => {
+            if let Some(brightness) = message.arg().and_then(|a| a.scaled(u8::MAX)) {
+                state::set_brightness(brightness);
+            } else {
+                defmt::warn!("OSC: bad brightness");
+            }
+        }
+        "/identify" => state::identify(match message.arg() {
+            Some(Arg::Int(secs)) => Duration::from_secs(secs.clamp(0, 3600) as u64),
+            Some(Arg::Float(secs)) => {
+                Duration::from_millis((secs.clamp(0.0, 3600.0) * 1000.0) as u64)
+            }
+            _ => IDENTIFY_DEFAULT,
+        }),
+        _ => defmt::debug!("OSC: ignoring {}", message.address),
+    }
+}
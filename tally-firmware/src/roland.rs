@@ -0,0 +1,113 @@
+use core::fmt::Write as _;
+
+use embassy_futures::select::select;
+use embassy_net::{
+    IpEndpoint, Ipv4Address, Stack,
+    tcp::{self, ConnectError, TcpSocket},
+};
+use embassy_time::{Duration, Ticker, with_timeout};
+use embedded_io_async::Write;
+use heapless::String;
+use static_cell::ConstStaticCell;
+use tally_core::roland::parse_response;
+use tally_rpc::rpc::TallySource;
+
+use crate::{config::CONFIG, state, state::TallyState};
+
+/// Give up on a request after this long.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Consecutive failed polls after which we stop showing the last tally.
+const MAX_MISSED: u8 = 3;
+
+static RX_BUF: ConstStaticCell<[u8; 512]> = ConstStaticCell::new([0; 512]);
+static TX_BUF: ConstStaticCell<[u8; 128]> = ConstStaticCell::new([0; 128]);
+static BUF: ConstStaticCell<[u8; 512]> = ConstStaticCell::new([0; 512]);
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Error {
+    Connect(ConnectError),
+    Tcp(tcp::Error),
+    Timeout,
+    BadResponse,
+}
+
+/// Poll a Roland V-60HD/V-160HD's Smart Tally for the configured channel's tally.
+#[embassy_executor::task]
+pub async fn roland_client(stack: Stack<'static>) {
+    let rx_buf = RX_BUF.take();
+    let tx_buf = TX_BUF.take();
+    let buf = BUF.take();
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let Some(roland) = config.get().await.roland else {
+            state::clear(TallySource::Roland);
+            config.changed().await;
+            continue;
+        };
+        let remote = IpEndpoint::new(Ipv4Address::from(roland.ip).into(), roland.port);
+        let mut path = String::<32>::new();
+        write!(path, "/tally/{}/status", roland.channel).unwrap();
+        let poll = async {
+            let mut ticker = Ticker::every(Duration::from_millis(roland.poll_ms.max(50).into()));
+            let mut missed = 0;
+            loop {
+                let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut tx_buf[..]);
+                let result =
+                    with_timeout(REQUEST_TIMEOUT, request(&mut socket, remote, &path, buf))
+                        .await
+                        .unwrap_or(Err(Error::Timeout));
+                socket.abort();
+                let _ = socket.flush().await;
+                match result {
+                    Ok(tally) => {
+                        if missed >= MAX_MISSED {
+                            defmt::info!("Roland: {} is back", remote);
+                        }
+                        missed = 0;
+                        state::set(TallySource::Roland, tally);
+                    }
+                    Err(e) => {
+                        missed = missed.saturating_add(1);
+                        if missed == MAX_MISSED {
+                            defmt::warn!("Roland: lost {}: {:?}", remote, e);
+                            state::lost(TallySource::Roland);
+                        }
+                    }
+                }
+                ticker.next().await;
+            }
+        };
+        select(poll, config.changed()).await;
+    }
+}
+
+/// Make one Smart Tally request, reading the response into `buf`.
+async fn request(
+    socket: &mut TcpSocket<'_>,
+    remote: IpEndpoint,
+    path: &str,
+    buf: &mut [u8],
+) -> Result<TallyState, Error> {
+    socket.connect(remote).await.map_err(Error::Connect)?;
+    let mut head = String::<128>::new();
+    write!(
+        head,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, remote.addr
+    )
+    .map_err(|_| Error::BadResponse)?;
+    socket
+        .write_all(head.as_bytes())
+        .await
+        .map_err(Error::Tcp)?;
+    // The switcher closes the connection once it's sent the response
+    let mut filled = 0;
+    while filled < buf.len() {
+        match socket.read(&mut buf[filled..]).await.map_err(Error::Tcp)? {
+            0 => break,
+            len => filled += len,
+        }
+    }
+    parse_response(&buf[..filled]).ok_or(Error::BadResponse)
+}
@@ -1,14 +1,27 @@
+use embassy_executor::Spawner;
+use embassy_net::{IpListenEndpoint, Stack, tcp::TcpSocket};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use postcard_rpc::{
     define_dispatch,
     header::{VarHeader, VarKeyKind},
     server::{
-        Dispatch, Server, WireTx,
+        AsWireRxErrorKind, AsWireTxErrorKind, Dispatch, Server, ServerError, WireTx,
         impls::embassy_net_tcp::dispatch_impl::{
             PacketBuffers, WireRxBuf, WireRxImpl, WireSpawnImpl, WireStorage, WireTxImpl, spawn_fn,
         },
     },
 };
-use tally_rpc::rpc::{ENDPOINTS_LIST, InfoEndpoint, InfoResponse, TOPICS_IN_LIST, TOPICS_OUT_LIST};
+use static_cell::{ConstStaticCell, StaticCell};
+use tally_rpc::rpc::{
+    AppearancePreset, ApplyPresetEndpoint, Color, Config, ENDPOINTS_LIST, GetConfigEndpoint,
+    GetStatusEndpoint, IdentifyEndpoint, InfoEndpoint, InfoResponse, SetBrightnessEndpoint,
+    SetColorEndpoint, SetConfigEndpoint, SetTallyEndpoint, StatusResponse, TOPICS_IN_LIST,
+    TOPICS_OUT_LIST, Tally,
+};
+
+use crate::handlers;
+
+const RPC_PORT: u16 = 1234;
 
 // postcard-rpc stuff
 // We have TCP RPC server for device configuration/monitoring
@@ -29,9 +42,17 @@ define_dispatch! {
     endpoints: {
         list: ENDPOINTS_LIST;
 
-        | EndpointTy    | kind | handler |
-        | ------------- | ---- | ------- |
-        | InfoEndpoint  | async | info_handler |
+        | EndpointTy            | kind      | handler                 |
+        | --------------------- | --------- | ----------------------- |
+        | InfoEndpoint          | blocking  | info_handler            |
+        | GetConfigEndpoint     | blocking  | get_config_handler      |
+        | SetConfigEndpoint     | blocking  | set_config_handler      |
+        | SetTallyEndpoint      | blocking  | set_tally_handler       |
+        | SetColorEndpoint      | blocking  | set_color_handler       |
+        | SetBrightnessEndpoint | blocking  | set_brightness_handler  |
+        | IdentifyEndpoint      | blocking  | identify_handler        |
+        | GetStatusEndpoint     | blocking  | status_handler          |
+        | ApplyPresetEndpoint   | blocking  | apply_preset_handler    |
 
     };
 
@@ -54,17 +75,48 @@ static TCP_BUFS: ConstStaticCell<PacketBuffers<1024, 1024>> =
 static STORAGE: AppStorage = AppStorage::new();
 static RPC_SOCK: StaticCell<TcpSocket> = StaticCell::new();
 
-async fn info_handler(_context: &mut Context, _header: VarHeader, _req: ()) -> InfoResponse {
-    InfoResponse {
-        mac: [0; 6],
-        fw_version: (0, 0, 0),
-    }
+fn info_handler(_context: &mut Context, _header: VarHeader, _req: ()) -> InfoResponse<'static> {
+    handlers::info()
+}
+
+fn get_config_handler(_context: &mut Context, _header: VarHeader, _req: ()) -> Config {
+    handlers::get_config()
 }
 
-pub async fn run_rpc() {
+fn set_config_handler(_context: &mut Context, _header: VarHeader, config: Config) {
+    handlers::set_config(config)
+}
+
+fn set_tally_handler(_context: &mut Context, _header: VarHeader, tally: Tally) {
+    handlers::set_tally(tally)
+}
+
+fn set_color_handler(_context: &mut Context, _header: VarHeader, color: Option<Color>) {
+    handlers::set_color(color)
+}
+
+fn set_brightness_handler(_context: &mut Context, _header: VarHeader, brightness: u8) {
+    handlers::set_brightness(brightness)
+}
+
+fn identify_handler(_context: &mut Context, _header: VarHeader, _req: ()) {
+    handlers::identify()
+}
+
+fn status_handler(_context: &mut Context, _header: VarHeader, _req: ()) -> StatusResponse {
+    handlers::status()
+}
+
+fn apply_preset_handler(_context: &mut Context, _header: VarHeader, preset: AppearancePreset) {
+    handlers::apply_preset(preset)
+}
+
+/// Serve postcard-rpc over TCP for the tools.
+#[embassy_executor::task]
+pub async fn rpc_server(stack: Stack<'static>, spawner: Spawner) {
     let tcp_bufs = TCP_BUFS.take();
     let rpc_sock = RPC_SOCK.init(TcpSocket::new(
-        eth_stack,
+        stack,
         tcp_bufs.rx_buf.as_mut_slice(),
         tcp_bufs.tx_buf.as_mut_slice(),
     ));
@@ -73,7 +125,7 @@ pub async fn run_rpc() {
     let (tx_impl, rx_impl) = STORAGE
         .accept(
             rpc_sock,
-            IpListenEndpoint::from(1234),
+            IpListenEndpoint::from(RPC_PORT),
             bufs.tx_buf.as_mut_slice(),
         )
         .await;
@@ -88,5 +140,16 @@ pub async fn run_rpc() {
         dispatcher,
         vkk,
     );
-    server.run().await;
+    loop {
+        // Returns when the client goes away. `run` starts by waiting for the next connection,
+        // so just go round again.
+        match server.run().await {
+            ServerError::TxFatal(e) => {
+                defmt::info!("RPC: client gone: {:?}", defmt::Debug2Format(&e.as_kind()))
+            }
+            ServerError::RxFatal(e) => {
+                defmt::info!("RPC: client gone: {:?}", defmt::Debug2Format(&e.as_kind()))
+            }
+        }
+    }
 }
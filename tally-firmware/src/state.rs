@@ -0,0 +1,210 @@
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use tally_rpc::rpc::{Color, TallySource};
+
+pub use tally_core::state::TallyState;
+
+use crate::{
+    arbitration::{self, Change},
+    leds::PIXELS,
+};
+
+/// Max number of tasks that can hold a receiver on [`TALLY`].
+pub const TALLY_RECEIVERS: usize = 2;
+
+/// The tally state this device is currently showing, as arbitrated between the sources.
+///
+/// Empty until some source has told us something.
+pub static TALLY: Watch<CriticalSectionRawMutex, TallyState, TALLY_RECEIVERS> = Watch::new();
+
+/// Report `source`'s tally state.
+pub fn set(source: TallySource, state: TallyState) {
+    arbitration::report(source, Change::Tally(Some(state)));
+}
+
+/// Withdraw `source`'s tally state, e.g. because it's been turned off, leaving the tally to the
+/// other sources.
+pub fn clear(source: TallySource) {
+    arbitration::report(source, Change::Tally(None));
+}
+
+/// Report that `source` has lost its signal, e.g. because the connection to the switcher
+/// dropped, so its last tally no longer stands.
+pub fn lost(source: TallySource) {
+    set(source, TallyState::LostSignal);
+}
+
+/// Show the arbitrated tally state. Receivers are only woken if it actually changed.
+pub fn publish(state: Option<TallyState>) {
+    TALLY.sender().send_if_modified(|current| {
+        if *current == state {
+            false
+        } else {
+            *current = state;
+            true
+        }
+    });
+}
+
+/// The source whose tally is showing, as arbitrated. `None` until some source has a tally.
+pub static TALLY_SOURCE: Watch<CriticalSectionRawMutex, Option<TallySource>, TALLY_RECEIVERS> =
+    Watch::new_with(None);
+
+pub fn publish_tally_source(source: Option<TallySource>) {
+    TALLY_SOURCE.sender().send_if_modified(|current| {
+        if *current == Some(source) {
+            false
+        } else {
+            *current = Some(source);
+            true
+        }
+    });
+}
+
+/// Sources that have lost their signal.
+pub static LOST_SOURCES: Watch<
+    CriticalSectionRawMutex,
+    Vec<TallySource, { TallySource::ALL.len() }>,
+    TALLY_RECEIVERS,
+> = Watch::new_with(Vec::new());
+
+pub fn publish_lost_sources(sources: Vec<TallySource, { TallySource::ALL.len() }>) {
+    LOST_SOURCES.sender().send_if_modified(|current| {
+        if current.as_ref() == Some(&sources) {
+            false
+        } else {
+            *current = Some(sources.clone());
+            true
+        }
+    });
+}
+
+/// How far an in-progress transition has taken this device towards program, from 0 (preview)
+/// to 255 (program). `None` when there's no transition involving us.
+pub static TRANSITION: Watch<CriticalSectionRawMutex, Option<u8>, TALLY_RECEIVERS> =
+    Watch::new_with(None);
+
+pub fn set_transition(level: Option<u8>) {
+    TRANSITION.sender().send_if_modified(|current| {
+        if *current == Some(level) {
+            false
+        } else {
+            *current = Some(level);
+            true
+        }
+    });
+}
+
+/// Whether the programme output is being recorded or streamed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OutputStatus {
+    pub recording: bool,
+    pub streaming: bool,
+}
+
+pub static OUTPUT_STATUS: Watch<CriticalSectionRawMutex, OutputStatus, TALLY_RECEIVERS> =
+    Watch::new_with(OutputStatus {
+        recording: false,
+        streaming: false,
+    });
+
+pub fn set_output_status(status: OutputStatus) {
+    OUTPUT_STATUS.sender().send_if_modified(|current| {
+        if *current == Some(status) {
+            false
+        } else {
+            *current = Some(status);
+            true
+        }
+    });
+}
+
+/// A colour to show instead of the tally, e.g. to signal to the operator. Arbitrated between the
+/// sources like the tally.
+pub static COLOR_OVERRIDE: Watch<CriticalSectionRawMutex, Option<Color>, TALLY_RECEIVERS> =
+    Watch::new_with(None);
+
+/// Report `source`'s colour override, or withdraw it with `None`.
+pub fn set_color_override(source: TallySource, color: Option<Color>) {
+    arbitration::report(source, Change::ColorOverride(color));
+}
+
+pub fn publish_color_override(color: Option<Color>) {
+    COLOR_OVERRIDE.sender().send_if_modified(|current| {
+        if *current == Some(color) {
+            false
+        } else {
+            *current = Some(color);
+            true
+        }
+    });
+}
+
+/// Overall LED brightness, applied on top of everything else.
+pub static BRIGHTNESS: Watch<CriticalSectionRawMutex, u8, TALLY_RECEIVERS> =
+    Watch::new_with(u8::MAX);
+
+pub fn set_brightness(brightness: u8) {
+    BRIGHTNESS.sender().send_if_modified(|current| {
+        if *current == Some(brightness) {
+            false
+        } else {
+            *current = Some(brightness);
+            true
+        }
+    });
+}
+
+/// Whether the button on GPIO0 is held down.
+pub static BUTTON: Watch<CriticalSectionRawMutex, bool, TALLY_RECEIVERS> = Watch::new_with(false);
+
+pub fn set_button(pressed: bool) {
+    BUTTON.sender().send_if_modified(|current| {
+        if *current == Some(pressed) {
+            false
+        } else {
+            *current = Some(pressed);
+            true
+        }
+    });
+}
+
+/// Whether someone is asking for the operator's attention over talkback.
+pub static TALKBACK: Watch<CriticalSectionRawMutex, bool, TALLY_RECEIVERS> = Watch::new_with(false);
+
+pub fn set_talkback(active: bool) {
+    TALKBACK.sender().send_if_modified(|current| {
+        if *current == Some(active) {
+            false
+        } else {
+            *current = Some(active);
+            true
+        }
+    });
+}
+
+/// Pixel colours from a lighting desk, which take over from everything else while set.
+pub static DMX_PIXELS: Watch<CriticalSectionRawMutex, Option<[Color; PIXELS]>, TALLY_RECEIVERS> =
+    Watch::new_with(None);
+
+pub fn set_dmx_pixels(pixels: Option<[Color; PIXELS]>) {
+    DMX_PIXELS.sender().send_if_modified(|current| {
+        if *current == Some(pixels) {
+            false
+        } else {
+            *current = Some(pixels);
+            true
+        }
+    });
+}
+
+/// Until when the device should flash to identify itself.
+pub static IDENTIFY: Watch<CriticalSectionRawMutex, Option<Instant>, TALLY_RECEIVERS> =
+    Watch::new_with(None);
+
+/// Flash the LEDs for `duration`, so someone can find the device.
+pub fn identify(duration: Duration) {
+    IDENTIFY.sender().send(Some(Instant::now() + duration));
+}
@@ -0,0 +1,92 @@
+//! Keeping the configuration in flash, so changes survive a reboot.
+
+use embedded_storage::{ReadStorage, Storage};
+use esp_storage::FlashStorage;
+use static_cell::ConstStaticCell;
+use tally_rpc::rpc::Config;
+
+use crate::config::CONFIG;
+
+/// Where the config lives: the nvs partition in espflash's default partition table, which
+/// nothing else on the device uses.
+const OFFSET: u32 = 0x9000;
+/// One flash sector, for the header and the serialised config.
+const LEN: usize = 4096;
+/// Marks a stored config. Change it when the layout of [`Config`] changes, so an old one
+/// isn't misread.
+const MAGIC: [u8; 4] = *b"uTC1";
+/// The magic, then the length of the serialised config as a little-endian `u16`.
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+static BUF: ConstStaticCell<[u8; LEN]> = ConstStaticCell::new([0; LEN]);
+
+pub struct ConfigStore {
+    flash: FlashStorage,
+    buf: &'static mut [u8; LEN],
+}
+
+impl ConfigStore {
+    /// Panics if called twice, as there's only one buffer.
+    pub fn take() -> Self {
+        Self {
+            flash: FlashStorage::new(),
+            buf: BUF.take(),
+        }
+    }
+
+    /// The stored config, or `None` if there isn't one we can read.
+    pub fn load(&mut self) -> Option<Config> {
+        if let Err(e) = self.flash.read(OFFSET, &mut self.buf[..]) {
+            defmt::error!(
+                "Config: failed to read flash: {:?}",
+                defmt::Debug2Format(&e)
+            );
+            return None;
+        }
+        let (header, data) = self.buf.split_at(HEADER_LEN);
+        if header[..MAGIC.len()] != MAGIC {
+            defmt::info!("Config: nothing stored, using the defaults");
+            return None;
+        }
+        let len = usize::from(u16::from_le_bytes([header[4], header[5]]));
+        match data.get(..len).map(postcard::from_bytes) {
+            Some(Ok(config)) => Some(config),
+            _ => {
+                defmt::warn!("Config: stored config is corrupt, using the defaults");
+                None
+            }
+        }
+    }
+
+    fn save(&mut self, config: &Config) {
+        let (header, data) = self.buf.split_at_mut(HEADER_LEN);
+        let len = match postcard::to_slice(config, data) {
+            Ok(data) => data.len(),
+            Err(e) => {
+                defmt::error!("Config: failed to serialise: {:?}", defmt::Debug2Format(&e));
+                return;
+            }
+        };
+        header[..MAGIC.len()].copy_from_slice(&MAGIC);
+        header[MAGIC.len()..].copy_from_slice(&(len as u16).to_le_bytes());
+        if let Err(e) = self.flash.write(OFFSET, &self.buf[..HEADER_LEN + len]) {
+            defmt::error!(
+                "Config: failed to write flash: {:?}",
+                defmt::Debug2Format(&e)
+            );
+        }
+    }
+}
+
+/// Store the config whenever it changes.
+#[embassy_executor::task]
+pub async fn persist_config(mut store: ConfigStore) {
+    let mut config = CONFIG.receiver().unwrap();
+    // What's running now was loaded from flash, or is the default
+    config.get().await;
+    loop {
+        let current = config.changed().await;
+        store.save(&current);
+        defmt::info!("Config: saved");
+    }
+}
@@ -0,0 +1,148 @@
+use embassy_futures::select::{Either, select};
+use embassy_net::{IpEndpoint, Ipv4Address, Stack, tcp::TcpSocket};
+use embassy_time::Duration;
+use esp_hal::rng::Rng;
+use static_cell::ConstStaticCell;
+use tally_core::tally_arbiter::protocol::{self, Event, Listener, Packet};
+use tally_rpc::rpc::{TallyArbiterConfig, TallySource};
+
+use crate::{
+    backoff::Backoff,
+    config::{self, CONFIG},
+    state,
+    websocket::{Error, WebSocket},
+};
+
+/// Longest socket.io message we send.
+const MAX_MESSAGE: usize = 256;
+/// How long to flash for when Tally Arbiter asks.
+const FLASH_TIME: Duration = Duration::from_secs(1);
+
+static RX_BUF: ConstStaticCell<[u8; 2048]> = ConstStaticCell::new([0; 2048]);
+static TX_BUF: ConstStaticCell<[u8; 512]> = ConstStaticCell::new([0; 512]);
+// Holds one whole incoming frame. Events we don't care about, e.g. the full device list, can
+// be bigger and get skipped.
+static FRAME_BUF: ConstStaticCell<[u8; 4096]> = ConstStaticCell::new([0; 4096]);
+static OUT_BUF: ConstStaticCell<[u8; MAX_MESSAGE + 8]> = ConstStaticCell::new([0; MAX_MESSAGE + 8]);
+
+/// Register with Tally Arbiter as a listener client and follow the assigned device's tally,
+/// reconnecting when the connection drops.
+#[embassy_executor::task]
+pub async fn tally_arbiter_client(stack: Stack<'static>, mut rng: Rng) {
+    let rx_buf = RX_BUF.take();
+    let tx_buf = TX_BUF.take();
+    let buf = FRAME_BUF.take();
+    let out = OUT_BUF.take();
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let Some(ta) = config.get().await.tally_arbiter else {
+            state::clear(TallySource::TallyArbiter);
+            config.changed().await;
+            continue;
+        };
+        let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut tx_buf[..]);
+        socket.set_keep_alive(Some(Duration::from_secs(5)));
+        socket.set_timeout(Some(Duration::from_secs(15)));
+        let remote = IpEndpoint::new(Ipv4Address::from(ta.ip).into(), ta.port);
+        let result = select(
+            run_session(&mut socket, remote, &ta, buf, out, &mut rng, &mut backoff),
+            config.changed(),
+        )
+        .await;
+        socket.abort();
+        let _ = socket.flush().await;
+        if let Either::First(e) = result {
+            defmt::warn!("Tally Arbiter: session with {} ended: {:?}", remote, e);
+            state::lost(TallySource::TallyArbiter);
+            backoff.wait().await;
+        }
+    }
+}
+
+async fn run_session(
+    socket: &mut TcpSocket<'_>,
+    remote: IpEndpoint,
+    ta: &TallyArbiterConfig,
+    buf: &mut [u8],
+    out: &mut [u8],
+    rng: &mut Rng,
+    backoff: &mut Backoff,
+) -> Error {
+    let mut ws = WebSocket::new("Tally Arbiter", socket, out, rng);
+    let path = "/socket.io/?EIO=4&transport=websocket";
+    let filled = match ws.connect(remote, path, None, buf).await {
+        Ok(filled) => filled,
+        Err(e) => return e,
+    };
+    backoff.reset();
+
+    let mut listener = Listener::new(&ta.device_id);
+    // Engine.IO sends each packet as one text message
+    ws.run(buf, filled, async |ws, text| {
+        handle_packet(ws, text, ta, &mut listener).await
+    })
+    .await
+}
+
+async fn handle_packet(
+    ws: &mut WebSocket<'_, '_>,
+    text: &[u8],
+    ta: &TallyArbiterConfig,
+    listener: &mut Listener,
+) -> Result<(), Error> {
+    let mut message = [0; MAX_MESSAGE];
+    match Packet::parse(text) {
+        // Join the default namespace
+        Packet::Open => ws.send_text(b"40").await,
+        Packet::Ping => ws.send_text(b"3").await,
+        Packet::Connected => {
+            defmt::info!(
+                "Tally Arbiter: listening for device {}",
+                listener.device_id()
+            );
+            // IDs and names are limited to what fits
+            let len =
+                protocol::listener_connect(&mut message, listener.device_id(), &ta.name).unwrap();
+            ws.send_text(&message[..len]).await?;
+            let len = protocol::bus_options(&mut message).unwrap();
+            ws.send_text(&message[..len]).await
+        }
+        Packet::Disconnected => Err(Error::Closed),
+        Packet::Event { ack, json } => {
+            let event = match Event::parse(json) {
+                Ok(event) => event,
+                Err(_) => {
+                    defmt::warn!("Tally Arbiter: bad event");
+                    return Ok(());
+                }
+            };
+            if let Some(tally) = listener.handle(&event) {
+                state::set(TallySource::TallyArbiter, tally);
+            }
+            match event {
+                Event::Flash => {
+                    state::identify(FLASH_TIME);
+                    if let Some(len) = ack.and_then(|id| protocol::ack(&mut message, id)) {
+                        ws.send_text(&message[..len]).await?;
+                    }
+                }
+                Event::Reassign { old, new } if old == listener.device_id() => {
+                    defmt::info!("Tally Arbiter: reassigned to device {}", new);
+                    if let Ok(len) = protocol::reassigned(&mut message, old, new) {
+                        ws.send_text(&message[..len]).await?;
+                    }
+                    // Saving the new device restarts the session, which registers for it
+                    let mut saved = CONFIG.try_get().unwrap_or_default();
+                    if let (Some(saved_ta), Ok(new)) = (&mut saved.tally_arbiter, new.try_into()) {
+                        saved_ta.device_id = new;
+                        config::set(saved);
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+        Packet::Other => Ok(()),
+    }
+}
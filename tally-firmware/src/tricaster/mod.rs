@@ -0,0 +1,103 @@
+use embassy_futures::select::{Either, select};
+use embassy_net::{
+    IpEndpoint, Ipv4Address, Stack,
+    tcp::{self, ConnectError, TcpSocket},
+};
+use embassy_time::Duration;
+use embedded_io_async::Write;
+use static_cell::ConstStaticCell;
+use tally_core::tricaster::xml::{Follower, Tokenizer};
+use tally_rpc::rpc::TallySource;
+
+use crate::{backoff::Backoff, config::CONFIG, state};
+
+/// Longest tag we keep. `shortcut_state` tags listing many sources can get long.
+const MAX_TAG: usize = 512;
+
+static RX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+static TX_BUF: ConstStaticCell<[u8; 128]> = ConstStaticCell::new([0; 128]);
+static BUF: ConstStaticCell<[u8; 256]> = ConstStaticCell::new([0; 256]);
+static TOKENIZER: ConstStaticCell<Tokenizer<MAX_TAG>> = ConstStaticCell::new(Tokenizer::new());
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Error {
+    Connect(ConnectError),
+    Tcp(tcp::Error),
+    Closed,
+}
+
+/// Connect to a TriCaster's automation protocol and follow the configured input's tally,
+/// reconnecting when the connection drops.
+#[embassy_executor::task]
+pub async fn tricaster_client(stack: Stack<'static>) {
+    let rx_buf = RX_BUF.take();
+    let tx_buf = TX_BUF.take();
+    let buf = BUF.take();
+    let tokenizer = TOKENIZER.take();
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let Some(tricaster) = config.get().await.tricaster else {
+            state::clear(TallySource::Tricaster);
+            config.changed().await;
+            continue;
+        };
+        let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut tx_buf[..]);
+        // Nothing is pushed unless the tally changes
+        socket.set_keep_alive(Some(Duration::from_secs(5)));
+        socket.set_timeout(Some(Duration::from_secs(15)));
+        let remote = IpEndpoint::new(Ipv4Address::from(tricaster.ip).into(), tricaster.port);
+        *tokenizer = Tokenizer::new();
+        let result = select(
+            run_session(
+                &mut socket,
+                remote,
+                Follower::new(&tricaster.input),
+                tokenizer,
+                buf,
+                &mut backoff,
+            ),
+            config.changed(),
+        )
+        .await;
+        socket.abort();
+        let _ = socket.flush().await;
+        if let Either::First(e) = result {
+            defmt::warn!("TriCaster: session with {} ended: {:?}", remote, e);
+            state::lost(TallySource::Tricaster);
+            backoff.wait().await;
+        }
+    }
+}
+
+async fn run_session(
+    socket: &mut TcpSocket<'_>,
+    remote: IpEndpoint,
+    mut follower: Follower<'_>,
+    tokenizer: &mut Tokenizer<MAX_TAG>,
+    buf: &mut [u8],
+    backoff: &mut Backoff,
+) -> Error {
+    if let Err(e) = socket.connect(remote).await {
+        return Error::Connect(e);
+    }
+    defmt::info!("TriCaster: connected to {}", remote);
+    backoff.reset();
+    // The current states get pushed straight away, then again whenever they change
+    if let Err(e) = socket.write_all(b"<register name=\"NTK_states\"/>\n").await {
+        return Error::Tcp(e);
+    }
+    loop {
+        let len = match socket.read(buf).await {
+            Ok(0) => return Error::Closed,
+            Ok(len) => len,
+            Err(e) => return Error::Tcp(e),
+        };
+        for b in &buf[..len] {
+            if let Some(tally) = tokenizer.push(*b).and_then(|tag| follower.handle(&tag)) {
+                state::set(TallySource::Tricaster, tally);
+            }
+        }
+    }
+}
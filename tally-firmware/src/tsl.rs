@@ -0,0 +1,202 @@
+use embassy_futures::select::{Either, select};
+use embassy_net::{
+    Stack,
+    tcp::TcpSocket,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::Duration;
+use static_cell::ConstStaticCell;
+use tally_core::tally::{TSL5_BROADCAST, TSL5Deframer, TSL5Packet, TSLMessage};
+use tally_rpc::rpc::{MappedProtocol, TallySource, TslConfig};
+
+use crate::{backoff::Backoff, config::CONFIG, mapping::Inputs, state};
+
+/// Largest TSL v5.0 packet we can reassemble from a TCP stream.
+const TSL5_MAX_PACKET: usize = 1024;
+
+static RX_META: ConstStaticCell<[PacketMetadata; 4]> =
+    ConstStaticCell::new([PacketMetadata::EMPTY; 4]);
+static RX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+static BUF: ConstStaticCell<[u8; 512]> = ConstStaticCell::new([0; 512]);
+
+static V5_UDP_RX_META: ConstStaticCell<[PacketMetadata; 4]> =
+    ConstStaticCell::new([PacketMetadata::EMPTY; 4]);
+static V5_UDP_RX_BUF: ConstStaticCell<[u8; 2048]> = ConstStaticCell::new([0; 2048]);
+static V5_UDP_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+
+static V5_TCP_RX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+static V5_TCP_BUF: ConstStaticCell<[u8; 256]> = ConstStaticCell::new([0; 256]);
+static V5_DEFRAMER: ConstStaticCell<TSL5Deframer<TSL5_MAX_PACKET>> =
+    ConstStaticCell::new(TSL5Deframer::new());
+
+/// Listen for TSL UMD v3.1/v4.0 messages and follow the configured display address.
+///
+/// A datagram may contain several back-to-back messages, possibly of mixed versions.
+#[embassy_executor::task]
+pub async fn tsl_listener(stack: Stack<'static>) {
+    let rx_meta = RX_META.take();
+    let rx_buf = RX_BUF.take();
+    let buf = BUF.take();
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let current = config.get().await;
+        let tsl = current.tsl;
+        let mut inputs = Inputs::new(current.mapping.as_ref(), MappedProtocol::Tsl);
+        let mut socket = UdpSocket::new(stack, &mut rx_meta[..], &mut rx_buf[..], &mut [], &mut []);
+        if let Err(e) = socket.bind(tsl.port) {
+            defmt::error!("TSL: failed to bind port {}: {:?}", tsl.port, e);
+            config.changed().await;
+            continue;
+        }
+        defmt::info!("TSL: listening on UDP port {}", tsl.port);
+        loop {
+            match select(socket.recv_from(buf), config.changed()).await {
+                Either::First(Ok((len, _))) => {
+                    let mut rest = &buf[..len];
+                    while !rest.is_empty() {
+                        match TSLMessage::decode(rest) {
+                            Ok((msg, len)) => {
+                                let address = msg.address();
+                                match &mut inputs {
+                                    Some(inputs) => inputs.report(|a| {
+                                        (a == u16::from(address))
+                                            .then(|| msg.tally(tsl.colour_tally))
+                                    }),
+                                    None if address == tsl.address => {
+                                        state::set(TallySource::Tsl, msg.tally(tsl.colour_tally))
+                                    }
+                                    None => {}
+                                }
+                                rest = &rest[len..];
+                            }
+                            Err(e) => {
+                                defmt::warn!("TSL: bad message: {:?}", e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Either::First(Err(e)) => defmt::warn!("TSL: receive error: {:?}", e),
+                // Config changed - rebind in case the port did
+                Either::Second(_) => break,
+            }
+        }
+    }
+}
+
+/// Apply any display messages in a TSL v5.0 packet that are addressed to us, or to the
+/// mapping's indexes if it mentions TSL.
+fn handle_tsl5(packet: &[u8], tsl: &TslConfig, inputs: &mut Option<Inputs>) {
+    let packet = match TSL5Packet::decode(packet) {
+        Ok(packet) => packet,
+        Err(e) => {
+            defmt::warn!("TSL5: bad packet: {:?}", e);
+            return;
+        }
+    };
+    if packet.screen != tsl.screen && packet.screen != TSL5_BROADCAST {
+        return;
+    }
+    for msg in packet.messages() {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => {
+                defmt::warn!("TSL5: bad display message: {:?}", e);
+                continue;
+            }
+        };
+        let for_index = |i| msg.index == i || msg.index == TSL5_BROADCAST;
+        match inputs {
+            Some(inputs) => inputs.report(|i| for_index(i).then(|| msg.tally(tsl.colour_tally))),
+            None if for_index(tsl.index) => {
+                state::set(TallySource::Tsl, msg.tally(tsl.colour_tally))
+            }
+            None => {}
+        }
+    }
+}
+
+/// Listen for TSL UMD v5.0 packets over UDP.
+#[embassy_executor::task]
+pub async fn tsl5_udp_listener(stack: Stack<'static>) {
+    let rx_meta = V5_UDP_RX_META.take();
+    let rx_buf = V5_UDP_RX_BUF.take();
+    let buf = V5_UDP_BUF.take();
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let current = config.get().await;
+        let tsl = current.tsl;
+        let mut inputs = Inputs::new(current.mapping.as_ref(), MappedProtocol::Tsl);
+        let mut socket = UdpSocket::new(stack, &mut rx_meta[..], &mut rx_buf[..], &mut [], &mut []);
+        if let Err(e) = socket.bind(tsl.v5_port) {
+            defmt::error!("TSL5: failed to bind UDP port {}: {:?}", tsl.v5_port, e);
+            config.changed().await;
+            continue;
+        }
+        defmt::info!("TSL5: listening on UDP port {}", tsl.v5_port);
+        loop {
+            match select(socket.recv_from(buf), config.changed()).await {
+                Either::First(Ok((len, _))) => handle_tsl5(&buf[..len], &tsl, &mut inputs),
+                Either::First(Err(e)) => defmt::warn!("TSL5: receive error: {:?}", e),
+                Either::Second(_) => break,
+            }
+        }
+    }
+}
+
+/// Accept a single TCP connection at a time carrying DLE/STX framed TSL UMD v5.0 packets.
+#[embassy_executor::task]
+pub async fn tsl5_tcp_listener(stack: Stack<'static>) {
+    let rx_buf = V5_TCP_RX_BUF.take();
+    let buf = V5_TCP_BUF.take();
+    let deframer = V5_DEFRAMER.take();
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let current = config.get().await;
+        let mut tsl = current.tsl;
+        let mut inputs = Inputs::new(current.mapping.as_ref(), MappedProtocol::Tsl);
+        let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut []);
+        match select(socket.accept(tsl.v5_port), config.changed()).await {
+            Either::First(Ok(())) => backoff.reset(),
+            Either::First(Err(e)) => {
+                defmt::warn!("TSL5: accept error: {:?}", e);
+                // Don't spin if it keeps failing, e.g. on a bad port, but pick up a fix straight away
+                select(backoff.wait(), config.changed()).await;
+                continue;
+            }
+            Either::Second(_) => continue,
+        }
+        defmt::info!("TSL5: connection from {:?}", socket.remote_endpoint());
+        deframer.reset();
+        loop {
+            match select(socket.read(buf), config.changed()).await {
+                Either::First(Ok(0)) => break,
+                Either::First(Ok(len)) => {
+                    for b in &buf[..len] {
+                        if let Some(packet) = deframer.push(*b) {
+                            handle_tsl5(packet, &tsl, &mut inputs);
+                        }
+                    }
+                }
+                Either::First(Err(e)) => {
+                    defmt::warn!("TSL5: read error: {:?}", e);
+                    break;
+                }
+                Either::Second(c) => {
+                    if c.tsl.v5_port != tsl.v5_port {
+                        break;
+                    }
+                    inputs = Inputs::new(c.mapping.as_ref(), MappedProtocol::Tsl);
+                    tsl = c.tsl;
+                }
+            }
+        }
+        defmt::info!("TSL5: connection closed");
+        if let Some(inputs) = &mut inputs {
+            inputs.lost();
+        }
+        socket.abort();
+        let _ = socket.flush().await;
+    }
+}
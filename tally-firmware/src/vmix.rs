@@ -0,0 +1,114 @@
+use embassy_futures::select::{Either, select};
+use embassy_net::{
+    IpEndpoint, Ipv4Address, Stack,
+    tcp::{self, ConnectError, TcpSocket},
+};
+use embassy_time::Duration;
+use embedded_io_async::Write;
+use static_cell::ConstStaticCell;
+use tally_core::vmix::{LineBuffer, parse_tally};
+use tally_rpc::rpc::{MappedProtocol, TallySource};
+
+use crate::{backoff::Backoff, config::CONFIG, mapping::Inputs, state};
+
+/// Longest line we keep. Longer tally lines are truncated, which only loses inputs past this.
+const MAX_LINE: usize = 256;
+
+static RX_BUF: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
+static TX_BUF: ConstStaticCell<[u8; 128]> = ConstStaticCell::new([0; 128]);
+static BUF: ConstStaticCell<[u8; 256]> = ConstStaticCell::new([0; 256]);
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Error {
+    Connect(ConnectError),
+    Tcp(tcp::Error),
+    Closed,
+}
+
+/// Connect to vMix's TCP API and follow the configured input's tally, reconnecting when the
+/// connection drops.
+#[embassy_executor::task]
+pub async fn vmix_client(stack: Stack<'static>) {
+    let rx_buf = RX_BUF.take();
+    let tx_buf = TX_BUF.take();
+    let buf = BUF.take();
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    let mut config = CONFIG.receiver().unwrap();
+    loop {
+        let current = config.get().await;
+        let Some(vmix) = current.vmix else {
+            state::clear(TallySource::Vmix);
+            config.changed().await;
+            continue;
+        };
+        let mut inputs = Inputs::new(current.mapping.as_ref(), MappedProtocol::Vmix);
+        let mut socket = TcpSocket::new(stack, &mut rx_buf[..], &mut tx_buf[..]);
+        // vMix doesn't send anything unless the tally changes, so use keepalives to notice it
+        // going away.
+        socket.set_keep_alive(Some(Duration::from_secs(5)));
+        socket.set_timeout(Some(Duration::from_secs(15)));
+        let remote = IpEndpoint::new(Ipv4Address::from(vmix.ip).into(), vmix.port);
+        let result = select(
+            run_session(
+                &mut socket,
+                remote,
+                vmix.input,
+                &mut inputs,
+                buf,
+                &mut backoff,
+            ),
+            config.changed(),
+        )
+        .await;
+        socket.abort();
+        let _ = socket.flush().await;
+        if let Either::First(e) = result {
+            defmt::warn!("vMix: session with {} ended: {:?}", remote, e);
+            state::lost(TallySource::Vmix);
+            if let Some(inputs) = &mut inputs {
+                inputs.lost();
+            }
+            backoff.wait().await;
+        }
+    }
+}
+
+async fn run_session(
+    socket: &mut TcpSocket<'_>,
+    remote: IpEndpoint,
+    input: u16,
+    inputs: &mut Option<Inputs>,
+    buf: &mut [u8],
+    backoff: &mut Backoff,
+) -> Error {
+    if let Err(e) = socket.connect(remote).await {
+        return Error::Connect(e);
+    }
+    defmt::info!("vMix: connected to {}", remote);
+    backoff.reset();
+    if let Err(e) = socket.write_all(b"SUBSCRIBE TALLY\r\n").await {
+        return Error::Tcp(e);
+    }
+    let mut lines = LineBuffer::<MAX_LINE>::new();
+    loop {
+        let len = match socket.read(buf).await {
+            Ok(0) => return Error::Closed,
+            Ok(len) => len,
+            Err(e) => return Error::Tcp(e),
+        };
+        for b in &buf[..len] {
+            let Some(line) = lines.push(*b) else {
+                continue;
+            };
+            match inputs {
+                Some(inputs) => inputs.report(|i| parse_tally(line, i)),
+                None => {
+                    if let Some(tally) = parse_tally(line, input) {
+                        state::set(TallySource::Vmix, tally);
+                    }
+                }
+            }
+        }
+    }
+}
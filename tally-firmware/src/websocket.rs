@@ -0,0 +1,164 @@
+//! A WebSocket client connection over TCP, for the protocols that talk WebSocket. They only
+//! deal with the text messages, this handles the upgrade and everything else.
+
+use core::fmt::Write as _;
+
+use embassy_net::{
+    IpEndpoint,
+    tcp::{self, ConnectError, TcpSocket},
+};
+use embedded_io_async::Write;
+use esp_hal::rng::Rng;
+use heapless::String;
+use tally_core::websocket::{self, FrameHeader, opcode};
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    Connect(ConnectError),
+    Tcp(tcp::Error),
+    /// The server didn't accept the WebSocket upgrade
+    Handshake,
+    Closed,
+}
+
+pub struct WebSocket<'a, 's> {
+    /// Who we're talking to, for logging
+    name: &'static str,
+    socket: &'a mut TcpSocket<'s>,
+    /// Holds an outgoing frame
+    out: &'a mut [u8],
+    rng: &'a mut Rng,
+}
+
+impl<'a, 's> WebSocket<'a, 's> {
+    pub fn new(
+        name: &'static str,
+        socket: &'a mut TcpSocket<'s>,
+        out: &'a mut [u8],
+        rng: &'a mut Rng,
+    ) -> Self {
+        Self {
+            name,
+            socket,
+            out,
+            rng,
+        }
+    }
+
+    /// Connect to `remote` and upgrade to a WebSocket at `path`, asking for `protocol` if set.
+    /// Anything received after the server's response is left at the start of `buf`, and its
+    /// length returned.
+    pub async fn connect(
+        &mut self,
+        remote: IpEndpoint,
+        path: &str,
+        protocol: Option<&str>,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.socket.connect(remote).await.map_err(Error::Connect)?;
+
+        let mut key = [0; 16];
+        self.rng.read(&mut key);
+        let mut host = String::<24>::new();
+        let _ = write!(host, "{}", remote);
+        let mut request = String::<256>::new();
+        websocket::handshake(&mut request, &host, path, protocol, key).unwrap();
+        self.socket
+            .write_all(request.as_bytes())
+            .await
+            .map_err(Error::Tcp)?;
+        let mut filled = 0;
+        let end = loop {
+            if filled == buf.len() {
+                return Err(Error::Handshake);
+            }
+            match self.socket.read(&mut buf[filled..]).await {
+                Ok(0) => return Err(Error::Closed),
+                Ok(len) => filled += len,
+                Err(e) => return Err(Error::Tcp(e)),
+            }
+            match websocket::handshake_response(&buf[..filled]) {
+                Some((end, true)) => break end,
+                Some((_, false)) => return Err(Error::Handshake),
+                None => {}
+            }
+        };
+        // Anything after the response is the start of the first frame
+        buf.copy_within(end..filled, 0);
+        defmt::info!("{}: connected to {}", self.name, remote);
+        Ok(filled - end)
+    }
+
+    /// Receive frames into `buf`, which starts with `filled` bytes left from [`Self::connect`],
+    /// passing each text message to `handle` until the connection ends. Frames too big for
+    /// `buf` are skipped.
+    pub async fn run(
+        &mut self,
+        buf: &mut [u8],
+        mut filled: usize,
+        mut handle: impl AsyncFnMut(&mut Self, &[u8]) -> Result<(), Error>,
+    ) -> Error {
+        // Bytes left of a frame too big to buffer
+        let mut skip = 0;
+        loop {
+            while let Some(header) = FrameHeader::decode(&buf[..filled]) {
+                let len = usize::try_from(header.payload_len)
+                    .unwrap_or(usize::MAX)
+                    .saturating_add(header.header_len);
+                if len > buf.len() {
+                    defmt::warn!("{}: skipping {} byte frame", self.name, len);
+                    skip = len - filled;
+                    filled = 0;
+                    break;
+                }
+                if filled < len {
+                    break;
+                }
+                let payload = &buf[header.header_len..len];
+                // Neither obs-websocket nor Engine.IO fragment their messages
+                match header.opcode {
+                    opcode::TEXT if header.fin => {
+                        if let Err(e) = handle(self, payload).await {
+                            return e;
+                        }
+                    }
+                    opcode::PING => {
+                        if let Err(e) = self.send(opcode::PONG, payload).await {
+                            return e;
+                        }
+                    }
+                    opcode::CLOSE => return Error::Closed,
+                    _ => {}
+                }
+                buf.copy_within(len..filled, 0);
+                filled -= len;
+            }
+
+            let len = match self.socket.read(&mut buf[filled..]).await {
+                Ok(0) => return Error::Closed,
+                Ok(len) => len,
+                Err(e) => return Error::Tcp(e),
+            };
+            let skipped = len.min(skip);
+            skip -= skipped;
+            buf.copy_within(filled + skipped..filled + len, filled);
+            filled += len - skipped;
+        }
+    }
+
+    pub async fn send_text(&mut self, text: &[u8]) -> Result<(), Error> {
+        self.send(opcode::TEXT, text).await
+    }
+
+    async fn send(&mut self, opcode: u8, payload: &[u8]) -> Result<(), Error> {
+        let mut mask = [0; 4];
+        self.rng.read(&mut mask);
+        // Messages are limited to what fits
+        let len = websocket::encode_frame(self.out, opcode, payload, mask).unwrap();
+        self.socket
+            .write_all(&self.out[..len])
+            .await
+            .map_err(Error::Tcp)
+    }
+}
@@ -0,0 +1,179 @@
+//! Native µTally tally broadcast, sent over UDP multicast so one packet drives a whole fleet.
+//!
+//! A packet is [`MAGIC`], the [`VERSION`] byte, then a postcard-encoded [`TallyBroadcast`].
+//! Every packet carries the full state, so senders repeat it regularly and lost packets don't
+//! matter.
+
+use heapless::{String, Vec};
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+use crate::rpc::Color;
+
+pub const MAGIC: [u8; 4] = *b"uTly";
+/// Bumped whenever [`TallyBroadcast`] changes incompatibly.
+pub const VERSION: u8 = 1;
+/// The default multicast group.
+pub const GROUP: [u8; 4] = [239, 255, 117, 116];
+pub const PORT: u16 = 40600;
+/// Number of cameras a broadcast covers.
+pub const CAMERAS: usize = 64;
+/// Most colour overrides one broadcast can carry.
+pub const MAX_COLORS: usize = 16;
+/// Longest size of a whole packet.
+pub const MAX_LEN: usize = 256;
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub struct TallyBroadcast {
+    /// Incremented for every packet, so receivers can drop ones that arrive out of order
+    pub seq: u32,
+    /// Bit `n` set when camera `n + 1` is on program
+    pub program: u64,
+    /// Bit `n` set when camera `n + 1` is on preview
+    pub preview: u64,
+    /// Colours to show instead of the tally. Cameras not listed go back to their tally colour.
+    pub colors: Vec<ColorOverride, MAX_COLORS>,
+    /// Free text for the fleet, e.g. the show name, for devices that can show it
+    pub text: Option<String<64>>,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorOverride {
+    /// Camera number, from 1
+    pub camera: u8,
+    pub color: Color,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Not a µTally broadcast
+    NotBroadcast,
+    /// Sent with a protocol version we don't understand
+    Version(u8),
+    Encoding,
+}
+
+impl TallyBroadcast {
+    /// The `(program, preview)` state of `camera`, numbered from 1.
+    pub fn tally(&self, camera: u8) -> Option<(bool, bool)> {
+        let bit = 1u64.checked_shl(u32::from(camera).checked_sub(1)?)?;
+        Some((self.program & bit != 0, self.preview & bit != 0))
+    }
+
+    /// Put `camera`, numbered from 1, on program and/or preview.
+    pub fn set_tally(&mut self, camera: u8, program: bool, preview: bool) {
+        let Some(bit) = u32::from(camera)
+            .checked_sub(1)
+            .and_then(|n| 1u64.checked_shl(n))
+        else {
+            return;
+        };
+        self.program = if program {
+            self.program | bit
+        } else {
+            self.program & !bit
+        };
+        self.preview = if preview {
+            self.preview | bit
+        } else {
+            self.preview & !bit
+        };
+    }
+
+    pub fn color(&self, camera: u8) -> Option<Color> {
+        self.colors
+            .iter()
+            .find(|c| c.camera == camera)
+            .map(|c| c.color)
+    }
+
+    /// Write the packet into `buf`, returning the part used.
+    pub fn encode<'a>(&self, buf: &'a mut [u8]) -> Result<&'a mut [u8], Error> {
+        let header_len = MAGIC.len() + 1;
+        if buf.len() < header_len {
+            return Err(Error::Encoding);
+        }
+        let (header, body) = buf.split_at_mut(header_len);
+        header[..MAGIC.len()].copy_from_slice(&MAGIC);
+        header[MAGIC.len()] = VERSION;
+        let len = postcard::to_slice(self, body)
+            .map_err(|_| Error::Encoding)?
+            .len();
+        Ok(&mut buf[..header_len + len])
+    }
+
+    pub fn decode(packet: &[u8]) -> Result<Self, Error> {
+        let rest = packet.strip_prefix(&MAGIC).ok_or(Error::NotBroadcast)?;
+        match rest.split_first() {
+            Some((&VERSION, body)) => postcard::from_bytes(body).map_err(|_| Error::Encoding),
+            Some((version, _)) => Err(Error::Version(*version)),
+            None => Err(Error::NotBroadcast),
+        }
+    }
+}
+
+/// Whether sequence number `seq` comes after `last`, allowing for wrapping.
+pub fn is_newer(seq: u32, last: u32) -> bool {
+    (seq.wrapping_sub(last) as i32) > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut broadcast = TallyBroadcast {
+            seq: 7,
+            program: 0,
+            preview: 0,
+            colors: Vec::new(),
+            text: Some(String::try_from("Evening news").unwrap()),
+        };
+        broadcast.set_tally(1, true, false);
+        broadcast.set_tally(3, true, true);
+        broadcast.set_tally(64, false, true);
+        broadcast
+            .colors
+            .push(ColorOverride {
+                camera: 2,
+                color: Color::new(0, 0, 255),
+            })
+            .unwrap();
+
+        let mut buf = [0; MAX_LEN];
+        let packet = broadcast.encode(&mut buf).unwrap();
+        assert_eq!(&packet[..5], b"uTly\x01");
+        let decoded = TallyBroadcast::decode(packet).unwrap();
+        assert_eq!(decoded, broadcast);
+        assert_eq!(decoded.tally(1), Some((true, false)));
+        assert_eq!(decoded.tally(2), Some((false, false)));
+        assert_eq!(decoded.tally(3), Some((true, true)));
+        assert_eq!(decoded.tally(64), Some((false, true)));
+        assert_eq!(decoded.tally(0), None);
+        assert_eq!(decoded.tally(65), None);
+        assert_eq!(decoded.color(2), Some(Color::new(0, 0, 255)));
+        assert_eq!(decoded.color(1), None);
+    }
+
+    #[test]
+    fn test_bad_packets() {
+        assert_eq!(TallyBroadcast::decode(b"nope"), Err(Error::NotBroadcast));
+        assert_eq!(
+            TallyBroadcast::decode(b"uTly\x02\x00"),
+            Err(Error::Version(2))
+        );
+        assert_eq!(
+            TallyBroadcast::decode(b"uTly\x01\x07"),
+            Err(Error::Encoding)
+        );
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer(2, 1));
+        assert!(!is_newer(1, 1));
+        assert!(!is_newer(1, 2));
+        assert!(is_newer(0, u32::MAX));
+    }
+}
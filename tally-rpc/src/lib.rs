@@ -1,2 +1,3 @@
 #![cfg_attr(not(any(test, feature = "use-std")), no_std)]
+pub mod broadcast;
 pub mod rpc;
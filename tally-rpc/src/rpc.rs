@@ -5,13 +5,20 @@ use serde::{Deserialize, Serialize};
 
 endpoints! {
     list = ENDPOINTS_LIST;
-    | EndpointTy            | RequestTy     | ResponseTy        | Path         | Cfg                            |
-    | ----------            | ---------     | ----------        | ----         | ---                            |
-    | InfoEndpoint          | ()            | InfoResponse<'a>  | "info"       | cfg(not(feature = "use-std"))  |
-    | InfoEndpoint          | ()            | InfoResponse      | "info"       | cfg(feature = "use-std")       |
-    | SetConfigEndpoint     | Config        | ()                | "setconf"    |                                |
-    | StartColorTest        | ()            | bool              | "startcolor" |                                |
-    | StopColorTest         | ()            | bool              | "stopcolor"  |                                |
+    | EndpointTy            | RequestTy        | ResponseTy       | Path         | Cfg                           |
+    | ----------            | ---------        | ----------       | ----         | ---                           |
+    | InfoEndpoint          | ()               | InfoResponse<'a> | "info"       | cfg(not(feature = "use-std")) |
+    | InfoEndpoint          | ()               | InfoResponse     | "info"       | cfg(feature = "use-std")      |
+    | GetConfigEndpoint     | ()               | Config           | "getconf"    |                               |
+    | SetConfigEndpoint     | Config           | ()               | "setconf"    |                               |
+    | SetTallyEndpoint      | Tally            | ()               | "settally"   |                               |
+    | SetColorEndpoint      | ColorRequest     | ()               | "setcolor"   |                               |
+    | SetBrightnessEndpoint | u8               | ()               | "setbright"  |                               |
+    | IdentifyEndpoint      | ()               | ()               | "identify"   |                               |
+    | GetStatusEndpoint     | ()               | StatusResponse   | "status"     |                               |
+    | ApplyPresetEndpoint   | AppearancePreset | ()               | "preset"     |                               |
+    | StartColorTest        | ()               | bool             | "startcolor" |                               |
+    | StopColorTest         | ()               | bool             | "stopcolor"  |                               |
 }
 
 topics! {
@@ -34,7 +41,7 @@ pub enum WireErr {}
 
 // Requests
 
-#[derive(Serialize, Deserialize, Schema, Debug)]
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
 pub enum IfaceConfig {
     Static { ip: [u8; 4], mask: u8 },
     DHCP,
@@ -55,11 +62,647 @@ impl From<IfaceConfig> for embassy_net::Config {
     }
 }
 
-#[derive(Serialize, Deserialize, Schema, Debug)]
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct TslConfig {
+    /// UDP port to listen for TSL UMD messages on
+    pub port: u16,
+    /// Display address to follow (0-126)
+    pub address: u8,
+    /// Which of the TSL v4.0/v5.0 colour tallies to follow, when the sender provides them
+    pub colour_tally: TslColourTally,
+    /// UDP and TCP port to listen for TSL UMD v5.0 messages on
+    pub v5_port: u16,
+    /// TSL v5.0 screen to follow
+    pub screen: u16,
+    /// TSL v5.0 display index to follow
+    pub index: u16,
+}
+
+impl Default for TslConfig {
+    fn default() -> Self {
+        Self {
+            port: 40001,
+            address: 0,
+            colour_tally: TslColourTally::Left,
+            v5_port: 40002,
+            screen: 0,
+            index: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TslColourTally {
+    Left,
+    Text,
+    Right,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct AtemConfig {
+    /// Switcher IP address
+    pub ip: [u8; 4],
+    /// Input number to follow
+    pub input: u16,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct VmixConfig {
+    /// vMix machine IP address
+    pub ip: [u8; 4],
+    /// TCP API port, normally 8099
+    pub port: u16,
+    /// Input number to follow
+    pub input: u16,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct RolandConfig {
+    /// Switcher IP address
+    pub ip: [u8; 4],
+    /// HTTP port, normally 80
+    pub port: u16,
+    /// Smart Tally channel to follow, numbered from 1
+    pub channel: u8,
+    /// How often to ask for the tally
+    pub poll_ms: u16,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct TricasterConfig {
+    /// TriCaster IP address
+    pub ip: [u8; 4],
+    /// Automation protocol port, normally 5951
+    pub port: u16,
+    /// Input to follow, as TriCaster names it, e.g. `input3` or `ddr1`
+    pub input: heapless::String<16>,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct BroadcastConfig {
+    /// Multicast group to join, normally [`crate::broadcast::GROUP`]
+    pub group: [u8; 4],
+    /// UDP port, normally [`crate::broadcast::PORT`]
+    pub port: u16,
+    /// Camera number to follow, from 1
+    pub camera: u8,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct EmberConfig {
+    /// Provider IP address
+    pub ip: [u8; 4],
+    /// Provider port, normally 9000
+    pub port: u16,
+    /// Numeric path of the parameter to follow, e.g. `1.3.2`. A boolean parameter is program
+    /// when true; an integer one has bit 0 for program and bit 1 for preview.
+    pub path: heapless::String<48>,
+}
+
+/// What an OBS-following device lights up for.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub enum ObsTarget {
+    /// A scene being on program/preview
+    Scene(heapless::String<64>),
+    /// A source being visible in the program/preview scene
+    Source(heapless::String<64>),
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct ObsConfig {
+    /// OBS machine IP address
+    pub ip: [u8; 4],
+    /// obs-websocket port, normally 4455
+    pub port: u16,
+    /// obs-websocket password. Empty if authentication is disabled.
+    pub password: heapless::String<64>,
+    pub target: ObsTarget,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct MqttConfig {
+    /// Broker IP address
+    pub ip: [u8; 4],
+    /// Broker port, normally 1883
+    pub port: u16,
+    /// Username, empty to connect anonymously
+    pub username: heapless::String<32>,
+    /// Password, only sent along with a username
+    pub password: heapless::String<64>,
+    /// Prepended to all of this device's topics, e.g. `utally/cam1`
+    pub prefix: heapless::String<32>,
+    /// Publish Home Assistant discovery configs, so the device appears there automatically
+    pub home_assistant: bool,
+}
+
+/// Registration with a Tally Arbiter server as one of its listener clients.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct TallyArbiterConfig {
+    /// Server IP address
+    pub ip: [u8; 4],
+    /// Server port, normally 4455
+    pub port: u16,
+    /// Tally Arbiter device to follow. Updated when the server reassigns us.
+    pub device_id: heapless::String<40>,
+    /// Shown in the server's list of listener clients
+    pub name: heapless::String<32>,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct OscConfig {
+    /// UDP port to listen for OSC on
+    pub port: u16,
+}
+
+/// How DMX channels map onto the pixels.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmxMode {
+    /// Three channels (red, green, blue) for each pixel
+    PerPixel,
+    /// Three channels for the whole device
+    WholeDevice,
+}
+
+/// Lighting-desk control of the pixels over sACN (E1.31) or Art-Net. While data is arriving
+/// it takes over every pixel.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct DmxConfig {
+    /// sACN universe to listen to (1-63999)
+    pub universe: u16,
+    /// Art-Net port address (net, sub-net and universe) to listen to
+    pub artnet_universe: u16,
+    /// DMX address of the first channel (1-512)
+    pub start_address: u16,
+    pub mode: DmxMode,
+    /// Ignore sACN sources below this priority. Art-Net, which has no priorities, counts as
+    /// 100, the sACN default.
+    pub priority: u8,
+    /// Keep showing the last levels for this long after data stops, or forever if 0
+    pub hold_ms: u32,
+}
+
+/// Number of GPI (contact closure) inputs a device has.
+pub const GPI_PINS: usize = 2;
+
+/// What a GPI input does while it's active.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpiFunction {
+    Program,
+    Preview,
+    /// Flash the LEDs, so the operator knows someone wants to talk to them
+    Talkback,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct GpiConfig {
+    /// Active when the pin is low, e.g. a contact closing to ground. Otherwise active when high.
+    pub active_low: bool,
+    /// How long a new level must hold before it counts
+    pub debounce_ms: u16,
+    pub function: GpiFunction,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Animation {
+    Solid,
+    /// Smoothly fade in and out
+    Breathe,
+    /// On for the first half of each period, off for the second
+    Blink,
+    /// A third of the pixels lit, going round the strip once a period
+    Chase,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub struct Appearance {
+    pub color: Color,
+    pub animation: Animation,
+    /// Length of one animation cycle
+    pub period_ms: u16,
+    /// Scales the colour, within the device's maximum brightness
+    pub brightness: u8,
+}
+
+impl Appearance {
+    pub const fn new(color: Color, animation: Animation, period_ms: u16) -> Self {
+        Self {
+            color,
+            animation,
+            period_ms,
+            brightness: u8::MAX,
+        }
+    }
+}
+
+/// Everything that can report a tally.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TallySource {
+    /// The postcard-rpc and HTTP APIs
+    Api,
+    Tsl,
+    Atem,
+    Vmix,
+    Obs,
+    Roland,
+    Tricaster,
+    Ember,
+    Mqtt,
+    TallyArbiter,
+    Osc,
+    Gpi,
+    Broadcast,
+    /// The inputs picked by the [`TallyMapping`]
+    Mapping,
+}
+
+impl TallySource {
+    pub const ALL: [Self; 14] = [
+        Self::Api,
+        Self::Tsl,
+        Self::Atem,
+        Self::Vmix,
+        Self::Obs,
+        Self::Roland,
+        Self::Tricaster,
+        Self::Ember,
+        Self::Mqtt,
+        Self::TallyArbiter,
+        Self::Osc,
+        Self::Gpi,
+        Self::Broadcast,
+        Self::Mapping,
+    ];
+}
+
+/// How much each source counts when several report a tally at once. The highest priority wins,
+/// and among equal priorities the most recent report.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub struct SourcePriorities {
+    pub api: u8,
+    pub tsl: u8,
+    pub atem: u8,
+    pub vmix: u8,
+    pub obs: u8,
+    pub roland: u8,
+    pub tricaster: u8,
+    pub ember: u8,
+    pub mqtt: u8,
+    pub tally_arbiter: u8,
+    pub osc: u8,
+    pub gpi: u8,
+    pub broadcast: u8,
+    pub mapping: u8,
+}
+
+impl SourcePriorities {
+    pub fn get(&self, source: TallySource) -> u8 {
+        match source {
+            TallySource::Api => self.api,
+            TallySource::Tsl => self.tsl,
+            TallySource::Atem => self.atem,
+            TallySource::Vmix => self.vmix,
+            TallySource::Obs => self.obs,
+            TallySource::Roland => self.roland,
+            TallySource::Tricaster => self.tricaster,
+            TallySource::Ember => self.ember,
+            TallySource::Mqtt => self.mqtt,
+            TallySource::TallyArbiter => self.tally_arbiter,
+            TallySource::Osc => self.osc,
+            TallySource::Gpi => self.gpi,
+            TallySource::Broadcast => self.broadcast,
+            TallySource::Mapping => self.mapping,
+        }
+    }
+}
+
+impl Default for SourcePriorities {
+    /// All equal, so the latest report wins
+    fn default() -> Self {
+        Self {
+            api: 100,
+            tsl: 100,
+            atem: 100,
+            vmix: 100,
+            obs: 100,
+            roland: 100,
+            tricaster: 100,
+            ember: 100,
+            mqtt: 100,
+            tally_arbiter: 100,
+            osc: 100,
+            gpi: 100,
+            broadcast: 100,
+            mapping: 100,
+        }
+    }
+}
+
+/// Most selectors a [`TallyMapping`] can have.
+pub const MAX_SELECTORS: usize = 8;
+
+/// Lights the device for several switcher inputs, e.g. a camera routed to both an ISO input and
+/// a SuperSource box. Protocols the mapping mentions follow its selectors instead of their own
+/// configured input.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub struct TallyMapping {
+    /// How the selectors for each bus combine
+    pub combine: Combine,
+    pub selectors: Vec<Selector, MAX_SELECTORS>,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combine {
+    /// On a bus when any of its selectors match
+    Or,
+    /// On a bus when all of its selectors match
+    And,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    pub protocol: MappedProtocol,
+    /// Switcher input, TSL display address (v5.0 display index), or broadcast camera
+    pub input: u16,
+    /// The bus the input has to be on to match
+    pub bus: Bus,
+}
+
+/// Protocols that report the tally of every input, so can be used in a [`TallyMapping`].
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappedProtocol {
+    Tsl,
+    Atem,
+    Vmix,
+    Broadcast,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+    Program,
+    Preview,
+}
+
+/// Seconds each source can go without reporting before its signal counts as lost, or 0 to
+/// never time it out. Only useful for sources that repeat their tally; ones with a connection
+/// lose their signal as soon as it drops anyway.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub struct SourceTimeouts {
+    pub api: u16,
+    pub tsl: u16,
+    pub atem: u16,
+    pub vmix: u16,
+    pub obs: u16,
+    pub roland: u16,
+    pub tricaster: u16,
+    pub ember: u16,
+    pub mqtt: u16,
+    pub tally_arbiter: u16,
+    pub osc: u16,
+    pub gpi: u16,
+    pub broadcast: u16,
+    pub mapping: u16,
+}
+
+impl SourceTimeouts {
+    pub fn get(&self, source: TallySource) -> u16 {
+        match source {
+            TallySource::Api => self.api,
+            TallySource::Tsl => self.tsl,
+            TallySource::Atem => self.atem,
+            TallySource::Vmix => self.vmix,
+            TallySource::Obs => self.obs,
+            TallySource::Roland => self.roland,
+            TallySource::Tricaster => self.tricaster,
+            TallySource::Ember => self.ember,
+            TallySource::Mqtt => self.mqtt,
+            TallySource::TallyArbiter => self.tally_arbiter,
+            TallySource::Osc => self.osc,
+            TallySource::Gpi => self.gpi,
+            TallySource::Broadcast => self.broadcast,
+            TallySource::Mapping => self.mapping,
+        }
+    }
+}
+
+impl Default for SourceTimeouts {
+    /// Only broadcasts are sent regularly enough to notice them stopping
+    fn default() -> Self {
+        Self {
+            api: 0,
+            tsl: 0,
+            atem: 0,
+            vmix: 0,
+            obs: 0,
+            roland: 0,
+            tricaster: 0,
+            ember: 0,
+            mqtt: 0,
+            tally_arbiter: 0,
+            osc: 0,
+            gpi: 0,
+            broadcast: 5,
+            mapping: 0,
+        }
+    }
+}
+
+/// What to do when the sources of the tally go away, so a stale tally isn't left showing. The
+/// lost signal is shown as [`TallyAppearances::lost_signal`].
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Default)]
+pub struct FailsafeConfig {
+    pub timeouts: SourceTimeouts,
+}
+
+/// Indication of the switcher's recording/streaming status, separate from the tally. Shown on
+/// the zones with [`PixelZone::status`] set.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct StatusConfig {
+    /// Shown while recording, if set
+    pub recording: Option<Appearance>,
+    /// Shown while streaming (and not recording), if set
+    pub streaming: Option<Appearance>,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            recording: Some(Appearance::new(
+                Color::new(255, 0, 0),
+                Animation::Breathe,
+                4000,
+            )),
+            streaming: Some(Appearance::new(
+                Color::new(0, 0, 255),
+                Animation::Breathe,
+                4000,
+            )),
+        }
+    }
+}
+
+/// How each tally state is shown.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub struct TallyAppearances {
+    /// Shown until some source reports a tally. `None` cycles through the hues.
+    pub idle: Option<Appearance>,
+    pub off: Appearance,
+    pub preview: Appearance,
+    pub program: Appearance,
+    pub program_preview: Appearance,
+    /// Shown once every source has lost its signal
+    pub lost_signal: Appearance,
+}
+
+/// Built-in sets of [`TallyAppearances`].
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppearancePreset {
+    /// Red program, green preview
+    Standard,
+    /// Tells the states apart by pattern as well as by hue, in colours that stay distinct with
+    /// the common kinds of colour blindness
+    ColourBlind,
+    /// Standard, at a brightness that won't light up a dark set
+    Dim,
+}
+
+impl TallyAppearances {
+    pub fn preset(preset: AppearancePreset) -> Self {
+        let solid = |r, g, b| Appearance::new(Color::new(r, g, b), Animation::Solid, 1000);
+        let off = solid(0, 0, 0);
+        let lost_signal = Appearance::new(Color::new(255, 255, 255), Animation::Breathe, 1000);
+        match preset {
+            AppearancePreset::Standard => Self {
+                idle: None,
+                off,
+                preview: solid(0, 255, 0),
+                program: solid(255, 0, 0),
+                program_preview: solid(255, 128, 0),
+                lost_signal,
+            },
+            // From the Okabe-Ito palette
+            AppearancePreset::ColourBlind => Self {
+                idle: None,
+                off,
+                preview: Appearance::new(Color::new(0, 114, 178), Animation::Breathe, 2000),
+                program: solid(213, 94, 0),
+                program_preview: Appearance::new(Color::new(213, 94, 0), Animation::Blink, 1000),
+                lost_signal: Appearance::new(Color::new(255, 255, 255), Animation::Chase, 1500),
+            },
+            AppearancePreset::Dim => {
+                let dim = |appearance: Appearance| Appearance {
+                    brightness: 48,
+                    ..appearance
+                };
+                let standard = Self::preset(AppearancePreset::Standard);
+                Self {
+                    idle: standard.idle.map(dim),
+                    off: standard.off,
+                    preview: dim(standard.preview),
+                    program: dim(standard.program),
+                    program_preview: dim(standard.program_preview),
+                    lost_signal: dim(standard.lost_signal),
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, tally: Tally) -> &Appearance {
+        match tally {
+            Tally::Off => &self.off,
+            Tally::Preview => &self.preview,
+            Tally::Program => &self.program,
+            Tally::ProgramPreview => &self.program_preview,
+            Tally::LostSignal => &self.lost_signal,
+        }
+    }
+}
+
+impl Default for TallyAppearances {
+    fn default() -> Self {
+        Self::preset(AppearancePreset::Standard)
+    }
+}
+
+/// Most zones a device can have.
+pub const MAX_ZONES: usize = 4;
+
+/// A range of pixels with its own role, e.g. the ones facing the talent.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq)]
+pub struct PixelZone {
+    pub first_pixel: u8,
+    pub pixel_count: u8,
+    /// Show preview as well as program, e.g. to the operator but not the talent
+    pub preview: bool,
+    /// Show recording/streaming status over the tally
+    pub status: bool,
+    /// How the tally is shown here, or `None` for the device's [`Config::appearances`]
+    pub appearances: Option<TallyAppearances>,
+}
+
+impl PixelZone {
+    /// What this zone shows for `tally`.
+    pub fn shown(&self, tally: Tally) -> Tally {
+        match tally {
+            Tally::Preview if !self.preview => Tally::Off,
+            Tally::ProgramPreview if !self.preview => Tally::Program,
+            tally => tally,
+        }
+    }
+}
+
+/// The pixel layout of the VOC tallylight-v2: the front half faces the talent and only shows
+/// program, the back half faces the operator.
+pub fn default_zones() -> Vec<PixelZone, MAX_ZONES> {
+    let zone = |first_pixel, operator| PixelZone {
+        first_pixel,
+        pixel_count: 5,
+        preview: operator,
+        status: operator,
+        appearances: None,
+    };
+    Vec::from_iter([zone(0, false), zone(5, true)])
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
 pub struct Config {
     //name: &'a str,
     pub eth: IfaceConfig,
     pub eth_leds: bool,
+    pub tsl: TslConfig,
+    /// Connect to an ATEM switcher, if set
+    pub atem: Option<AtemConfig>,
+    /// Connect to vMix, if set
+    pub vmix: Option<VmixConfig>,
+    /// Connect to OBS Studio, if set
+    pub obs: Option<ObsConfig>,
+    /// Poll a Roland switcher's Smart Tally, if set
+    pub roland: Option<RolandConfig>,
+    /// Connect to a TriCaster, if set
+    pub tricaster: Option<TricasterConfig>,
+    /// Follow a parameter from an Ember+ provider, if set
+    pub ember: Option<EmberConfig>,
+    /// Connect to an MQTT broker, if set
+    pub mqtt: Option<MqttConfig>,
+    /// Listen to a Tally Arbiter server, if set
+    pub tally_arbiter: Option<TallyArbiterConfig>,
+    /// Listen for sACN and Art-Net, if set
+    pub dmx: Option<DmxConfig>,
+    /// Listen for OSC commands, if set
+    pub osc: Option<OscConfig>,
+    /// Listen for native µTally tally broadcasts, if set
+    pub broadcast: Option<BroadcastConfig>,
+    /// Settings for each GPI input, or `None` to ignore it
+    pub gpi: [Option<GpiConfig>; GPI_PINS],
+    pub status: StatusConfig,
+    pub appearances: TallyAppearances,
+    /// How the pixels are split up, to match the board. Pixels outside every zone stay dark.
+    pub zones: Vec<PixelZone, MAX_ZONES>,
+    pub priorities: SourcePriorities,
+    /// Pick the inputs to follow with rules, if set
+    pub mapping: Option<TallyMapping>,
+    pub failsafe: FailsafeConfig,
+    /// Maximum LED brightness. Brightness set at runtime, e.g. over OSC, scales within this.
+    pub brightness: u8,
 }
 
 impl Default for Config {
@@ -67,10 +710,42 @@ impl Default for Config {
         Self {
             eth: IfaceConfig::DHCP,
             eth_leds: true,
+            tsl: TslConfig::default(),
+            atem: None,
+            vmix: None,
+            obs: None,
+            roland: None,
+            tricaster: None,
+            ember: None,
+            mqtt: None,
+            tally_arbiter: None,
+            dmx: None,
+            osc: None,
+            broadcast: None,
+            gpi: [const { None }; GPI_PINS],
+            status: StatusConfig::default(),
+            appearances: TallyAppearances::default(),
+            zones: default_zones(),
+            priorities: SourcePriorities::default(),
+            mapping: None,
+            failsafe: FailsafeConfig::default(),
+            brightness: u8::MAX,
         }
     }
 }
 
+/// A tally state, as set through the APIs.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tally {
+    Off,
+    Preview,
+    Program,
+    ProgramPreview,
+    /// The source that was reporting the tally has gone away
+    LostSignal,
+}
+
 // Responses
 
 #[cfg(not(feature = "use-std"))]
@@ -89,13 +764,34 @@ pub struct InfoResponse {
     pub fw_version: (u8, u8, u8),
 }
 
+/// What the device is showing right now.
 #[derive(Serialize, Deserialize, Schema, Debug)]
+pub struct StatusResponse {
+    /// `None` until some protocol has set a tally
+    pub tally: Option<Tally>,
+    /// Colour shown instead of the tally, if any
+    pub color: Option<Color>,
+    pub brightness: u8,
+    /// Sources that have lost their signal since they last reported a tally
+    pub lost_sources: Vec<TallySource, { TallySource::ALL.len() }>,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
 }
 
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// A colour to show over the tally, or `None` to go back to it.
+pub type ColorRequest = Option<Color>;
+
 // Topics
 #[derive(Serialize, Deserialize, Schema, Debug)]
 pub struct ColorTest {
@@ -1,13 +1,45 @@
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, ToSocketAddrs};
 
 use postcard_rpc::host_client::HostClient;
-use std::net::ToSocketAddrs;
-use tally_rpc::rpc::{InfoEndpoint, InfoResponse, WireErr};
+use tally_rpc::rpc::{
+    GetConfigEndpoint, InfoEndpoint, InfoResponse, SetConfigEndpoint, TallyMapping, WireErr,
+};
+use tool::mapping;
+
+const USAGE: &str = "usage: tallycli [mapping [clear | or|and protocol:input:bus...]]";
+
+enum Command {
+    Info,
+    GetMapping,
+    SetMapping(Option<TallyMapping>),
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => Ok(Command::Info),
+        ["mapping"] => Ok(Command::GetMapping),
+        ["mapping", "clear"] => Ok(Command::SetMapping(None)),
+        ["mapping", combine, selectors @ ..] => {
+            mapping::parse_mapping(combine, selectors).map(|m| Command::SetMapping(Some(m)))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
     let addr = (Ipv4Addr::new(10, 11, 12, 13), 1234)
         .to_socket_addrs()
         .unwrap()
@@ -15,6 +47,24 @@ async fn main() {
         .unwrap();
     let cli = HostClient::<WireErr>::connect_tcp(addr).await;
     println!("connected");
-    let info: InfoResponse = cli.send_resp::<InfoEndpoint>(&()).await.unwrap();
-    println!("{:?}", info);
+    match command {
+        Command::Info => {
+            let info: InfoResponse = cli.send_resp::<InfoEndpoint>(&()).await.unwrap();
+            println!("{:?}", info);
+        }
+        Command::GetMapping => {
+            let config = cli.send_resp::<GetConfigEndpoint>(&()).await.unwrap();
+            match config.mapping {
+                Some(m) => println!("{}", mapping::format_mapping(&m)),
+                None => println!("no mapping"),
+            }
+        }
+        Command::SetMapping(new) => {
+            // The rest of the config stays as it is
+            let mut config = cli.send_resp::<GetConfigEndpoint>(&()).await.unwrap();
+            config.mapping = new;
+            cli.send_resp::<SetConfigEndpoint>(&config).await.unwrap();
+            println!("mapping set");
+        }
+    }
 }
@@ -1,7 +1,15 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
 use eframe::{
     egui::{self, WidgetText},
     epaint::Hsva,
 };
+use postcard_rpc::host_client::HostClient;
+use tally_rpc::rpc::{GetConfigEndpoint, SetConfigEndpoint, TallyMapping, WireErr};
+use tokio::runtime::Runtime;
+use tool::mapping;
+
+const RPC_PORT: u16 = 1234;
 
 fn main() -> eframe::Result {
     tracing_subscriber::fmt::init();
@@ -48,6 +56,78 @@ struct MyApp {
     ip: String,
     color_test: ColorTest,
     status: ConnectionStatus,
+    runtime: Option<Runtime>,
+    client: Option<HostClient<WireErr>>,
+    /// The mapping as `or|and protocol:input:bus...`, empty for none
+    mapping: String,
+    error: Option<String>,
+}
+
+impl MyApp {
+    fn connect(&mut self) -> Result<(), String> {
+        let ip: Ipv4Addr = self.ip.trim().parse().map_err(|_| "bad IP address")?;
+        let runtime = match self.runtime.take() {
+            Some(runtime) => runtime,
+            None => Runtime::new().map_err(|e| e.to_string())?,
+        };
+        let client = runtime.block_on(HostClient::<WireErr>::connect_tcp(SocketAddr::from((
+            ip, RPC_PORT,
+        ))));
+        self.runtime = Some(runtime);
+        self.client = Some(client);
+        self.status = ConnectionStatus::Connected;
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(client) = self.client.take() {
+            client.close();
+        }
+        self.status = ConnectionStatus::Disconnected;
+    }
+
+    fn get_mapping(&mut self) -> Result<(), String> {
+        let (Some(runtime), Some(client)) = (&self.runtime, &self.client) else {
+            return Err("not connected".into());
+        };
+        let config = runtime
+            .block_on(client.send_resp::<GetConfigEndpoint>(&()))
+            .map_err(|e| format!("{e:?}"))?;
+        self.mapping = config
+            .mapping
+            .as_ref()
+            .map(mapping::format_mapping)
+            .unwrap_or_default();
+        Ok(())
+    }
+
+    fn set_mapping(&mut self) -> Result<(), String> {
+        let new = parse_mapping(&self.mapping)?;
+        let (Some(runtime), Some(client)) = (&self.runtime, &self.client) else {
+            return Err("not connected".into());
+        };
+        // The rest of the config stays as it is
+        runtime.block_on(async {
+            let mut config = client
+                .send_resp::<GetConfigEndpoint>(&())
+                .await
+                .map_err(|e| format!("{e:?}"))?;
+            config.mapping = new;
+            client
+                .send_resp::<SetConfigEndpoint>(&config)
+                .await
+                .map_err(|e| format!("{e:?}"))
+        })
+    }
+}
+
+fn parse_mapping(text: &str) -> Result<Option<TallyMapping>, String> {
+    let mut words = text.split_whitespace();
+    let Some(combine) = words.next() else {
+        return Ok(None);
+    };
+    let selectors: Vec<_> = words.collect();
+    mapping::parse_mapping(combine, &selectors).map(Some)
 }
 
 impl eframe::App for MyApp {
@@ -65,17 +145,35 @@ impl eframe::App for MyApp {
                 match self.status {
                     ConnectionStatus::Connected => {
                         if ui.button("Disconnect").clicked() {
-                            self.status = ConnectionStatus::Disconnected;
+                            self.disconnect();
                         }
                     }
 
                     ConnectionStatus::Disconnected => {
                         if ui.button("Connect").clicked() {
-                            self.status = ConnectionStatus::Connected;
+                            self.error = self.connect().err();
                         }
                     }
                 }
             });
+            if self.status == ConnectionStatus::Connected {
+                ui.horizontal(|ui| {
+                    let label = ui.label("Mapping: ");
+                    ui.text_edit_singleline(&mut self.mapping)
+                        .labelled_by(label.id);
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Get mapping").clicked() {
+                        self.error = self.get_mapping().err();
+                    }
+                    if ui.button("Set mapping").clicked() {
+                        self.error = self.set_mapping().err();
+                    }
+                });
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
             match &mut self.color_test {
                 ColorTest::Stopped => {
                     if ui.button("Colortest").clicked() {
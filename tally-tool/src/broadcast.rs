@@ -0,0 +1,93 @@
+//! Sending native µTally tally broadcasts, to drive a whole fleet of devices with one packet.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+};
+
+use tally_rpc::{
+    broadcast::{self, ColorOverride, TallyBroadcast},
+    rpc::Color,
+};
+use tokio::net::UdpSocket;
+
+pub struct Sender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    state: TallyBroadcast,
+}
+
+impl Sender {
+    /// Send to the default multicast group and port.
+    pub async fn new() -> io::Result<Self> {
+        Self::with_target(broadcast::GROUP.into(), broadcast::PORT).await
+    }
+
+    pub async fn with_target(group: Ipv4Addr, port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        // Tally is for the local network
+        socket.set_multicast_ttl_v4(1)?;
+        Ok(Self {
+            socket,
+            target: (group, port).into(),
+            state: TallyBroadcast {
+                seq: 0,
+                program: 0,
+                preview: 0,
+                colors: Default::default(),
+                text: None,
+            },
+        })
+    }
+
+    /// Put `camera`, numbered from 1, on program and/or preview.
+    pub fn set_tally(&mut self, camera: u8, program: bool, preview: bool) {
+        self.state.set_tally(camera, program, preview);
+    }
+
+    /// Show `color` on `camera` instead of its tally, or go back to the tally with `None`.
+    pub fn set_color(&mut self, camera: u8, color: Option<Color>) -> io::Result<()> {
+        self.state.colors.retain(|c| c.camera != camera);
+        if let Some(color) = color {
+            self.state
+                .colors
+                .push(ColorOverride { camera, color })
+                .map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "too many colour overrides")
+                })?;
+        }
+        Ok(())
+    }
+
+    pub fn set_text(&mut self, text: Option<&str>) -> io::Result<()> {
+        self.state.text = text
+            .map(|t| t.try_into())
+            .transpose()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "text too long"))?;
+        Ok(())
+    }
+
+    /// Take every camera off air and clear the colour overrides.
+    pub fn clear(&mut self) {
+        self.state.program = 0;
+        self.state.preview = 0;
+        self.state.colors.clear();
+    }
+
+    pub fn state(&self) -> &TallyBroadcast {
+        &self.state
+    }
+
+    /// Send the current state. Call this after changes and also regularly, e.g. a few times a
+    /// second, so devices that missed a packet or just joined catch up.
+    pub async fn send(&mut self) -> io::Result<()> {
+        self.state.seq = self.state.seq.wrapping_add(1);
+        let mut buf = [0; broadcast::MAX_LEN];
+        let packet = self
+            .state
+            .encode(&mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:?}")))?;
+        self.socket.send_to(packet, self.target).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+//! Reading and writing [`TallyMapping`]s as text, one `protocol:input:bus` per selector, e.g.
+//! `atem:3:program`.
+
+use tally_rpc::rpc::{Bus, Combine, MAX_SELECTORS, MappedProtocol, Selector, TallyMapping};
+
+pub const PROTOCOLS: [MappedProtocol; 4] = [
+    MappedProtocol::Tsl,
+    MappedProtocol::Atem,
+    MappedProtocol::Vmix,
+    MappedProtocol::Broadcast,
+];
+
+pub fn protocol_name(protocol: MappedProtocol) -> &'static str {
+    match protocol {
+        MappedProtocol::Tsl => "tsl",
+        MappedProtocol::Atem => "atem",
+        MappedProtocol::Vmix => "vmix",
+        MappedProtocol::Broadcast => "broadcast",
+    }
+}
+
+pub fn bus_name(bus: Bus) -> &'static str {
+    match bus {
+        Bus::Program => "program",
+        Bus::Preview => "preview",
+    }
+}
+
+pub fn combine_name(combine: Combine) -> &'static str {
+    match combine {
+        Combine::Or => "or",
+        Combine::And => "and",
+    }
+}
+
+pub fn format_selector(selector: &Selector) -> String {
+    format!(
+        "{}:{}:{}",
+        protocol_name(selector.protocol),
+        selector.input,
+        bus_name(selector.bus)
+    )
+}
+
+pub fn format_mapping(mapping: &TallyMapping) -> String {
+    let mut text = combine_name(mapping.combine).to_string();
+    for selector in &mapping.selectors {
+        text.push(' ');
+        text.push_str(&format_selector(selector));
+    }
+    text
+}
+
+pub fn parse_selector(text: &str) -> Result<Selector, String> {
+    let mut parts = text.split(':');
+    let (Some(protocol), Some(input), Some(bus), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(format!("{text}: expected protocol:input:bus"));
+    };
+    let protocol = PROTOCOLS
+        .into_iter()
+        .find(|p| protocol_name(*p) == protocol)
+        .ok_or_else(|| format!("{text}: unknown protocol {protocol}"))?;
+    let input = input
+        .parse()
+        .map_err(|_| format!("{text}: bad input {input}"))?;
+    let bus = [Bus::Program, Bus::Preview]
+        .into_iter()
+        .find(|b| bus_name(*b) == bus)
+        .ok_or_else(|| format!("{text}: unknown bus {bus}"))?;
+    Ok(Selector {
+        protocol,
+        input,
+        bus,
+    })
+}
+
+/// Parse `or` or `and` followed by the selectors.
+pub fn parse_mapping(combine: &str, selectors: &[impl AsRef<str>]) -> Result<TallyMapping, String> {
+    let combine = [Combine::Or, Combine::And]
+        .into_iter()
+        .find(|c| combine_name(*c) == combine)
+        .ok_or_else(|| format!("expected or/and, not {combine}"))?;
+    let mut mapping = TallyMapping {
+        combine,
+        selectors: Default::default(),
+    };
+    for selector in selectors {
+        mapping
+            .selectors
+            .push(parse_selector(selector.as_ref())?)
+            .map_err(|_| format!("at most {MAX_SELECTORS} selectors"))?;
+    }
+    Ok(mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let selectors = ["atem:3:program", "tsl:12:preview", "broadcast:1:program"];
+        let mapping = parse_mapping("and", &selectors).unwrap();
+        assert_eq!(mapping.combine, Combine::And);
+        assert_eq!(
+            mapping.selectors[1],
+            Selector {
+                protocol: MappedProtocol::Tsl,
+                input: 12,
+                bus: Bus::Preview,
+            }
+        );
+        let text = format_mapping(&mapping);
+        assert_eq!(
+            text,
+            "and atem:3:program tsl:12:preview broadcast:1:program"
+        );
+        let mut words = text.split(' ');
+        let combine = words.next().unwrap();
+        let selectors: Vec<_> = words.collect();
+        assert_eq!(parse_mapping(combine, &selectors), Ok(mapping));
+    }
+
+    #[test]
+    fn test_bad_names() {
+        assert!(parse_selector("obs:3:program").is_err());
+        assert!(parse_selector("atem:3:aux").is_err());
+        assert!(parse_selector("atem:three:program").is_err());
+        assert!(parse_mapping("xor", &["atem:3:program"]).is_err());
+    }
+
+    #[test]
+    fn test_fields() {
+        assert!(parse_selector("atem:3").is_err());
+        assert!(parse_selector("atem:3:program:1").is_err());
+        assert!(parse_selector("").is_err());
+    }
+
+    #[test]
+    fn test_max_selectors() {
+        let selectors: Vec<_> = (0..=MAX_SELECTORS)
+            .map(|i| format!("vmix:{i}:program"))
+            .collect();
+        assert!(parse_mapping("or", &selectors[..MAX_SELECTORS]).is_ok());
+        assert!(parse_mapping("or", &selectors).is_err());
+    }
+}